@@ -0,0 +1,127 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect::{Attempt, Policy},
+};
+use url::{Host, Url};
+
+/// Whether `ip` is somewhere a crawl of the public web has no business going:
+/// loopback, RFC 1918, link-local, unique-local, or unspecified.
+pub fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_private_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_private_v4(ip),
+            None => is_private_v6(ip),
+        },
+    }
+}
+
+fn is_private_v4(ip: Ipv4Addr) -> bool {
+    ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified()
+}
+
+fn is_private_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+
+    ip.is_loopback()
+        || ip.is_unspecified()
+        // fe80::/10
+        || first & 0xffc0 == 0xfe80
+        // fc00::/7
+        || first & 0xfe00 == 0xfc00
+}
+
+/// The private address a url points straight at, if it's written as an ip.
+/// Hostnames are left to [`PublicResolver`].
+pub fn private_literal(url: &Url) -> Option<IpAddr> {
+    let ip = match url.host()? {
+        Host::Ipv4(ip) => IpAddr::V4(ip),
+        Host::Ipv6(ip) => IpAddr::V6(ip),
+        Host::Domain(_) => return None,
+    };
+
+    is_private(ip).then_some(ip)
+}
+
+/// Why a request never went out.
+#[derive(Debug)]
+pub struct BlockedAddress {
+    pub host: String,
+    pub ip: IpAddr,
+}
+
+impl fmt::Display for BlockedAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is at private address {}, pass --allow-private to fetch it anyway", self.host, self.ip)
+    }
+}
+
+impl Error for BlockedAddress {}
+
+/// Whether `err` (or anything it was caused by) is a [`BlockedAddress`].
+pub fn is_blocked(err: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(err);
+
+    while let Some(err) = source {
+        if err.is::<BlockedAddress>() {
+            return true;
+        }
+        source = err.source();
+    }
+
+    false
+}
+
+/// Resolves hostnames like normal, but refuses any that point at a private address,
+/// so a public-looking name can't be used to reach internal hosts.
+pub struct PublicResolver {
+    /// Hosts that are allowed to be private anyway, i.e. the proxies we were told to use.
+    pub trusted_hosts: HashSet<String>,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_owned();
+        let trusted = self.trusted_hosts.contains(&host);
+
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+
+            // refusing when any of them is private means round-robin dns can't sneak one through.
+            if let Some(addr) = addrs.iter().find(|addr| !trusted && is_private(addr.ip())) {
+                return Err(Box::new(BlockedAddress { host, ip: addr.ip() }) as _);
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Follows up to `max` redirects, only to urls `in_scope` accepts, and never to a literal private ip unless
+/// `allow_private`. A redirect that isn't followed comes back as the response, so its Location is still there to use.
+pub fn redirect_policy(max: usize, allow_private: bool, in_scope: impl Fn(&Url) -> bool + Send + Sync + 'static) -> Policy {
+    Policy::custom(move |attempt: Attempt| {
+        // previous() has the original url in it too, so it's one longer than the redirects so far.
+        if attempt.previous().len() > max {
+            return attempt.stop();
+        }
+
+        if let Some(ip) = private_literal(attempt.url()).filter(|_| !allow_private) {
+            let host = attempt.url().host_str().unwrap_or_default().to_owned();
+            return attempt.error(BlockedAddress { host, ip });
+        }
+
+        if !in_scope(attempt.url()) {
+            return attempt.stop();
+        }
+
+        attempt.follow()
+    })
+}
@@ -0,0 +1,34 @@
+//! Keeping the bodies of fetched pages, for `--store-content`.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use tokio::fs;
+
+/// Where the bodies of parsed pages are kept. Bodies are stored as the decoded text their `content_hash` is
+/// taken from, and identical ones are only stored once.
+#[derive(Clone)]
+pub enum ContentStorage {
+    /// In the db, with a record for each body in this table, its id being the hash.
+    Inline(String),
+    /// In this directory, as a file for each body named after its hash.
+    Dir(PathBuf),
+}
+
+/// Writes `content` to `dir/<hash>` unless it's already there, returning the file's path.
+pub async fn write_to_dir(dir: &Path, hash: &str, content: &str) -> io::Result<PathBuf> {
+    let path = dir.join(hash);
+    if fs::try_exists(&path).await? {
+        return Ok(path);
+    }
+
+    // written out under another name first, so nothing ever finds half a body. two pages with the same body
+    // at once each get their own.
+    let partial = dir.join(format!("{hash}.{:016x}.partial", fastrand::u64(..)));
+    fs::write(&partial, content).await?;
+    fs::rename(&partial, &path).await?;
+
+    Ok(path)
+}
@@ -0,0 +1,189 @@
+use std::{env, ffi::OsString, fs, path::{Path, PathBuf}};
+
+use anyhow::Context;
+use clap::{parser::ValueSource, Arg, ArgAction, ArgMatches, CommandFactory, FromArgMatches};
+use toml::{Table, Value};
+use url::Url;
+
+use crate::Cli;
+
+/// Args that can't be set from a config file.
+const CLI_ONLY: [&str; 3] = ["config", "help", "version"];
+
+/// Args whose values never go in the settings a run is recorded with.
+const SECRETS: [&str; 3] = ["db_pass", "basic_auth", "bearer_token"];
+
+/// Parses the command line, filling in whatever it leaves out from the `--config` file if there is one.
+/// Anything on the command line (or in its env var) wins over the file, which wins over the defaults.
+///
+/// Also returns the settings it ended up with, for the crawl's run record. See [`settings`].
+pub fn parse() -> anyhow::Result<(Cli, serde_json::Value)> {
+    let matches = Cli::command().get_matches_from(with_config_file()?);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+    Ok((cli, settings(&matches)))
+}
+
+/// The command line, with whatever's in the `--config` file added to the end as more flags.
+fn with_config_file() -> anyhow::Result<Vec<OsString>> {
+    let argv: Vec<OsString> = env::args_os().collect();
+    let command = Cli::command();
+
+    // the file might be what fills in the required args, so their absence can't stop us from finding it.
+    let matches = match command.clone().ignore_errors(true).try_get_matches_from(&argv) {
+        Ok(matches) if matches.subcommand().is_none() => matches,
+        _ => return Ok(argv),
+    };
+    let Some(path) = matches.get_one::<PathBuf>("config") else {
+        return Ok(argv);
+    };
+
+    let mut args = argv.clone();
+    for (key, value) in read(path)? {
+        let id = key.replace('-', "_");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id.as_str() && !CLI_ONLY.contains(&id.as_str()))
+            .with_context(|| format!("Unknown setting {key:?} in {path:?}"))?;
+
+        if matches!(matches.value_source(&id), Some(ValueSource::CommandLine | ValueSource::EnvVariable)) {
+            continue;
+        }
+
+        // every setting has a long flag, so the file can just be turned into more of them.
+        let long = arg.get_long().unwrap();
+        let scalar = |value: Value| match value {
+            Value::String(value) => Ok(value),
+            Value::Integer(value) => Ok(value.to_string()),
+            Value::Float(value) => Ok(value.to_string()),
+            Value::Boolean(value) => Ok(value.to_string()),
+            _ => anyhow::bail!("Setting {key:?} in {path:?} should be a string, number, or boolean"),
+        };
+
+        match value {
+            Value::Boolean(true) if matches!(arg.get_action(), ArgAction::SetTrue) => args.push(format!("--{long}").into()),
+            Value::Boolean(false) if matches!(arg.get_action(), ArgAction::SetTrue) => {}
+            Value::Array(values) => {
+                for value in values {
+                    args.push(format!("--{long}={}", scalar(value)?).into());
+                }
+            }
+            value => args.push(format!("--{long}={}", scalar(value)?).into()),
+        }
+    }
+
+    Ok(args)
+}
+
+/// Every setting that has a value, defaults included, keyed by the same names a config file uses.
+/// Passwords and tokens are left out, along with credentials in urls and auth headers.
+fn settings(matches: &ArgMatches) -> serde_json::Value {
+    let mut settings = serde_json::Map::new();
+
+    for arg in Cli::command().get_arguments() {
+        let id = arg.get_id().as_str();
+        if id == "help" || id == "version" {
+            continue;
+        }
+        let Some(values) = matches.get_raw(id) else {
+            continue;
+        };
+
+        if SECRETS.contains(&id) {
+            settings.insert(id.to_owned(), "<redacted>".into());
+            continue;
+        }
+
+        let mut values = values.map(|value| setting_value(id, &value.to_string_lossy()));
+        let many = matches!(arg.get_action(), ArgAction::Append) || arg.get_value_delimiter().is_some();
+        let value = if many {
+            values.collect()
+        } else {
+            values.next().unwrap_or_default()
+        };
+        settings.insert(id.to_owned(), value);
+    }
+
+    settings.into()
+}
+
+fn setting_value(id: &str, value: &str) -> serde_json::Value {
+    if let Ok(number) = value.parse::<i64>() {
+        return number.into();
+    }
+    if let Ok(flag) = value.parse::<bool>() {
+        return flag.into();
+    }
+
+    // proxies and the like can have a user and password in them.
+    if let Ok(mut url) = Url::parse(value) {
+        if !url.username().is_empty() || url.password().is_some() {
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            return url.to_string().into();
+        }
+    }
+
+    // so can --header, as Authorization, Cookie or some kind of API key.
+    if let Some((name, _)) = value.split_once(':').filter(|_| id == "headers") {
+        let lowercase = name.trim().to_ascii_lowercase();
+        let secret = ["authorization", "proxy-authorization", "cookie"].contains(&lowercase.as_str())
+            || ["token", "key", "secret"].iter().any(|word| lowercase.contains(word));
+        if secret {
+            return format!("{}: <redacted>", name.trim()).into();
+        }
+    }
+
+    value.into()
+}
+
+fn read(path: &Path) -> anyhow::Result<Table> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {path:?}"))?;
+
+    content
+        .parse()
+        .with_context(|| format!("Failed to parse config file {path:?}"))
+}
+
+/// A config file listing every setting, commented out and set to its default, for `config init`.
+pub fn example() -> String {
+    let mut example = String::from(
+        "# Settings for findconn, used with `findconn --config <path>`.\n\
+         # Keys are the long flag names, and anything given on the command line wins over what's here.\n",
+    );
+
+    for arg in Cli::command().get_arguments() {
+        let id = arg.get_id().as_str();
+        if CLI_ONLY.contains(&id) || arg.is_hide_set() {
+            continue;
+        }
+
+        example.push('\n');
+        if let Some(help) = arg.get_help() {
+            example.push_str(&format!("# {help}\n"));
+        }
+        example.push_str(&format!("# {id} = {}\n", example_value(arg)));
+    }
+
+    example
+}
+
+fn example_value(arg: &Arg) -> String {
+    match arg.get_action() {
+        ArgAction::SetTrue => return "false".to_owned(),
+        ArgAction::Append => return "[]".to_owned(),
+        _ => {}
+    }
+
+    let Some(default) = arg.get_default_values().first() else {
+        let name = arg.get_value_names().and_then(|names| names.first()).map_or(arg.get_id().as_str(), |name| name.as_str());
+        return Value::String(format!("<{name}>")).to_string();
+    };
+
+    let default = default.to_string_lossy();
+    match default.parse::<i64>() {
+        Ok(number) => number.to_string(),
+        Err(_) => Value::String(default.into_owned()).to_string(),
+    }
+}
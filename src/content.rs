@@ -0,0 +1,191 @@
+use std::sync::LazyLock;
+
+use encoding_rs::{Encoding, UTF_8};
+use mime::Mime;
+use regex::bytes::Regex;
+use sha2::{Digest, Sha256};
+
+/// How far into an HTML document a `<meta charset>` is looked for, which is where browsers look too.
+const META_CHARSET_BYTES: usize = 1024;
+
+/// Matches both `<meta charset="...">` and `<meta http-equiv="Content-Type" content="text/html; charset=...">`.
+static META_CHARSET: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)<meta[^>]*?charset\s*=\s*["']?\s*([a-z0-9_:.\-]+)"#).unwrap()
+});
+
+const APPLICATION_TEXT_TYPES: [&str; 5] = [
+    "application/json",
+    "application/xml",
+    "application/javascript",
+    "application/ld+json",
+    "application/xhtml+xml",
+];
+
+/// Whether a Content-Type header value is something we can pull links out of.
+///
+/// `extra` holds user supplied types, either exact (`application/x-ndjson`) or a wildcard (`font/*`).
+pub fn is_text_content_type(content_type: &str, extra: &[String]) -> bool {
+    // parsing strips parameters like `; charset=utf-8` and lowercases everything.
+    let Ok(mime) = content_type.trim().parse::<Mime>() else {
+        return false;
+    };
+
+    let essence = mime.essence_str();
+
+    mime.type_() == mime::TEXT
+        || APPLICATION_TEXT_TYPES.contains(&essence)
+        || mime.suffix().is_some_and(|suffix| suffix == mime::JSON || suffix == mime::XML)
+        || extra.iter().any(|pattern| match pattern.strip_suffix("/*") {
+            Some(type_) => mime.type_() == type_,
+            None => pattern == essence,
+        })
+}
+
+/// Content types that say nothing about what the body is, so it's worth sniffing before giving up on it.
+/// A missing Content-Type counts too.
+pub fn is_generic_content_type(content_type: &str) -> bool {
+    let content_type = content_type.trim();
+
+    content_type.is_empty()
+        || content_type
+            .parse::<Mime>()
+            .is_ok_and(|mime| matches!(mime.essence_str(), "application/octet-stream" | "binary/octet-stream" | "application/unknown"))
+}
+
+/// What the start of a body looks like.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sniffed {
+    Html,
+    Json,
+    Xml,
+    Text,
+    Binary,
+}
+
+impl Sniffed {
+    /// The content type to parse it as, for bodies the header was no help with.
+    pub fn content_type(self) -> Option<&'static str> {
+        match self {
+            Self::Html => Some("text/html"),
+            Self::Json => Some("application/json"),
+            Self::Xml => Some("application/xml"),
+            Self::Text => Some("text/plain"),
+            Self::Binary => None,
+        }
+    }
+}
+
+/// Guesses what a body is from its first few KB.
+pub fn sniff(prefix: &[u8]) -> Sniffed {
+    let prefix = prefix.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(prefix);
+
+    // text basically never has NUL bytes, while nearly every binary format does early on.
+    let control = prefix
+        .iter()
+        .filter(|byte| byte.is_ascii_control() && !byte.is_ascii_whitespace())
+        .count();
+    if prefix.contains(&0) || control * 10 > prefix.len() {
+        return Sniffed::Binary;
+    }
+
+    let text = match std::str::from_utf8(prefix) {
+        Ok(text) => text,
+        // the prefix can cut a character in half, which is fine.
+        Err(err) if err.error_len().is_none() => std::str::from_utf8(&prefix[..err.valid_up_to()]).unwrap(),
+        // probably just another charset, the control characters would've given away anything binary.
+        Err(_) => return Sniffed::Text,
+    };
+
+    let start = text.trim_start();
+    let start = start.get(..16).unwrap_or(start).to_ascii_lowercase();
+
+    if ["<!doctype html", "<html", "<head", "<body", "<!--"].iter().any(|tag| start.starts_with(tag)) {
+        Sniffed::Html
+    } else if start.starts_with("<?xml") || start.starts_with("<rss") || start.starts_with("<feed") {
+        Sniffed::Xml
+    } else if start.starts_with('{') || start.starts_with('[') {
+        Sniffed::Json
+    } else {
+        Sniffed::Text
+    }
+}
+
+pub fn is_html_content_type(content_type: &str) -> bool {
+    content_type
+        .trim()
+        .parse::<Mime>()
+        .is_ok_and(|mime| mime.essence_str() == "text/html" || mime.essence_str() == "application/xhtml+xml")
+}
+
+/// XML that might be a feed, including `application/rss+xml` and `application/atom+xml`.
+pub fn is_xml_content_type(content_type: &str) -> bool {
+    content_type
+        .trim()
+        .parse::<Mime>()
+        .is_ok_and(|mime| {
+            matches!(mime.essence_str(), "application/xml" | "text/xml")
+                || (mime.suffix() == Some(mime::XML) && mime.essence_str() != "application/xhtml+xml")
+        })
+}
+
+/// Decodes a body, returning the text and the encoding that was used.
+///
+/// The charset comes from its Content-Type, then (for HTML) a `<meta>` declaration near the start, and otherwise
+/// it's UTF-8. A byte order mark beats all of them, and anything that doesn't decode becomes U+FFFD.
+pub fn decode_body(body: &[u8], content_type: &str) -> (String, &'static Encoding) {
+    let encoding = content_type
+        .trim()
+        .parse::<Mime>()
+        .ok()
+        .and_then(|mime| mime.get_param(mime::CHARSET).and_then(|charset| Encoding::for_label(charset.as_str().as_bytes())))
+        .or_else(|| is_html_content_type(content_type).then(|| meta_charset(body)).flatten())
+        .unwrap_or(UTF_8);
+
+    let (text, encoding, _) = encoding.decode(body);
+    (text.into_owned(), encoding)
+}
+
+/// The SHA-256 of a decoded body, in hex, so pages with the same text in different encodings still match.
+/// It's byte for byte, so even a difference in whitespace makes it a different page.
+pub fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// The encoding declared by a `<meta>` tag in the first [`META_CHARSET_BYTES`] of an HTML document.
+fn meta_charset(body: &[u8]) -> Option<&'static Encoding> {
+    let head = &body[..body.len().min(META_CHARSET_BYTES)];
+    let label = META_CHARSET.captures(head)?.get(1)?;
+
+    Encoding::for_label(label.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_content_types() {
+        let extra = ["application/x-ndjson".to_owned(), "font/*".to_owned()];
+        let cases = [
+            ("text/html", true),
+            ("text/html; charset=ISO-8859-1", true),
+            ("APPLICATION/JSON", true),
+            ("application/json; charset=utf-8", true),
+            ("application/xhtml+xml", true),
+            ("application/ld+json", true),
+            ("application/vnd.api+json", true),
+            ("application/atom+xml", true),
+            ("application/x-ndjson", true),
+            ("font/woff2", true),
+            ("image/png", false),
+            ("application/pdf", false),
+            ("application/octet-stream", false),
+            ("not a type", false),
+            ("", false),
+        ];
+
+        for (content_type, expected) in cases {
+            assert_eq!(is_text_content_type(content_type, &extra), expected, "{content_type:?}");
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use cookie_store::{CookieDomain, CookieExpiration, CookieStore};
+use tracing::debug;
+use url::Url;
+
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Loads a Netscape-format `cookies.txt` file, as exported by browsers and curl.
+pub fn load_netscape(path: &Path) -> anyhow::Result<CookieStore> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read cookies file {path:?}"))?;
+
+    let mut store = CookieStore::default();
+    let now = unix_now();
+
+    for (i, line) in content.lines().enumerate() {
+        let (line, http_only) = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [domain, include_subdomains, cookie_path, secure, expires, name, value] = fields[..] else {
+            anyhow::bail!("{path:?} line {}: expected 7 tab-separated fields", i + 1);
+        };
+
+        let secure = secure.eq_ignore_ascii_case("TRUE");
+        let expires: i64 = expires
+            .parse()
+            .with_context(|| format!("{path:?} line {}: invalid expiry {expires:?}", i + 1))?;
+
+        // rebuilding a Set-Cookie string lets the store apply its normal domain/path rules.
+        let host = domain.trim_start_matches('.');
+        let mut set_cookie = format!("{name}={value}; Path={cookie_path}");
+
+        if include_subdomains.eq_ignore_ascii_case("TRUE") {
+            set_cookie.push_str(&format!("; Domain={host}"));
+        }
+
+        if expires != 0 {
+            if expires <= now {
+                continue;
+            }
+
+            set_cookie.push_str(&format!("; Max-Age={}", expires - now));
+        }
+
+        if secure {
+            set_cookie.push_str("; Secure");
+        }
+
+        if http_only {
+            set_cookie.push_str("; HttpOnly");
+        }
+
+        let scheme = if secure { "https" } else { "http" };
+        let url = Url::parse(&format!("{scheme}://{host}{cookie_path}"))
+            .with_context(|| format!("{path:?} line {}: invalid domain {domain:?}", i + 1))?;
+
+        if let Err(err) = store.parse(&set_cookie, &url) {
+            debug!("Skipping cookie {name:?} for {domain:?}: {err}");
+        }
+    }
+
+    Ok(store)
+}
+
+/// Writes the unexpired cookies in `store` to a Netscape-format `cookies.txt` file.
+pub fn save_netscape(store: &CookieStore, path: &Path) -> anyhow::Result<()> {
+    let mut out = String::from("# Netscape HTTP Cookie File\n");
+
+    for cookie in store.iter_unexpired() {
+        let (domain, include_subdomains) = match &cookie.domain {
+            CookieDomain::HostOnly(host) => (host.clone(), "FALSE"),
+            CookieDomain::Suffix(suffix) => (format!(".{suffix}"), "TRUE"),
+            CookieDomain::NotPresent | CookieDomain::Empty => continue,
+        };
+
+        let expires = match cookie.expires {
+            CookieExpiration::AtUtc(at) => at.unix_timestamp(),
+            CookieExpiration::SessionEnd => 0,
+        };
+
+        let prefix = if cookie.http_only().unwrap_or(false) { HTTP_ONLY_PREFIX } else { "" };
+        let secure = if cookie.secure().unwrap_or(false) { "TRUE" } else { "FALSE" };
+
+        out.push_str(&format!(
+            "{prefix}{domain}\t{include_subdomains}\t{}\t{secure}\t{expires}\t{}\t{}\n",
+            &*cookie.path,
+            cookie.name(),
+            cookie.value(),
+        ));
+    }
+
+    fs::write(path, out)
+        .with_context(|| format!("Failed to write cookies file {path:?}"))
+}
@@ -0,0 +1,1493 @@
+//! The crawler itself, configured with a [`CrawlerBuilder`].
+
+mod discover;
+
+use std::{collections::{BTreeMap, HashMap, HashSet}, sync::{atomic::{AtomicU64, AtomicUsize, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use regex::Regex;
+use reqwest::{header::HeaderMap, Certificate, Client, Proxy};
+use reqwest_cookie_store::CookieStoreMutex;
+use surrealdb::sql::Thing;
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::{
+    address::{self, PublicResolver},
+    archive::ContentStorage,
+    db,
+    email::EmailFinder,
+    emit::{Edge, EmitFormat, Emitter},
+    filter::{is_web_scheme, DomainFilter, DomainPattern, ExtensionFilter, FilterDecision, Scope, SiteScope, UrlFilter},
+    frontier::{Frontier, FrontierItem, Strategy},
+    model::{FetchInfo, LinkData, PageLink, Relation, SiteURLNode},
+    normalize::{UrlNormalizer, TRACKING_PARAMS},
+    politeness::{HostHealth, HostLimits, Politeness, RateLimit},
+    proxies::{self, ProxyPool},
+    robots::RobotsCache,
+    store::GraphStore,
+    summary::{Status, Summary},
+    visited::{Claimed, Visited},
+    webhook::{EventKind, Webhook},
+};
+#[cfg(feature = "metrics")]
+use crate::metrics::{Histogram, Metrics};
+
+use discover::{crawl_worker, robots_agent, screen, seed_from_sitemap};
+
+pub const DEFAULT_USER_AGENT: &str = concat!(
+    "findconn/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/HyperCodec/site-connection-finder)",
+);
+
+/// Something that happened during a crawl, as sent to [`CrawlerBuilder::events`].
+#[derive(Clone)]
+pub enum CrawlEvent {
+    /// A url was seen for the first time.
+    NodeCreated { id: Thing, node: Box<SiteURLNode> },
+    /// A page was found linking to (or redirecting to, or declaring as canonical) another, `url`.
+    RelationCreated {
+        relate_table: String,
+        id: Thing,
+        from: Thing,
+        to: Thing,
+        url: String,
+        link: LinkData,
+    },
+    /// A page couldn't be fetched or read.
+    FetchFailed {
+        id: Thing,
+        url: String,
+        error: String,
+        /// dns, connect, timeout, tls, http, or body.
+        error_kind: &'static str,
+    },
+    /// The crawl is over. This is always the last event.
+    CrawlFinished(Summary),
+}
+
+/// Sets up a [`Crawler`]. Everything has the same default as the flag it's named after.
+pub struct CrawlerBuilder {
+    seeds: Vec<String>,
+    sitemap: Option<String>,
+    resume: bool,
+    refresh: Option<Duration>,
+    prune_stale_edges: bool,
+    define_schema: bool,
+    table: String,
+    relate_table: String,
+    redirect_table: String,
+    canonical_table: String,
+    frontier_table: String,
+    email_table: String,
+    email_relate_table: String,
+    strategy: Strategy,
+    concurrency: usize,
+    per_host_concurrency: usize,
+    max_depth: Option<u32>,
+    max_pages: Option<usize>,
+    max_pages_per_domain: Option<usize>,
+    by_registrable_domain: bool,
+    same_domain: bool,
+    scope: Scope,
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    record_excluded: bool,
+    allow_domains: Vec<DomainPattern>,
+    deny_domains: Vec<DomainPattern>,
+    record_external: bool,
+    record_other_schemes: bool,
+    extension_filter: bool,
+    skip_extensions: Vec<String>,
+    allow_extensions: Vec<String>,
+    normalize: bool,
+    fold_trailing_slashes: bool,
+    keep_fragments: bool,
+    strip_params: Option<Vec<String>>,
+    strip_all_params: bool,
+    robots: bool,
+    max_crawl_delay: Duration,
+    meta_robots: bool,
+    max_retry_after: Duration,
+    host_backoff_after: u32,
+    bench_host_after: u32,
+    delay: Duration,
+    rate: Option<f64>,
+    retries: u32,
+    user_agent: String,
+    headers: HeaderMap,
+    timeout: Duration,
+    connect_timeout: Duration,
+    max_redirects: usize,
+    ca_certs: Vec<Certificate>,
+    insecure: bool,
+    allow_private: bool,
+    proxies: Vec<String>,
+    cookies: Option<Arc<CookieStoreMutex>>,
+    auth: Option<Auth>,
+    head_precheck: Option<u64>,
+    max_body_bytes: u64,
+    content_types: Vec<String>,
+    parse_errors: bool,
+    skip_nofollow: bool,
+    record_nofollow: bool,
+    follow_canonical: bool,
+    skip_duplicate_content: bool,
+    store_content: Option<ContentStorage>,
+    extract_text: Option<usize>,
+    collect_emails: bool,
+    webhook: Option<(Url, Vec<EventKind>)>,
+    emit: Option<EmitFormat>,
+    events: Option<mpsc::Sender<CrawlEvent>>,
+    count_host_pages: bool,
+    run: Option<Thing>,
+}
+
+impl Default for CrawlerBuilder {
+    fn default() -> Self {
+        Self {
+            seeds: Vec::new(),
+            sitemap: None,
+            resume: false,
+            refresh: None,
+            prune_stale_edges: false,
+            define_schema: true,
+            table: "site".to_owned(),
+            relate_table: "containslink".to_owned(),
+            redirect_table: "redirectsto".to_owned(),
+            canonical_table: "canonicalof".to_owned(),
+            frontier_table: "frontier".to_owned(),
+            email_table: "email".to_owned(),
+            email_relate_table: "mentionsemail".to_owned(),
+            strategy: Strategy::Bfs,
+            concurrency: 16,
+            per_host_concurrency: 2,
+            max_depth: None,
+            max_pages: None,
+            max_pages_per_domain: None,
+            by_registrable_domain: false,
+            same_domain: false,
+            scope: Scope::Host,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            record_excluded: false,
+            allow_domains: Vec::new(),
+            deny_domains: Vec::new(),
+            record_external: false,
+            record_other_schemes: false,
+            extension_filter: true,
+            skip_extensions: Vec::new(),
+            allow_extensions: Vec::new(),
+            normalize: true,
+            fold_trailing_slashes: false,
+            keep_fragments: false,
+            strip_params: None,
+            strip_all_params: false,
+            robots: true,
+            max_crawl_delay: Duration::from_secs(30),
+            meta_robots: true,
+            max_retry_after: Duration::from_secs(300),
+            host_backoff_after: 3,
+            bench_host_after: 10,
+            delay: Duration::ZERO,
+            rate: None,
+            retries: 2,
+            user_agent: DEFAULT_USER_AGENT.to_owned(),
+            headers: HeaderMap::new(),
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            max_redirects: 10,
+            ca_certs: Vec::new(),
+            insecure: false,
+            allow_private: false,
+            proxies: Vec::new(),
+            cookies: None,
+            auth: None,
+            head_precheck: None,
+            max_body_bytes: 10 * 1024 * 1024,
+            content_types: Vec::new(),
+            parse_errors: false,
+            skip_nofollow: false,
+            record_nofollow: false,
+            follow_canonical: false,
+            skip_duplicate_content: false,
+            store_content: None,
+            extract_text: None,
+            collect_emails: false,
+            webhook: None,
+            emit: None,
+            events: None,
+            count_host_pages: false,
+            run: None,
+        }
+    }
+}
+
+impl CrawlerBuilder {
+    /// The urls to start from. Not needed when resuming.
+    pub fn seeds(mut self, seeds: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.seeds = seeds.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// An XML sitemap (or sitemap index, optionally gzipped) whose urls are crawled alongside the seeds.
+    pub fn sitemap(mut self, sitemap: impl Into<Option<String>>) -> Self {
+        self.sitemap = sitemap.into();
+        self
+    }
+
+    /// Carry on an interrupted crawl from the urls still in its frontier table, instead of starting from the seeds.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Crawl an existing db again, only re-fetching pages last checked longer ago than `older_than`.
+    pub fn refresh(mut self, older_than: impl Into<Option<Duration>>) -> Self {
+        self.refresh = older_than.into();
+        self
+    }
+
+    /// With [`CrawlerBuilder::refresh`], delete the relations for links that are no longer on a page.
+    pub fn prune_stale_edges(mut self, prune: bool) -> Self {
+        self.prune_stale_edges = prune;
+        self
+    }
+
+    /// Define the tables' field types and indexes before crawling, if they aren't already.
+    pub fn define_schema(mut self, define: bool) -> Self {
+        self.define_schema = define;
+        self
+    }
+
+    /// The table the urls are stored in.
+    pub fn table(mut self, table: impl Into<String>) -> Self {
+        self.table = table.into();
+        self
+    }
+
+    pub fn relate_table(mut self, table: impl Into<String>) -> Self {
+        self.relate_table = table.into();
+        self
+    }
+
+    pub fn redirect_table(mut self, table: impl Into<String>) -> Self {
+        self.redirect_table = table.into();
+        self
+    }
+
+    pub fn canonical_table(mut self, table: impl Into<String>) -> Self {
+        self.canonical_table = table.into();
+        self
+    }
+
+    /// The table urls waiting to be crawled are kept in until they're done.
+    pub fn frontier_table(mut self, table: impl Into<String>) -> Self {
+        self.frontier_table = table.into();
+        self
+    }
+
+    pub fn email_table(mut self, table: impl Into<String>) -> Self {
+        self.email_table = table.into();
+        self
+    }
+
+    pub fn email_relate_table(mut self, table: impl Into<String>) -> Self {
+        self.email_relate_table = table.into();
+        self
+    }
+
+    /// Which queued url to crawl next.
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// The most pages fetched at once.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// The most pages fetched at once from the same host.
+    pub fn per_host_concurrency(mut self, concurrency: usize) -> Self {
+        self.per_host_concurrency = concurrency;
+        self
+    }
+
+    /// The deepest link to fetch from a seed. Pages past this are recorded but not fetched.
+    pub fn max_depth(mut self, depth: impl Into<Option<u32>>) -> Self {
+        self.max_depth = depth.into();
+        self
+    }
+
+    /// The most pages to fetch in total.
+    pub fn max_pages(mut self, pages: impl Into<Option<usize>>) -> Self {
+        self.max_pages = pages.into();
+        self
+    }
+
+    /// The most pages to fetch from any one site, going by [`CrawlerBuilder::scope`], or from any one registrable domain
+    /// with `by_registrable_domain`.
+    pub fn max_pages_per_domain(mut self, pages: impl Into<Option<usize>>, by_registrable_domain: bool) -> Self {
+        self.max_pages_per_domain = pages.into();
+        self.by_registrable_domain = by_registrable_domain;
+        self
+    }
+
+    /// Only fetch pages on the same site as one of the seeds, going by [`CrawlerBuilder::scope`].
+    pub fn same_domain(mut self, same_domain: bool) -> Self {
+        self.same_domain = same_domain;
+        self
+    }
+
+    /// What counts as the same site, for [`CrawlerBuilder::same_domain`], marking links as external, and counting
+    /// pages for [`CrawlerBuilder::max_pages_per_domain`].
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Only crawl urls matching one of these, besides the seeds.
+    pub fn include(mut self, patterns: Vec<Regex>) -> Self {
+        self.include = patterns;
+        self
+    }
+
+    /// Never crawl urls matching these.
+    pub fn exclude(mut self, patterns: Vec<Regex>) -> Self {
+        self.exclude = patterns;
+        self
+    }
+
+    /// Still record urls skipped by [`CrawlerBuilder::include`] or [`CrawlerBuilder::exclude`], with `excluded = true`.
+    pub fn record_excluded(mut self, record: bool) -> Self {
+        self.record_excluded = record;
+        self
+    }
+
+    /// Only crawl hosts matching one of these.
+    pub fn allow_domains(mut self, patterns: Vec<DomainPattern>) -> Self {
+        self.allow_domains = patterns;
+        self
+    }
+
+    /// Never crawl hosts matching these.
+    pub fn deny_domains(mut self, patterns: Vec<DomainPattern>) -> Self {
+        self.deny_domains = patterns;
+        self
+    }
+
+    /// Still record urls on hosts skipped by the domain filters, with `external = true`.
+    pub fn record_external(mut self, record: bool) -> Self {
+        self.record_external = record;
+        self
+    }
+
+    /// Record links with schemes other than http and https, like `mailto:` or `tel:`, as nodes. They're never fetched.
+    pub fn record_other_schemes(mut self, record: bool) -> Self {
+        self.record_other_schemes = record;
+        self
+    }
+
+    /// Whether to skip urls with file extensions that are never pages (images, media, archives, ...).
+    pub fn extension_filter(mut self, filter: bool) -> Self {
+        self.extension_filter = filter;
+        self
+    }
+
+    /// Extensions to skip on top of the built-in ones.
+    pub fn skip_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.skip_extensions = extensions;
+        self
+    }
+
+    /// Extensions to take off the built-in list.
+    pub fn allow_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.allow_extensions = extensions;
+        self
+    }
+
+    /// Whether to normalize urls (host case, default ports, duplicate slashes, percent-encoding) before comparing them.
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Treat /a/ and /a as the same page when there's no query string.
+    pub fn fold_trailing_slashes(mut self, fold: bool) -> Self {
+        self.fold_trailing_slashes = fold;
+        self
+    }
+
+    /// Treat urls that only differ by #fragment as different pages.
+    pub fn keep_fragments(mut self, keep: bool) -> Self {
+        self.keep_fragments = keep;
+        self
+    }
+
+    /// Remove tracking query parameters (utm_*, fbclid, gclid, ...) from urls, along with the `extra` names.
+    pub fn strip_tracking_params(mut self, extra: impl Into<Option<Vec<String>>>) -> Self {
+        self.strip_params = extra.into();
+        self
+    }
+
+    /// Remove the whole query string from every url.
+    pub fn strip_all_params(mut self, strip: bool) -> Self {
+        self.strip_all_params = strip;
+        self
+    }
+
+    /// Whether to follow robots.txt, waiting out `Crawl-delay`s up to `max_crawl_delay`.
+    pub fn robots(mut self, robots: bool, max_crawl_delay: Duration) -> Self {
+        self.robots = robots;
+        self.max_crawl_delay = max_crawl_delay;
+        self
+    }
+
+    /// Whether to follow `<meta name="robots">` tags and `X-Robots-Tag` headers.
+    pub fn meta_robots(mut self, meta_robots: bool) -> Self {
+        self.meta_robots = meta_robots;
+        self
+    }
+
+    /// The minimum delay between requests to the same host.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// The most requests per second to send overall.
+    pub fn rate(mut self, rate: impl Into<Option<f64>>) -> Self {
+        self.rate = rate.into();
+        self
+    }
+
+    /// How many times to retry a request after a connection error, timeout, or 5xx response, and how many times a page
+    /// that was rate limited goes back in the frontier before it's given up on.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// How many failures in a row a host gets before it's backed off of, with a cooldown that doubles after every
+    /// further one, and before it's benched for the rest of the crawl. Any success resets the count.
+    pub fn host_errors(mut self, backoff_after: u32, bench_after: u32) -> Self {
+        self.host_backoff_after = backoff_after;
+        self.bench_host_after = bench_after;
+        self
+    }
+
+    /// The longest `Retry-After` on a 429 or 503 to wait out. Hosts asking for more are given up on instead.
+    pub fn max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = max_retry_after;
+        self
+    }
+
+    /// The User-Agent sent with every request, also what robots.txt rules are matched against.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Extra headers sent with every request.
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// The timeout for a whole request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// How many redirects to follow in a row, 0 for none. One that isn't followed is recorded as a redirect to its
+    /// Location, which is crawled like any other url.
+    pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Extra CA certificates to trust.
+    pub fn ca_certs(mut self, certs: Vec<Certificate>) -> Self {
+        self.ca_certs = certs;
+        self
+    }
+
+    /// Accept invalid and self-signed TLS certificates.
+    pub fn insecure(mut self, insecure: bool) -> Self {
+        self.insecure = insecure;
+        self
+    }
+
+    /// Fetch pages on loopback, private, link-local and unique-local addresses.
+    pub fn allow_private(mut self, allow: bool) -> Self {
+        self.allow_private = allow;
+        self
+    }
+
+    /// Proxies to rotate requests across.
+    pub fn proxies(mut self, proxies: Vec<String>) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    /// A cookie jar to keep cookies in and send them back from.
+    pub fn cookies(mut self, jar: Arc<CookieStoreMutex>) -> Self {
+        self.cookies = Some(jar);
+        self
+    }
+
+    /// HTTP basic auth sent with every page request.
+    pub fn basic_auth(mut self, user: impl Into<String>, pass: Option<String>) -> Self {
+        self.auth = Some(Auth::Basic { user: user.into(), pass });
+        self
+    }
+
+    /// A bearer token sent with every page request.
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.auth = Some(Auth::Bearer(token.into()));
+        self
+    }
+
+    /// Send a HEAD request first and skip pages that aren't text or are bigger than `max_bytes`.
+    pub fn head_precheck(mut self, max_bytes: impl Into<Option<u64>>) -> Self {
+        self.head_precheck = max_bytes.into();
+        self
+    }
+
+    /// The most bytes of a response body to download.
+    pub fn max_body_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_body_bytes = max_bytes;
+        self
+    }
+
+    /// Extra content types to parse for links besides text/*, JSON and XML.
+    pub fn content_types(mut self, content_types: Vec<String>) -> Self {
+        self.content_types = content_types;
+        self
+    }
+
+    /// Still parse pages for links when they respond with an error status.
+    pub fn parse_errors(mut self, parse: bool) -> Self {
+        self.parse_errors = parse;
+        self
+    }
+
+    /// Don't follow nofollow links, but still record them if `record` is set.
+    pub fn skip_nofollow(mut self, skip: bool, record: bool) -> Self {
+        self.skip_nofollow = skip;
+        self.record_nofollow = record;
+        self
+    }
+
+    /// Treat a page's canonical url as already visited.
+    pub fn follow_canonical(mut self, follow: bool) -> Self {
+        self.follow_canonical = follow;
+        self
+    }
+
+    /// Don't parse a page whose body is exactly the same as one already parsed, which still gets recorded as its
+    /// `duplicate_of`.
+    pub fn skip_duplicate_content(mut self, skip: bool) -> Self {
+        self.skip_duplicate_content = skip;
+        self
+    }
+
+    /// Keep the body of every page that's parsed. Bodies are only as long as [`CrawlerBuilder::max_body_bytes`] lets
+    /// them be, and pages that aren't text are never parsed, so never stored.
+    pub fn store_content(mut self, storage: impl Into<Option<ContentStorage>>) -> Self {
+        self.store_content = storage.into();
+        self
+    }
+
+    /// Record the readable text of every page that's parsed, up to `max_chars` of it.
+    pub fn extract_text(mut self, max_chars: impl Into<Option<usize>>) -> Self {
+        self.extract_text = max_chars.into();
+        self
+    }
+
+    /// Record the email addresses on pages.
+    pub fn collect_emails(mut self, collect: bool) -> Self {
+        self.collect_emails = collect;
+        self
+    }
+
+    /// POST the `events` kinds to `url`, batched. All of them if `events` is empty.
+    pub fn webhook(mut self, url: Url, events: Vec<EventKind>) -> Self {
+        self.webhook = Some((url, events));
+        self
+    }
+
+    /// Stream every new relation to stdout as it's found.
+    pub fn emit(mut self, format: impl Into<Option<EmitFormat>>) -> Self {
+        self.emit = format.into();
+        self
+    }
+
+    /// Sends every [`CrawlEvent`] here as it happens. The crawl waits for room in the channel, so keep up with it.
+    pub fn events(mut self, events: mpsc::Sender<CrawlEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Keep count of the pages fetched from each host, for [`CrawlHandle::host_pages`].
+    pub fn count_host_pages(mut self, count: bool) -> Self {
+        self.count_host_pages = count;
+        self
+    }
+
+    /// Stamp the nodes and relations this crawl creates with `run`, like one from [`db::start_run`](crate::db::start_run).
+    pub fn run(mut self, run: Thing) -> Self {
+        self.run = Some(run);
+        self
+    }
+
+    /// Sets up the crawler to write into `store`, like a [`SurrealStore`](crate::store::SurrealStore).
+    ///
+    /// This is where the seeds are checked, and the HTTP clients are built.
+    pub fn build(self, store: Arc<dyn GraphStore>) -> anyhow::Result<Crawler> {
+        let seeds = dedupe_seeds(self.seeds)?;
+        if seeds.is_empty() && !self.resume {
+            anyhow::bail!("There are no seeds to crawl");
+        }
+
+        for proxy in &self.proxies {
+            proxies::check_proxy(proxy)?;
+        }
+
+        if let Some(ContentStorage::Dir(dir)) = &self.store_content {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {dir:?} to store page content in"))?;
+        }
+
+        if self.insecure {
+            warn!("--insecure is on, TLS certificates are NOT being checked. Anything fetched over https could have been tampered with.");
+        }
+
+        // the proxies themselves are allowed to be on a private network, only what's fetched through them isn't.
+        let proxy_hosts: HashSet<String> = self.proxies
+            .iter()
+            .filter_map(|proxy| Url::parse(proxy).ok()?.host_str().map(str::to_owned))
+            .collect();
+
+        let same_domain_hosts = if self.same_domain {
+            if seeds.is_empty() {
+                anyhow::bail!("--same-domain needs --url to know which hosts to stay on");
+            }
+
+            let mut hosts = seeds
+                .iter()
+                .map(|seed| Url::parse(seed)?.host_str().map(str::to_owned).ok_or_else(|| anyhow::anyhow!("Initial url {seed:?} has no host")))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            hosts.sort();
+            hosts.dedup();
+            info!("Restricting crawl to hosts {hosts:?}");
+            Some(hosts)
+        } else {
+            None
+        };
+
+        let url_filter = UrlFilter {
+            include: self.include,
+            exclude: self.exclude,
+        };
+        let site_scope = SiteScope {
+            scope: self.scope,
+            domains: DomainFilter {
+                allow: self.allow_domains,
+                deny: self.deny_domains,
+            },
+        };
+        let redirect_scope = RedirectScope {
+            url_filter: url_filter.clone(),
+            site_scope: site_scope.clone(),
+            same_domain_hosts: same_domain_hosts.clone(),
+        };
+
+        let clients = ProxyPool::new(&self.proxies, |proxy| -> reqwest::Result<Client> {
+            let mut client_builder = Client::builder()
+                .user_agent(&self.user_agent)
+                .default_headers(self.headers.clone())
+                .timeout(self.timeout)
+                .connect_timeout(self.connect_timeout);
+
+            // proxied connections are still TLS to the site itself, so these apply to every client.
+            for cert in &self.ca_certs {
+                client_builder = client_builder.add_root_certificate(cert.clone());
+            }
+            if self.insecure {
+                client_builder = client_builder.danger_accept_invalid_certs(true);
+            }
+
+            if !self.allow_private {
+                client_builder = client_builder.dns_resolver(Arc::new(PublicResolver { trusted_hosts: proxy_hosts.clone() }));
+            }
+
+            let redirect_scope = redirect_scope.clone();
+            client_builder = client_builder.redirect(address::redirect_policy(self.max_redirects, self.allow_private, move |url| redirect_scope.allows(url)));
+
+            // every proxy's client shares the one jar, so a session carries over whichever proxy is used.
+            if let Some(jar) = &self.cookies {
+                client_builder = client_builder.cookie_provider(jar.clone());
+            }
+
+            if let Some(proxy) = proxy {
+                client_builder = client_builder.proxy(Proxy::all(proxy)?);
+            }
+
+            client_builder.build()
+        })?;
+        if self.proxies.len() > 1 {
+            info!("Rotating requests across {} proxies", self.proxies.len());
+        }
+
+        let (webhook, webhook_task) = match self.webhook {
+            Some((url, events)) => {
+                let events = if events.is_empty() { EventKind::value_variants().to_vec() } else { events };
+                let (webhook, task) = Webhook::start(url, events, &self.user_agent)?;
+                (Some(webhook), Some(task))
+            }
+            None => (None, None),
+        };
+
+        let state = Arc::new(AppState {
+            store,
+            table: self.table,
+            relate_table: self.relate_table,
+            redirect_table: self.redirect_table,
+            canonical_table: self.canonical_table,
+            email_finder: self.collect_emails.then(EmailFinder::new),
+            email_table: self.email_table,
+            email_relate_table: self.email_relate_table,
+            webhook,
+            events: self.events,
+            emitter: self.emit.map(Emitter::new),
+            follow_canonical: self.follow_canonical,
+            skip_duplicate_content: self.skip_duplicate_content,
+            content_hashes: Default::default(),
+            content_storage: self.store_content,
+            extract_text: self.extract_text,
+            frontier: Frontier::new(self.strategy),
+            frontier_table: self.frontier_table,
+            clients,
+            max_depth: self.max_depth,
+            max_pages: self.max_pages,
+            max_pages_per_domain: self.max_pages_per_domain,
+            domain_scope: if self.by_registrable_domain {
+                SiteScope { scope: Scope::Domain, ..SiteScope::default() }
+            } else {
+                site_scope.clone()
+            },
+            same_domain_hosts,
+            url_filter,
+            record_excluded: self.record_excluded,
+            record_external: self.record_external,
+            record_other_schemes: self.record_other_schemes,
+            extension_filter: self.extension_filter.then(|| ExtensionFilter::new(&self.skip_extensions, &self.allow_extensions)),
+            site_scope,
+            robots: self.robots.then(|| RobotsCache::new(robots_agent(&self.user_agent), self.max_crawl_delay)),
+            meta_robots: self.meta_robots.then(|| robots_agent(&self.user_agent).to_owned()),
+            politeness: Politeness::new(self.delay, self.max_retry_after),
+            host_limits: HostLimits::new(self.per_host_concurrency.max(1)),
+            host_health: HostHealth::new(self.host_backoff_after, self.bench_host_after.max(1)),
+            rate_limit: self.rate.map(RateLimit::new),
+            retries: self.retries,
+            allow_private: self.allow_private,
+            auth: self.auth,
+            head_precheck: self.head_precheck,
+            max_body_bytes: self.max_body_bytes,
+            content_types: self.content_types
+                .iter()
+                .map(|content_type| content_type.trim().to_lowercase())
+                .collect(),
+            parse_errors: self.parse_errors,
+            skip_nofollow: self.skip_nofollow,
+            record_nofollow: self.record_nofollow,
+            refresh: self.refresh,
+            prune_stale_edges: self.prune_stale_edges,
+            normalizer: UrlNormalizer {
+                normalize: self.normalize,
+                fold_trailing_slashes: self.fold_trailing_slashes,
+                keep_fragments: self.keep_fragments,
+                strip_params: self.strip_params.map(|extra| {
+                    TRACKING_PARAMS
+                        .iter()
+                        .map(|param| param.to_string())
+                        .chain(extra)
+                        .collect()
+                }),
+                strip_all_params: self.strip_all_params,
+            },
+            visited: Visited::new(self.refresh.is_some()),
+            pages_fetched: AtomicUsize::new(0),
+            pages_reserved: AtomicUsize::new(0),
+            host_pages: self.count_host_pages.then(Default::default),
+            run: self.run,
+            domain_pages: Default::default(),
+            other_schemes: Default::default(),
+            errors: AtomicUsize::new(0),
+            error_kinds: Default::default(),
+            bytes_downloaded: AtomicU64::new(0),
+            requests: AtomicUsize::new(0),
+            #[cfg(feature = "metrics")]
+            in_flight: AtomicUsize::new(0),
+            #[cfg(feature = "metrics")]
+            fetch_durations: Histogram::default(),
+            nodes_created: AtomicUsize::new(0),
+            relations_created: AtomicUsize::new(0),
+            skipped_content_type: AtomicUsize::new(0),
+            duplicate_pages: AtomicUsize::new(0),
+            hosts: Default::default(),
+            started: Instant::now(),
+        });
+
+        Ok(Crawler {
+            state,
+            seeds,
+            sitemap: self.sitemap,
+            resume: self.resume,
+            define_schema: self.define_schema,
+            concurrency: self.concurrency.max(1),
+            webhook_task,
+        })
+    }
+}
+
+/// Parses `seeds`, leaving out any that are the same url as an earlier one. A bad seed is an error, unlike any
+/// url found during the crawl.
+pub fn dedupe_seeds(seeds: Vec<String>) -> anyhow::Result<Vec<String>> {
+    let mut deduped = Vec::new();
+    let mut seen = HashSet::new();
+    for seed in seeds {
+        let parsed = Url::parse(&seed).map_err(|err| anyhow::anyhow!("Invalid seed url {seed:?}: {err}"))?;
+
+        // a long list is likely to repeat itself.
+        if seen.insert(parsed.to_string()) {
+            deduped.push(seed);
+        }
+    }
+
+    Ok(deduped)
+}
+
+/// Crawls from a set of seeds into a [`GraphStore`], made with [`Crawler::builder`].
+pub struct Crawler {
+    state: Arc<AppState>,
+    seeds: Vec<String>,
+    sitemap: Option<String>,
+    resume: bool,
+    define_schema: bool,
+    concurrency: usize,
+    webhook_task: Option<JoinHandle<()>>,
+}
+
+impl Crawler {
+    pub fn builder() -> CrawlerBuilder {
+        CrawlerBuilder::default()
+    }
+
+    /// A way to check on (or stop) the crawl while [`Crawler::crawl`] runs.
+    pub fn handle(&self) -> CrawlHandle {
+        CrawlHandle {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Crawls until there's nothing left to, a limit is reached, or [`CrawlHandle::stop`] is called.
+    pub async fn crawl(self) -> anyhow::Result<Summary> {
+        let state = self.state;
+
+        if self.define_schema {
+            let email_relate_table = state.email_finder.is_some().then_some(state.email_relate_table.as_str());
+            let relate_tables: Vec<&str> = [state.relate_table.as_str(), &state.redirect_table, &state.canonical_table].into_iter().chain(email_relate_table).collect();
+            state.store.define_schema(&state.table, &relate_tables).await?;
+        } else {
+            warn!("Not defining the schema, so nothing stops the same url or relation from being written twice");
+        }
+
+        if self.resume {
+            let pending = state.store.load_frontier(&state.frontier_table).await?;
+            if pending.is_empty() {
+                info!("Nothing left in the frontier to resume");
+            } else {
+                info!("Resuming with {} queued urls ...", pending.len());
+            }
+            state.frontier.extend(pending);
+        } else {
+            info!("Scraping for site linkages ...");
+            let seeds = self.seeds
+                .into_iter()
+                .map(|url| FrontierItem {
+                    url,
+                    ..Default::default()
+                })
+                .collect();
+            state.enqueue(seeds, None).await?;
+
+            if let Some(sitemap) = &self.sitemap {
+                let seeded = seed_from_sitemap(sitemap, &state).await;
+                info!("Seeded {seeded} urls from sitemap {sitemap:?}");
+            }
+        }
+
+        // with nothing queued, there'd be no last item for a worker to finish, so they'd wait forever.
+        if state.frontier.pending() > 0 {
+            let workers: Vec<_> = (0..self.concurrency)
+                .map(|_| tokio::spawn(crawl_worker(state.clone())))
+                .collect();
+
+            for worker in workers {
+                worker.await?;
+            }
+        }
+
+        if state.frontier.is_stopped() {
+            info!(
+                "Stopped early: {} pages fetched, {} pending",
+                state.pages_fetched.load(Ordering::SeqCst),
+                state.store.frontier_len(&state.frontier_table).await?,
+            );
+        } else {
+            info!(
+                "Done scraping connections ({} pages fetched, {} discovered but not fetched)",
+                state.pages_fetched.load(Ordering::SeqCst),
+                state.visited.unfetched(),
+            );
+        }
+
+        state.store.flush().await?;
+
+        let summary = state.summary();
+        state.notify(|| CrawlEvent::CrawlFinished(summary.clone())).await;
+
+        if let (Some(webhook), Some(task)) = (&state.webhook, self.webhook_task) {
+            webhook.finish().await;
+            task.await?;
+        }
+
+        Ok(summary)
+    }
+}
+
+/// Checks on a crawl from outside it, see [`Crawler::handle`].
+#[derive(Clone)]
+pub struct CrawlHandle {
+    state: Arc<AppState>,
+}
+
+impl CrawlHandle {
+    /// The crawl's counters so far.
+    pub fn status(&self) -> Status {
+        self.state.status()
+    }
+
+    /// Lets the pages in flight finish and then ends the crawl, leaving the rest in the frontier table to resume.
+    pub fn stop(&self) {
+        self.state.frontier.stop();
+    }
+
+    /// How many pages were fetched from each host, with [`CrawlerBuilder::count_host_pages`].
+    pub fn host_pages(&self) -> Option<HashMap<String, usize>> {
+        self.state.host_pages.as_ref().map(|host_pages| host_pages.lock().unwrap().clone())
+    }
+
+    /// The crawl's metrics in Prometheus' text format.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> String {
+        self.state.metrics().to_string()
+    }
+}
+
+/// Credentials attached to every page request.
+enum Auth {
+    Basic { user: String, pass: Option<String> },
+    Bearer(String),
+}
+
+/// What a redirect has to stay within to be followed. One that leaves it is recorded as pointing there instead,
+/// and what it points to goes through the filters like any other link, so it ends up external or excluded.
+#[derive(Clone)]
+struct RedirectScope {
+    url_filter: UrlFilter,
+    site_scope: SiteScope,
+    same_domain_hosts: Option<Vec<String>>,
+}
+
+impl RedirectScope {
+    fn allows(&self, url: &Url) -> bool {
+        on_seed_sites(&self.site_scope, self.same_domain_hosts.as_deref(), url)
+            && self.site_scope.domains.check(url.host_str()).is_ok()
+            && matches!(self.url_filter.check(url.as_str()), FilterDecision::Fetch)
+    }
+}
+
+/// Whether `url` is on the same site as one of `hosts`, the seeds' hosts when there's `--same-domain`. Without it,
+/// every url is.
+fn on_seed_sites(scope: &SiteScope, hosts: Option<&[String]>, url: &Url) -> bool {
+    hosts.is_none_or(|hosts| hosts.iter().any(|host| scope.same_site(Some(host), url.host_str())))
+}
+
+struct AppState {
+    store: Arc<dyn GraphStore>,
+    table: String,
+    relate_table: String,
+    redirect_table: String,
+    canonical_table: String,
+    follow_canonical: bool,
+    skip_duplicate_content: bool,
+    /// The first page parsed with each content hash, to spot the ones after it that are the same.
+    content_hashes: Mutex<HashMap<String, Thing>>,
+    content_storage: Option<ContentStorage>,
+    /// How many chars of each page's text to keep, with `--extract-text`.
+    extract_text: Option<usize>,
+    webhook: Option<Webhook>,
+    events: Option<mpsc::Sender<CrawlEvent>>,
+    emitter: Option<Emitter>,
+    /// Set with `--collect-emails`.
+    email_finder: Option<EmailFinder>,
+    email_table: String,
+    email_relate_table: String,
+    frontier: Frontier,
+    frontier_table: String,
+    clients: ProxyPool,
+    max_depth: Option<u32>,
+    max_pages: Option<usize>,
+    max_pages_per_domain: Option<usize>,
+    /// What `--max-pages-per-domain` counts pages under.
+    domain_scope: SiteScope,
+    same_domain_hosts: Option<Vec<String>>,
+    /// Which hosts are the same site, along with the `--allow-domain` and `--deny-domain` lists.
+    site_scope: SiteScope,
+    url_filter: UrlFilter,
+    extension_filter: Option<ExtensionFilter>,
+    record_excluded: bool,
+    record_external: bool,
+    record_other_schemes: bool,
+    robots: Option<RobotsCache>,
+    /// The agent name meta robots tags are matched against, or `None` with `--ignore-meta-robots`.
+    meta_robots: Option<String>,
+    /// Per-host request gaps, from `--delay-ms`, robots.txt's `Crawl-delay` and `Retry-After`s.
+    politeness: Politeness,
+    host_limits: HostLimits,
+    /// Consecutive failures per host, from `--host-backoff-after` and `--bench-host-after`.
+    host_health: HostHealth,
+    rate_limit: Option<RateLimit>,
+    retries: u32,
+    allow_private: bool,
+    auth: Option<Auth>,
+    head_precheck: Option<u64>,
+    max_body_bytes: u64,
+    content_types: Vec<String>,
+    parse_errors: bool,
+    skip_nofollow: bool,
+    record_nofollow: bool,
+    /// With `--refresh`, pages checked more recently than this ago aren't fetched again.
+    refresh: Option<Duration>,
+    prune_stale_edges: bool,
+    normalizer: UrlNormalizer,
+    visited: Visited,
+    pages_fetched: AtomicUsize,
+    /// Pages fetched or being fetched right now, which is what `--max-pages` is held to.
+    pages_reserved: AtomicUsize,
+    /// How many pages were fetched from each host, kept for `--dry-run`'s summary.
+    host_pages: Option<Mutex<HashMap<String, usize>>>,
+    /// What created nodes and relations are stamped with.
+    run: Option<Thing>,
+    /// How many pages were fetched from each host or registrable domain, for `--max-pages-per-domain`.
+    domain_pages: Mutex<HashMap<String, usize>>,
+    /// How many links had each scheme other than http and https.
+    other_schemes: Mutex<BTreeMap<String, usize>>,
+    /// Pages that failed to be crawled.
+    errors: AtomicUsize,
+    error_kinds: Mutex<BTreeMap<&'static str, usize>>,
+    bytes_downloaded: AtomicU64,
+    /// Every request sent, retries and HEADs included.
+    requests: AtomicUsize,
+    /// Requests sent that haven't gotten a response yet.
+    #[cfg(feature = "metrics")]
+    in_flight: AtomicUsize,
+    /// How long pages took to respond, for `--metrics-addr`.
+    #[cfg(feature = "metrics")]
+    fetch_durations: Histogram,
+    nodes_created: AtomicUsize,
+    relations_created: AtomicUsize,
+    skipped_content_type: AtomicUsize,
+    /// Pages with the same content as one parsed before them.
+    duplicate_pages: AtomicUsize,
+    /// Every host a node was created for.
+    hosts: Mutex<HashSet<String>>,
+    started: Instant,
+}
+
+impl AppState {
+    /// The first other page seen with `hash`, or `None` if `page` is the first.
+    fn duplicate_of(&self, hash: &str, page: &Thing) -> Option<Thing> {
+        let mut hashes = self.content_hashes.lock().unwrap();
+        let first = hashes.entry(hash.to_owned()).or_insert_with(|| page.clone());
+
+        (first != page).then(|| first.clone())
+    }
+
+    fn page_limit_reached(&self) -> bool {
+        self.max_pages
+            .is_some_and(|max| self.pages_reserved.load(Ordering::SeqCst) >= max)
+    }
+
+    /// Takes one of the `--max-pages` slots, or returns false if they're all taken.
+    fn reserve_page(&self) -> bool {
+        let Some(max) = self.max_pages else {
+            return true;
+        };
+
+        self.pages_reserved
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pages| (pages < max).then_some(pages + 1))
+            .is_ok()
+    }
+
+    /// Gives back a slot from [`AppState::reserve_page`] for a page that didn't end up being fetched.
+    fn release_page(&self) {
+        if self.max_pages.is_some() {
+            self.pages_reserved.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// What `--max-pages-per-domain` counts `host` as.
+    fn domain_key<'a>(&self, host: &'a str) -> &'a str {
+        self.domain_scope.site(host)
+    }
+
+    fn domain_limit_reached(&self, host: &str) -> bool {
+        self.max_pages_per_domain.is_some_and(|max| {
+            self.domain_pages.lock().unwrap().get(self.domain_key(host)).is_some_and(|&pages| pages >= max)
+        })
+    }
+
+    fn count_domain_page(&self, host: &str) {
+        if self.max_pages_per_domain.is_some() {
+            *self.domain_pages.lock().unwrap().entry(self.domain_key(host).to_owned()).or_default() += 1;
+        }
+    }
+
+    /// The hosts or registrable domains that reached `--max-pages-per-domain`, sorted.
+    fn capped_domains(&self) -> Vec<String> {
+        let Some(max) = self.max_pages_per_domain else {
+            return Vec::new();
+        };
+
+        let mut capped: Vec<String> = self.domain_pages
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, &pages)| pages >= max)
+            .map(|(domain, _)| domain.clone())
+            .collect();
+        capped.sort();
+        capped
+    }
+
+    /// [`Visited::claim`] for the node table, keeping count of what gets created.
+    /// With `--refresh`, nodes from the last crawl count as created the first time they come up.
+    async fn claim(&self, node: &SiteURLNode, deferred: bool) -> anyhow::Result<(Thing, bool)> {
+        let node = &SiteURLNode {
+            first_seen_run: self.run.clone(),
+            ..node.clone()
+        };
+        let (id, claimed) = self.visited.claim(self.store.as_ref(), &self.table, node, deferred).await?;
+
+        match claimed {
+            Claimed::Created => self.node_created(&id, node).await,
+            // whoever deferred it got there first, maybe by a longer way.
+            Claimed::Lifted => self.store.lower_depth(id.clone(), node.depth).await?,
+            Claimed::Existing | Claimed::Seen => {}
+        }
+
+        Ok((id, self.owns(claimed)))
+    }
+
+    /// Whether a claim that went `claimed` is ours to fetch.
+    fn owns(&self, claimed: Claimed) -> bool {
+        match claimed {
+            Claimed::Created | Claimed::Lifted => true,
+            Claimed::Existing => self.refresh.is_some(),
+            Claimed::Seen => false,
+        }
+    }
+
+    /// Whether a page reached by `link` at `depth` won't be fetched whether or not it gets claimed, so it shouldn't
+    /// stop a later link from fetching it.
+    fn deferred(&self, depth: u32, link: &LinkData) -> bool {
+        link.nofollow == Some(true) || self.max_depth.is_some_and(|max_depth| depth > max_depth)
+    }
+
+    async fn node_created(&self, id: &Thing, node: &SiteURLNode) {
+        self.nodes_created.fetch_add(1, Ordering::SeqCst);
+        if let Some(host) = Url::parse(&node.url).ok().as_ref().and_then(Url::host_str) {
+            self.hosts.lock().unwrap().insert(host.to_owned());
+        }
+
+        self.notify(|| CrawlEvent::NodeCreated {
+            id: id.clone(),
+            node: Box::new(node.clone()),
+        })
+        .await;
+    }
+
+    /// [`GraphStore::record_edge`] from `a` to `b` (which is `url` at `depth`), keeping count of the relations created.
+    async fn relate(&self, table: &str, a: Thing, b: Thing, url: &str, link: &LinkData, depth: u32) -> anyhow::Result<()> {
+        let link = &LinkData {
+            run: self.run.clone(),
+            ..link.clone()
+        };
+        if let Some(id) = self.store.record_edge(table, a.clone(), b.clone(), link).await? {
+            self.relations_created.fetch_add(1, Ordering::SeqCst);
+            self.notify(|| CrawlEvent::RelationCreated {
+                relate_table: table.to_owned(),
+                id,
+                from: a.clone(),
+                to: b.clone(),
+                url: url.to_owned(),
+                link: link.clone(),
+            })
+            .await;
+
+            if self.emitter.is_some() {
+                let source = self.store.node_url(a).await?.unwrap_or_default();
+                self.emit(&Edge::new(&source, url, depth)).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes `edge` for `--emit`, stopping the crawl if stdout went away, since nothing's left to read what it finds.
+    async fn emit(&self, edge: &Edge<'_>) {
+        let Some(emitter) = &self.emitter else {
+            return;
+        };
+
+        if let Err(err) = emitter.emit(edge).await {
+            warn!(error = %err, "Failed to write to stdout, stopping once the pages in flight are done");
+            self.frontier.stop();
+        }
+    }
+
+    /// [`GraphStore::record_failure`], sending a [`CrawlEvent::FetchFailed`] too.
+    async fn record_failure(&self, id: Thing, url: &str, err: &anyhow::Error) -> anyhow::Result<()> {
+        self.notify(|| CrawlEvent::FetchFailed {
+            id: id.clone(),
+            url: url.to_owned(),
+            error: format!("{err:#}"),
+            error_kind: db::error_kind(err),
+        })
+        .await;
+        self.store.record_failure(id, err).await
+    }
+
+    fn listening(&self) -> bool {
+        self.webhook.is_some() || self.events.is_some()
+    }
+
+    /// Sends an event to `--webhook` and [`CrawlerBuilder::events`], if there are either. `event` is only built
+    /// if it'll be sent.
+    async fn notify(&self, event: impl FnOnce() -> CrawlEvent) {
+        if !self.listening() {
+            return;
+        }
+
+        let event = event();
+        if let Some(webhook) = &self.webhook {
+            webhook.send(&event);
+        }
+        if let Some(events) = &self.events {
+            // nobody's listening anymore, which is up to them.
+            let _ = events.send(event).await;
+        }
+    }
+
+    /// Writes a page's `fetch` info along with nodes and relations for everything in `found`, all in one query
+    /// (see [`GraphStore::record_page`]) rather than leaving each found url to claim itself.
+    ///
+    /// Returns the items this page is now responsible for crawling, as owners. Urls that were already claimed
+    /// only needed their relation and depth, which are written here too, so they're left out.
+    async fn record_page(&self, page: Thing, page_url: &str, fetch: Option<FetchInfo>, found: Vec<FrontierItem>) -> anyhow::Result<Vec<FrontierItem>> {
+        let mut items = Vec::new();
+        let mut nodes: Vec<SiteURLNode> = Vec::new();
+        let (mut links, mut canonicals) = (Vec::new(), Vec::new());
+
+        for mut item in found {
+            let screened = match screen(&item.url, false, self) {
+                Ok(Some(screened)) => screened,
+                Ok(None) => continue,
+                Err(err) => {
+                    debug!(url = %item.url, error = %err, "Invalid url, skipping");
+                    continue;
+                }
+            };
+            item.url = screened.url.to_string();
+
+            if !nodes.iter().any(|node| node.url == item.url) {
+                let mut node = SiteURLNode::new(item.url.clone(), item.depth);
+                node.lastmod = item.lastmod.clone();
+                node.first_seen_run = self.run.clone();
+                nodes.push(node);
+            }
+
+            let link = PageLink {
+                url: item.url.clone(),
+                link: LinkData {
+                    run: self.run.clone(),
+                    ..item.link.clone()
+                },
+            };
+            if item.canonical {
+                canonicals.push(link);
+            } else {
+                links.push(link);
+            }
+
+            items.push(item);
+        }
+
+        let urls: Vec<String> = nodes.iter().map(|node| node.url.clone()).collect();
+        let mut reservation = self.visited.reserve(&urls).await;
+
+        let relations = vec![(self.relate_table.as_str(), links), (self.canonical_table.as_str(), canonicals)];
+        let written = nodes.clone();
+        let (claimed, relations_created) = match self.store.record_page(&self.table, page.clone(), fetch.clone(), nodes, relations).await {
+            Ok(written) => written,
+            Err(err) => {
+                // most likely raced another worker to one of the urls, so let each of them sort itself out.
+                debug!(url = %page, error = format!("{err:#}"), "Couldn't record the page's links in one go, queueing them to claim themselves");
+                drop(reservation);
+
+                if let Some(fetch) = fetch {
+                    self.store.record_fetch(page, fetch).await?;
+                }
+                return Ok(items);
+            }
+        };
+        self.relations_created.fetch_add(relations_created.len(), Ordering::SeqCst);
+
+        if self.listening() || self.emitter.is_some() {
+            let targets: HashMap<String, &SiteURLNode> = claimed.iter().map(|(id, _)| id.to_string()).zip(&written).collect();
+
+            for created in relations_created {
+                let Relation { out, nofollow, text, count, external, run, .. } = created.relation;
+                let Some(target) = targets.get(&out.to_string()) else {
+                    continue;
+                };
+
+                self.notify(|| CrawlEvent::RelationCreated {
+                    relate_table: created.relate_table.to_owned(),
+                    id: created.id,
+                    from: page.clone(),
+                    to: out,
+                    url: target.url.clone(),
+                    link: LinkData { nofollow, text, count, external, run },
+                })
+                .await;
+                self.emit(&Edge::new(page_url, &target.url, target.depth)).await;
+            }
+        }
+
+        // which nodes are ours, and whether they were deferred.
+        let mut ours = HashMap::new();
+        for (node, (id, created)) in written.into_iter().zip(claimed) {
+            let deferred = items.iter().filter(|item| item.url == node.url).all(|item| self.deferred(item.depth, &item.link));
+            let claimed = reservation.claimed(&node.url, id.clone(), created, deferred);
+
+            if claimed == Claimed::Created {
+                self.node_created(&id, &node).await;
+            }
+
+            // a mailto: or tel: link from --record-other-schemes is only there to be recorded, there's nothing to fetch.
+            let web = Url::parse(&node.url).is_ok_and(|url| is_web_scheme(url.scheme()));
+            if web && self.owns(claimed) {
+                ours.insert(node.url, deferred);
+            }
+        }
+
+        // a node linked both ways is followed by way of a link that isn't deferred.
+        let owned = items
+            .into_iter()
+            .filter(|item| ours.get(&item.url) == Some(&self.deferred(item.depth, &item.link)) && ours.remove(&item.url).is_some())
+            .map(|item| FrontierItem { owner: true, ..item })
+            .collect();
+
+        Ok(owned)
+    }
+
+    fn count_other_scheme(&self, scheme: &str) {
+        *self.other_schemes.lock().unwrap().entry(scheme.to_owned()).or_default() += 1;
+    }
+
+    fn record_error(&self, err: &anyhow::Error) {
+        self.errors.fetch_add(1, Ordering::SeqCst);
+        *self.error_kinds.lock().unwrap().entry(db::error_kind(err)).or_default() += 1;
+    }
+
+    fn summary(&self) -> Summary {
+        let requests = self.requests.load(Ordering::SeqCst);
+        let duration_secs = self.started.elapsed().as_secs_f64();
+
+        Summary {
+            pages_fetched: self.pages_fetched.load(Ordering::SeqCst),
+            pages_unfetched: self.visited.unfetched(),
+            nodes_created: self.nodes_created.load(Ordering::SeqCst),
+            relations_created: self.relations_created.load(Ordering::SeqCst),
+            skipped_content_type: self.skipped_content_type.load(Ordering::SeqCst),
+            duplicate_pages: self.duplicate_pages.load(Ordering::SeqCst),
+            errors: self.errors.load(Ordering::SeqCst),
+            errors_by_kind: self.error_kinds.lock().unwrap().clone(),
+            domains: self.hosts.lock().unwrap().len(),
+            capped_domains: self.capped_domains(),
+            benched_hosts: self.host_health.benched(),
+            other_schemes: self.other_schemes.lock().unwrap().clone(),
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::SeqCst),
+            requests,
+            requests_per_sec: requests as f64 / duration_secs.max(f64::EPSILON),
+            duration_secs,
+            stopped_early: self.frontier.is_stopped(),
+            proxy_requests: self.clients.request_counts().into_iter().collect(),
+        }
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            pages_fetched: self.pages_fetched.load(Ordering::SeqCst),
+            pending: self.frontier.pending(),
+            errors: self.errors.load(Ordering::SeqCst),
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::SeqCst),
+            elapsed: self.started.elapsed(),
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    fn metrics(&self) -> Metrics<'_> {
+        Metrics {
+            pages_fetched: self.pages_fetched.load(Ordering::SeqCst),
+            links_discovered: self.relations_created.load(Ordering::SeqCst),
+            errors_by_kind: self.error_kinds.lock().unwrap().clone(),
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::SeqCst),
+            frontier_size: self.frontier.queued(),
+            in_flight_requests: self.in_flight.load(Ordering::SeqCst),
+            fetch_durations: &self.fetch_durations,
+        }
+    }
+
+    /// Writes `items` to the frontier table (taking `done` off it in the same go) and then queues them.
+    async fn enqueue(&self, items: Vec<FrontierItem>, done: Option<Thing>) -> anyhow::Result<()> {
+        self.frontier.extend(self.store.enqueue(&self.frontier_table, items, done).await?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1117 @@
+use std::{collections::HashMap, sync::{atomic::Ordering, Arc}, time::{Duration, Instant}};
+
+use anyhow::Context;
+
+use reqwest::{header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LINK, LOCATION, RETRY_AFTER}, Method, Response, StatusCode};
+use surrealdb::sql::Thing;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use url::Url;
+
+use crate::{
+    address,
+    archive::{self, ContentStorage},
+    content::{self, decode_body, is_generic_content_type, is_html_content_type, is_text_content_type, is_xml_content_type, Sniffed},
+    db, email, extract,
+    filter::{classify_link, FilterDecision, LinkTarget},
+    frontier::FrontierItem,
+    model::{FetchInfo, LinkData, SiteURLNode, Validators},
+    politeness::HostFailure,
+    robots::Verdict,
+    sitemap::{self, Sitemap},
+};
+
+use super::{on_seed_sites, AppState, Auth};
+
+/// The longest non-http(s) link `--record-other-schemes` records.
+const MAX_OTHER_SCHEME_LEN: usize = 2048;
+
+/// How much of a body is read to sniff what it is before deciding whether to download the rest.
+const SNIFF_BYTES: usize = 4096;
+
+pub(super) async fn crawl_worker(state: Arc<AppState>) {
+    while let Some(item) = state.frontier.pop().await {
+        let entry = item.id.clone();
+        let url = item.url.clone();
+        let host = Url::parse(&url).ok().and_then(|url| url.host_str().map(str::to_owned));
+
+        // everything logged while crawling the page is inside this, named the same as the fields of the events in it.
+        let span = info_span!("page", %url, depth = item.depth, host = %host.as_deref().unwrap_or_default());
+        async {
+            let found = discover_sites(item, &state).await.unwrap_or_else(|err| {
+                state.record_error(&err);
+                // i don't think there's any fatal errors here so it's ok to just log it instead of bubbling it up.
+                error!(%url, error_kind = db::error_kind(&err), error = format!("{err:#}"), "Failed to crawl page");
+                Vec::new()
+            });
+
+            // the item only leaves the frontier table along with whatever it found, so nothing is lost if we die in between.
+            if let Err(err) = state.enqueue(found, entry).await {
+                error!("Failed to queue found urls: {err:?}");
+            }
+        }
+        .instrument(span)
+        .await;
+
+        state.frontier.finish();
+    }
+}
+
+/// Crawls one url, returning the urls it found to crawl next.
+async fn discover_sites(item: FrontierItem, state: &AppState) -> anyhow::Result<Vec<FrontierItem>> {
+    let FrontierItem { id: entry, source, url, depth, link, canonical, redirect, lastmod, owner, attempts } = item;
+    let relate_table = if canonical {
+        &state.canonical_table
+    } else if redirect {
+        &state.redirect_table
+    } else {
+        &state.relate_table
+    };
+
+    let Some(Screened { url: parsed_url, external, excluded }) = screen(&url, source.is_none(), state)? else {
+        return Ok(Vec::new());
+    };
+    let url = parsed_url.to_string();
+
+    let mut obj: SiteURLNode = SiteURLNode::new(url.clone(), depth);
+    obj.lastmod = lastmod;
+
+    // creating is the visited check, so two workers can't both decide to fetch the same url.
+    let claim_started = Instant::now();
+    let (node_id, created) = state.claim(&obj, state.deferred(depth, &link)).await?;
+    obj.id = Some(node_id.clone());
+    debug!(%url, created, duration_ms = claim_started.elapsed().as_millis() as u64, "Checked whether it was already visited");
+
+    if let Some(source) = source.clone().filter(|_| !owner) {
+        state.relate(relate_table, source, node_id.clone(), &url, &link, depth).await?;
+    }
+
+    // an owner coming back from the frontier table created the node before the crawl was interrupted, so it's still ours.
+    let created = created || owner;
+    if let (true, Some(entry)) = (created && !owner, entry) {
+        state.store.take_ownership(entry).await?;
+    }
+
+    if !created {
+        debug!(%url, "Already searched, skipping");
+        state.store.lower_depth(node_id, depth).await?;
+        return Ok(Vec::new());
+    }
+
+    if excluded {
+        state.store.mark_excluded(node_id).await?;
+        state.visited.skipped(&url);
+        return Ok(Vec::new());
+    }
+
+    if external {
+        state.store.mark_external(node_id).await?;
+        state.visited.skipped(&url);
+        return Ok(Vec::new());
+    }
+
+    if let Some(ext) = state.extension_filter.as_ref().and_then(|filter| filter.skipped(&parsed_url)) {
+        debug!(%url, ext, "Binary file extension, not fetching");
+        state.visited.skipped(&url);
+        return Ok(Vec::new());
+    }
+
+    if link.nofollow == Some(true) {
+        debug!(%url, "Only linked as nofollow, not fetching");
+        state.visited.skipped(&url);
+        return Ok(Vec::new());
+    }
+
+    if let Some(max_depth) = state.max_depth {
+        if depth > max_depth {
+            info!(%url, depth, "Reached max depth, not fetching");
+            state.visited.skipped(&url);
+            return Ok(Vec::new());
+        }
+    }
+
+    if !on_seed_sites(&state.site_scope, state.same_domain_hosts.as_deref(), &parsed_url) {
+        debug!(%url, hosts = ?state.same_domain_hosts, "Not on the same site as any of the --same-domain hosts, not fetching");
+        state.store.mark_external(node_id).await?;
+        state.visited.skipped(&url);
+        return Ok(Vec::new());
+    }
+
+    if state.page_limit_reached() {
+        debug!(%url, "Reached max pages, not fetching");
+        state.visited.skipped(&url);
+        return Ok(Vec::new());
+    }
+
+    if let Some(host) = parsed_url.host_str().filter(|host| state.domain_limit_reached(host)) {
+        debug!(%url, domain = state.domain_key(host), "Reached max pages for its domain, not fetching");
+        state.visited.skipped(&url);
+        return Ok(Vec::new());
+    }
+
+    // hostnames are only checked once they're resolved, which happens in PublicResolver.
+    if let Some(ip) = address::private_literal(&parsed_url).filter(|_| !state.allow_private) {
+        debug!(%url, %ip, "Private address, not fetching");
+        state.store.mark_private_blocked(node_id).await?;
+        state.visited.skipped(&url);
+        return Ok(Vec::new());
+    }
+
+    if let Some(robots) = &state.robots {
+        match robots.check(&state.clients, &parsed_url).await {
+            Verdict::Allowed { crawl_delay } => {
+                if let (Some(delay), Some(host)) = (crawl_delay, parsed_url.host_str()) {
+                    if state.politeness.set_crawl_delay(host, delay) {
+                        info!(%host, crawl_delay_secs = delay.as_secs_f32(), "Using robots.txt Crawl-delay");
+                    }
+                }
+            }
+            Verdict::Disallowed => {
+                debug!(%url, "Disallowed by robots.txt, not fetching");
+                state.store.mark_robots_blocked(node_id.clone()).await?;
+                state.visited.skipped(&url);
+                return Ok(Vec::new());
+            }
+            Verdict::TooSlow(delay) => {
+                debug!(%url, crawl_delay_secs = delay.as_secs_f32(), "Crawl-delay is over --max-crawl-delay, not fetching");
+                state.visited.skipped(&url);
+                return Ok(Vec::new());
+            }
+        }
+    }
+
+    if let Some(max_bytes) = state.head_precheck {
+        if !head_precheck(&parsed_url, max_bytes, state).await {
+            state.visited.skipped(&url);
+            return Ok(Vec::new());
+        }
+    }
+
+    if let Some(host) = parsed_url.host_str().filter(|host| state.politeness.is_abandoned(host)) {
+        debug!(%url, %host, "Host asked to wait longer than --max-retry-after, not fetching");
+        state.visited.skipped(&url);
+        return Ok(Vec::new());
+    }
+
+    if let Some(host) = parsed_url.host_str().filter(|host| state.host_health.skip_if_benched(host)) {
+        debug!(%url, %host, "Host was benched after repeated errors, not fetching");
+        state.visited.skipped(&url);
+        return Ok(Vec::new());
+    }
+
+    // the slot is taken before the request goes out, so workers racing for the last few can't overshoot --max-pages.
+    if !state.reserve_page() {
+        debug!(%url, "Reached max pages, not fetching");
+        state.visited.skipped(&url);
+        return Ok(Vec::new());
+    }
+
+    // held until the body is read, so a site that dominates the frontier can't take every worker at once.
+    let host_permit = state.host_limits.acquire(parsed_url.host_str().unwrap_or("")).await;
+
+    // marking it right before the request goes out keeps what a crash can lose to the pages actually being fetched.
+    wait_for_host(&parsed_url, state).await;
+    if !state.store.start_fetch(node_id.clone(), state.refresh).await? {
+        state.release_page();
+        if state.refresh.is_some() {
+            // it's still the way to whatever it links to, which might not be as fresh.
+            debug!(%url, "Checked recently enough, following the links it had last time");
+            return known_links(state, node_id, &url, depth).await;
+        }
+
+        debug!(%url, "Already fetched before the crawl was resumed, skipping");
+        return Ok(Vec::new());
+    }
+
+    let validators = if state.refresh.is_some() {
+        state.store.validators(node_id.clone()).await?.filter(Validators::any)
+    } else {
+        None
+    };
+
+    // get content
+    let started = Instant::now();
+    let mut res = match send_request(Method::GET, &parsed_url, validators.as_ref(), state).await {
+        Ok(res) => res,
+        Err(err) if address::is_blocked(err.as_ref()) => {
+            debug!(%url, error = format!("{err:#}"), "Resolved to a private address, not fetching");
+            state.release_page();
+            state.store.mark_private_blocked(node_id).await?;
+            state.visited.skipped(&url);
+            return Ok(Vec::new());
+        }
+        Err(err) => {
+            state.release_page();
+            host_failed(&parsed_url, state);
+            state.record_failure(node_id.clone(), &url, &err).await?;
+            return Err(err);
+        }
+    };
+
+    if let Some(wait) = rate_limited(&res) {
+        state.release_page();
+        let host = parsed_url.host_str().unwrap_or("");
+        if !state.politeness.pause(host, wait) {
+            warn!(%url, %host, retry_after_secs = wait.as_secs(), "Asked to wait longer than --max-retry-after, giving up on the host");
+            let err = anyhow::anyhow!("{host} asked to wait {}s, longer than --max-retry-after", wait.as_secs());
+            state.record_failure(node_id, &url, &err).await?;
+            return Err(err);
+        }
+        if attempts >= state.retries {
+            warn!(%url, %host, attempts, "Still rate limited after --retries tries, giving up on it");
+            let err = anyhow::anyhow!("{host} was still rate limiting it after {} tries", attempts + 1);
+            state.record_failure(node_id, &url, &err).await?;
+            return Err(err);
+        }
+
+        // it goes back in the frontier unfetched, and by the time a worker gets to it again the pause is what it waits on.
+        info!(%url, %host, retry_after_secs = wait.as_secs(), "Rate limited, pausing the host and trying again later");
+        state.store.reset_fetch(node_id).await?;
+        return Ok(vec![FrontierItem {
+            id: None,
+            source,
+            url,
+            depth,
+            link,
+            canonical,
+            redirect,
+            lastmod: obj.lastmod,
+            owner: true,
+            attempts: attempts + 1,
+        }]);
+    }
+
+    // anything the server answered at all counts as the host being up, short of it erroring itself.
+    if res.status().is_server_error() {
+        host_failed(&parsed_url, state);
+    } else if let Some(host) = parsed_url.host_str() {
+        state.host_health.succeeded(host);
+    }
+
+    let fetch_duration = started.elapsed();
+    let fetch_ms = fetch_duration.as_millis() as u64;
+    #[cfg(feature = "metrics")]
+    state.fetch_durations.observe(fetch_duration);
+    info!(%url, status = res.status().as_u16(), duration_ms = fetch_ms, "Fetched page");
+    state.visited.fetched(&url);
+    if let Some(host) = parsed_url.host_str() {
+        state.count_domain_page(host);
+    }
+    if let (Some(host_pages), Some(host)) = (&state.host_pages, parsed_url.host_str()) {
+        *host_pages.lock().unwrap().entry(host.to_owned()).or_default() += 1;
+    }
+
+    // nothing changed since the last crawl, so what it linked to then is what it links to now.
+    if res.status() == StatusCode::NOT_MODIFIED && validators.is_some() {
+        debug!(%url, "Not modified, following the links it had last time");
+        state.store.mark_checked(node_id.clone()).await?;
+        return known_links(state, node_id, &url, depth).await;
+    }
+
+    // reqwest follows redirects on its own, so the content belongs to wherever we ended up.
+    let mut final_url = res.url().clone();
+    state.normalizer.apply(&mut final_url);
+    let final_url = final_url.to_string();
+    if final_url != url {
+        debug!(%url, redirect_url = %final_url, "Redirected");
+        let redirected = FetchInfo {
+            final_url: Some(final_url.clone()),
+            fetch_ms: Some(fetch_ms),
+            ..Default::default()
+        };
+        state.store.record_fetch(node_id.clone(), redirected).await?;
+
+        let link = LinkData {
+            external: state.site_scope.is_external_link(&parsed_url, res.url()),
+            ..Default::default()
+        };
+
+        // the redirect policy only knows about scope, so a target that'd be filtered or blocked as a link is left to be
+        // crawled as one, and what it answered with isn't kept.
+        if !fetchable_redirect(&final_url, state).await? {
+            debug!(url = %final_url, source_url = %url, "Redirect target can't be fetched as it is, crawling it as a link instead");
+            return Ok(vec![FrontierItem {
+                source: Some(node_id),
+                url: final_url,
+                depth,
+                link,
+                redirect: true,
+                ..Default::default()
+            }]);
+        }
+
+        let mut target = SiteURLNode::new(final_url.clone(), depth);
+        let (target_id, created) = state.claim(&target, false).await?;
+        target.id = Some(target_id.clone());
+        state.relate(&state.redirect_table, node_id, target_id, &final_url, &link, depth).await?;
+
+        if !created {
+            debug!(url = %final_url, source_url = %url, "Redirect target was already searched, skipping");
+            return Ok(Vec::new());
+        }
+
+        obj = target;
+    }
+
+    let status = res.status();
+    let content_type = res.headers().get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_owned();
+
+    let header = |name| res.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_owned);
+    let mut fetch = FetchInfo {
+        status: Some(status.as_u16()),
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+        content_type: Some(content_type.clone()).filter(|content_type| !content_type.is_empty()),
+        fetch_ms: Some(fetch_ms),
+        ..Default::default()
+    };
+
+    // one that wasn't followed, because of --max-redirects or because it leaves the crawl, still leads to its Location.
+    if status.is_redirection() {
+        let location = res.headers()
+            .get(LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|location| res.url().join(location).ok());
+        state.store.record_fetch(obj.id.clone().unwrap(), fetch).await?;
+
+        let Some(location) = location else {
+            debug!(url = %obj.url, status = status.as_u16(), "Redirect without a usable Location");
+            return Ok(Vec::new());
+        };
+        debug!(url = %obj.url, redirect_url = %location, "Not following redirect");
+        return Ok(vec![FrontierItem {
+            source: obj.id.clone(),
+            link: LinkData {
+                external: state.site_scope.is_external_link(res.url(), &location),
+                ..Default::default()
+            },
+            url: location.to_string(),
+            depth,
+            redirect: true,
+            ..Default::default()
+        }]);
+    }
+
+    if !status.is_success() && !state.parse_errors {
+        debug!(url = %obj.url, status = status.as_u16(), "Error status, not parsing");
+        state.store.record_fetch(obj.id.clone().unwrap(), fetch).await?;
+        return Ok(Vec::new());
+    }
+    // --parse-errors gets this far with any status, but only a 2xx is a page that was fetched.
+    if status.is_success() {
+        state.pages_fetched.fetch_add(1, Ordering::SeqCst);
+    }
+
+    // a generic type like application/octet-stream could be anything, so those get a look at the body first.
+    let header_text = is_text_content_type(&content_type, &state.content_types);
+    if !header_text && !is_generic_content_type(&content_type) {
+        debug!(%url, %content_type, "Not a text content type, ignoring");
+        state.skipped_content_type.fetch_add(1, Ordering::SeqCst);
+        fetch.type_decided_by = Some("header");
+        state.store.record_fetch(obj.id.clone().unwrap(), fetch).await?;
+        return Ok(Vec::new());
+    }
+
+    if let Some(content_length) = res.content_length().filter(|len| *len > state.max_body_bytes) {
+        debug!(%url, content_length, "Over --max-body-bytes, ignoring");
+        state.store.record_fetch(obj.id.clone().unwrap(), fetch).await?;
+        return Ok(Vec::new());
+    }
+
+    let mut robots = state.meta_robots
+        .as_deref()
+        .map(|agent| {
+            res.headers()
+                .get_all("x-robots-tag")
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .map(|header| extract::robots_from_header(header, agent))
+                .fold(extract::RobotsDirectives::default(), extract::RobotsDirectives::merge)
+        })
+        .unwrap_or_default();
+    fetch.noindex = robots.noindex;
+
+    if robots.nofollow {
+        debug!(%url, "X-Robots-Tag says nofollow, not parsing");
+        state.store.record_fetch(obj.id.clone().unwrap(), fetch).await?;
+        return Ok(Vec::new());
+    }
+
+    let mut base = res.url().clone();
+    let mut canonical = res.headers()
+        .get_all(LINK)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .find_map(extract::canonical_from_link_header);
+
+    // plenty of servers get the Content-Type wrong, so the start of the body gets the final say before the rest is downloaded.
+    let read_started = Instant::now();
+    let mut body = Vec::new();
+    let mut truncated = match read_body(&mut res, &mut body, SNIFF_BYTES, state.max_body_bytes).await {
+        Ok(truncated) => truncated,
+        Err(err) => return Err(read_failed(state, &obj, err).await),
+    };
+
+    let sniffed = content::sniff(&body);
+    let trust_header = header_text && sniffed != Sniffed::Binary;
+    fetch.type_decided_by = Some(if trust_header { "header" } else { "sniff" });
+
+    let parse_as = if trust_header {
+        Some(content_type.as_str())
+    } else {
+        sniffed.content_type().filter(|_| !header_text)
+    };
+
+    let Some(parse_as) = parse_as else {
+        debug!(%url, %content_type, ?sniffed, "Body doesn't look like text, ignoring");
+        state.skipped_content_type.fetch_add(1, Ordering::SeqCst);
+        state.bytes_downloaded.fetch_add(body.len() as u64, Ordering::SeqCst);
+        fetch.content_length = Some(body.len() as u64);
+        state.store.record_fetch(obj.id.clone().unwrap(), fetch).await?;
+        return Ok(Vec::new());
+    };
+
+    if !trust_header {
+        debug!(%url, %content_type, ?sniffed, "Body looks like text, parsing it as {parse_as}");
+    }
+
+    if !truncated {
+        truncated = match read_body(&mut res, &mut body, usize::MAX, state.max_body_bytes).await {
+            Ok(truncated) => truncated,
+            Err(err) => return Err(read_failed(state, &obj, err).await),
+        };
+    }
+
+    if truncated {
+        debug!(%url, bytes_read = body.len(), "Went past --max-body-bytes, only parsing what was read");
+    }
+    debug!(%url, bytes_read = body.len(), truncated, duration_ms = read_started.elapsed().as_millis() as u64, "Read body");
+
+    drop(host_permit);
+    fetch.truncated = truncated;
+    fetch.content_length = Some(body.len() as u64);
+    state.bytes_downloaded.fetch_add(body.len() as u64, Ordering::SeqCst);
+
+    let is_html = is_html_content_type(parse_as);
+    let is_xml = is_xml_content_type(parse_as);
+    let (content, encoding) = decode_body(&body, parse_as);
+    fetch.encoding = Some(encoding.name());
+
+    let hash = content::content_hash(&content);
+    fetch.duplicate_of = state.duplicate_of(&hash, obj.id.as_ref().unwrap());
+    if let Some(storage) = &state.content_storage {
+        match store_content(state, storage, &hash, &content, fetch.duplicate_of.is_some()).await {
+            Ok(path) => fetch.content_path = path,
+            // the page itself was fine, so it still gets parsed.
+            Err(err) => warn!(%url, error = format!("{err:#}"), "Failed to store the page's content"),
+        }
+    }
+    fetch.content_hash = Some(hash);
+    if let Some(original) = &fetch.duplicate_of {
+        state.duplicate_pages.fetch_add(1, Ordering::SeqCst);
+
+        if state.skip_duplicate_content {
+            debug!(%url, %original, "Same content as a page already parsed, not parsing");
+            state.store.record_fetch(obj.id.clone().unwrap(), fetch).await?;
+            return Ok(Vec::new());
+        }
+    }
+
+    let feed_links = is_xml
+        .then(|| extract::parse_feed(&content))
+        .flatten();
+
+    // with an email finder, an html page's text for it to look through.
+    let mut page_text = None;
+
+    // text_links only spots absolute urls written out as text, which misses pretty much every
+    // link on a normal website, so it's only the fallback for content we can't parse.
+    let mut raw_links: Vec<extract::Link> = if let Some(feed_links) = feed_links {
+        feed_links
+            .into_iter()
+            .map(|url| extract::Link {
+                url,
+                nofollow: false,
+                text: None,
+            })
+            .collect()
+    } else if is_html {
+        let page = extract::parse_html(&content, state.meta_robots.as_deref().unwrap_or(""), state.extract_text, state.email_finder.is_some());
+        if state.meta_robots.is_some() {
+            robots = robots.merge(page.robots);
+        }
+
+        if let Some(href) = page.base {
+            match base.join(&href) {
+                Ok(resolved) => base = resolved,
+                Err(err) => debug!(%url, %href, error = %err, "Ignoring invalid <base href>"),
+            }
+        }
+
+        fetch.title = page.title;
+        fetch.text = page.text;
+        page_text = page.all_text;
+        fetch.noindex = robots.noindex;
+        canonical = canonical.or(page.canonical);
+
+        if robots.nofollow {
+            debug!(%url, "Meta robots says nofollow, not following its links");
+            state.store.record_fetch(obj.id.clone().unwrap(), fetch).await?;
+            return Ok(Vec::new());
+        }
+
+        page.links
+    } else {
+        // there's no markup to take out of anything else that's text, except xml that wasn't a feed.
+        if !is_xml {
+            fetch.text = state.extract_text
+                .map(|max_len| content.chars().take(max_len).collect::<String>())
+                .filter(|text| !text.trim().is_empty());
+        }
+
+        extract::text_links(&content)
+    };
+
+    if let Some(finder) = &state.email_finder {
+        // mailto links go to the email table instead, they're never something to fetch.
+        let mut emails = Vec::new();
+        raw_links.retain(|link| match email::mailto_addresses(&link.url) {
+            Some(addresses) => {
+                emails.extend(addresses);
+                false
+            }
+            None => true,
+        });
+
+        // an html page's text came out of the same parse as its links.
+        emails.extend(finder.find(page_text.as_deref().unwrap_or(&content)));
+        record_emails(state, obj.id.clone().unwrap(), emails).await?;
+    }
+
+    let mut found = Vec::new();
+    let page_url = Url::parse(&obj.url)?;
+    let canonical = canonical
+        .and_then(|canonical| base.join(&canonical).ok())
+        .filter(|canonical| canonical.as_str() != obj.url);
+
+    if let Some(canonical) = canonical {
+        let link = LinkData {
+            external: state.site_scope.is_external_link(&page_url, &canonical),
+            ..Default::default()
+        };
+        let canonical = canonical.to_string();
+        debug!(url = %obj.url, %canonical, "Declares a canonical url");
+
+        if state.follow_canonical {
+            // this page stands in for its canonical url, so whichever of them gets here first is the only one parsed.
+            let (canonical_id, created) = state.claim(&SiteURLNode::new(canonical.clone(), depth), false).await?;
+            state.relate(&state.canonical_table, obj.id.clone().unwrap(), canonical_id, &canonical, &link, depth).await?;
+
+            if !created {
+                debug!(url = %obj.url, %canonical, "Canonical url was already searched, not parsing");
+                state.store.record_fetch(obj.id.clone().unwrap(), fetch).await?;
+                return Ok(Vec::new());
+            }
+        } else {
+            found.push(FrontierItem {
+                source: obj.id.clone(),
+                url: canonical,
+                depth: depth + 1,
+                link,
+                canonical: true,
+                ..Default::default()
+            });
+        }
+    }
+
+    // kept in the order they're on the page, so --strategy crawls them in that order too.
+    let mut links: Vec<(String, FoundLink)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let links_found = raw_links.len();
+    for link in raw_links {
+        let mut resolved = match classify_link(&base, &link.url) {
+            LinkTarget::Web(resolved) => resolved,
+            LinkTarget::Other(other) => {
+                state.count_other_scheme(other.scheme());
+                // a data: uri can be a whole image, which makes for a pretty useless node.
+                if !state.record_other_schemes || other.as_str().len() > MAX_OTHER_SCHEME_LEN {
+                    debug!(scheme = other.scheme(), source_url = %obj.url, "Not a http(s) link, skipping");
+                    continue;
+                }
+
+                other
+            }
+            LinkTarget::Invalid(err) => {
+                debug!(href = %link.url, base = %base, source_url = %obj.url, error = %err, "Couldn't resolve link, skipping");
+                continue;
+            }
+        };
+
+        let anchor = resolved.fragment().is_some();
+        state.normalizer.apply(&mut resolved);
+
+        if anchor && resolved.fragment().is_none() && resolved.as_str() == obj.url {
+            // just an anchor within this page
+            continue;
+        }
+
+        let external = state.site_scope.is_external_link(&page_url, &resolved);
+        let resolved = resolved.to_string();
+
+        let position = *positions.entry(resolved.clone()).or_insert_with(|| {
+            links.push((resolved, FoundLink {
+                nofollow: true,
+                text: None,
+                count: 0,
+                external,
+            }));
+            links.len() - 1
+        });
+        let found = &mut links[position].1;
+        found.count += 1;
+        found.nofollow &= state.skip_nofollow && link.nofollow;
+        if found.text.is_none() {
+            found.text = link.text;
+        }
+    }
+
+    debug!(url = %obj.url, links_found, links_unique = links.len(), "Extracted links");
+
+    let mut current = Vec::new();
+    for (link, FoundLink { nofollow, text, count, external }) in links {
+        if nofollow && !state.record_nofollow {
+            debug!(url = %link, source_url = %obj.url, "Skipping nofollow url");
+            continue;
+        }
+
+        if state.prune_stale_edges {
+            current.push(link.clone());
+        }
+
+        debug!(url = %link, source_url = %obj.url, "Found url");
+
+        found.push(FrontierItem {
+            source: obj.id.clone(),
+            url: link,
+            depth: depth + 1,
+            link: LinkData {
+                nofollow: nofollow.then_some(true),
+                text,
+                count: Some(count),
+                external,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+
+    let found = state.record_page(obj.id.clone().unwrap(), &obj.url, Some(fetch), found).await?;
+
+    if state.prune_stale_edges {
+        let pruned = state.store.prune_links(&state.relate_table, obj.id.clone().unwrap(), current).await?;
+        if pruned > 0 {
+            debug!(url = %obj.url, pruned, "Removed relations for links no longer on the page");
+        }
+    }
+
+    Ok(found)
+}
+
+/// A url that made it past [`screen`].
+pub(super) struct Screened {
+    pub(super) url: Url,
+    /// Only being recorded because of `--record-external`.
+    pub(super) external: bool,
+    /// Only being recorded because of `--record-excluded`.
+    pub(super) excluded: bool,
+}
+
+/// Normalizes `url` and runs it past the domain and url filters. Returns `None` if it shouldn't even be recorded.
+pub(super) fn screen(url: &str, seed: bool, state: &AppState) -> anyhow::Result<Option<Screened>> {
+    // helps with comparing string urls
+    let mut parsed_url = Url::parse(url)?;
+    state.normalizer.apply(&mut parsed_url);
+    let url = parsed_url.as_str();
+
+    let external = match state.site_scope.domains.check(parsed_url.host_str()) {
+        Ok(()) => false,
+        Err(rejection) => {
+            debug!(%url, %rejection, "Filtered out");
+
+            if !state.record_external {
+                return Ok(None);
+            }
+
+            true
+        }
+    };
+
+    // seeds are what the user asked for, so they're fetched whatever the patterns say.
+    let excluded = match state.url_filter.check(url) {
+        FilterDecision::Fetch => false,
+        _ if seed => false,
+        decision => {
+            debug!(%url, %decision, "Excluded");
+
+            if !state.record_excluded {
+                return Ok(None);
+            }
+
+            true
+        }
+    };
+
+    Ok(Some(Screened { url: parsed_url, external, excluded }))
+}
+
+/// Saves the addresses found on a page to the email table, relating the page to each once with how often it came up.
+async fn record_emails(state: &AppState, node_id: Thing, emails: Vec<String>) -> anyhow::Result<()> {
+    let mut counts: Vec<(String, u32)> = Vec::new();
+    for address in emails {
+        match counts.iter_mut().find(|(seen, _)| *seen == address) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((address, 1)),
+        }
+    }
+
+    for (address, count) in counts {
+        debug!(url = %node_id, %address, "Found email address");
+
+        let email_id = state.store.save_email(&state.email_table, &address).await?;
+        let link = LinkData {
+            count: Some(count),
+            ..Default::default()
+        };
+        state.store.record_edge(&state.email_relate_table, node_id.clone(), email_id, &link).await?;
+    }
+
+    Ok(())
+}
+
+/// Keeps a page's body where `--store-content` said to, returning the path for bodies kept in files. A `duplicate`
+/// of a body already in the db isn't written again.
+async fn store_content(state: &AppState, storage: &ContentStorage, hash: &str, content: &str, duplicate: bool) -> anyhow::Result<Option<String>> {
+    match storage {
+        ContentStorage::Inline(content_table) => {
+            if !duplicate {
+                state.store.save_content(content_table, hash, content).await?;
+            }
+            Ok(None)
+        }
+        ContentStorage::Dir(dir) => {
+            let path = archive::write_to_dir(dir, hash, content).await?;
+            Ok(Some(path.display().to_string()))
+        }
+    }
+}
+
+/// Queues up what the node linked to when it was last fetched, for pages `--refresh` doesn't parse again.
+async fn known_links(state: &AppState, node_id: Thing, url: &str, depth: u32) -> anyhow::Result<Vec<FrontierItem>> {
+    let found = state.store.outgoing_links(&state.relate_table, node_id.clone())
+        .await?
+        .into_iter()
+        .map(|(url, link)| FrontierItem {
+            source: Some(node_id.clone()),
+            url,
+            depth: depth + 1,
+            link,
+            ..Default::default()
+        })
+        .collect();
+
+    state.record_page(node_id, url, None, found).await
+}
+
+/// Reads more of the body onto `body`, until it's at least `until` bytes or the body ends.
+///
+/// It never goes past `max_bytes` in total, so something that never stops streaming can't eat all our memory.
+/// Returns whether the body got cut off there.
+async fn read_body(res: &mut Response, body: &mut Vec<u8>, until: usize, max_bytes: u64) -> reqwest::Result<bool> {
+    let max_bytes = max_bytes as usize;
+
+    while body.len() < until {
+        let Some(chunk) = res.chunk().await? else {
+            break;
+        };
+
+        let room = max_bytes - body.len();
+        if chunk.len() > room {
+            body.extend_from_slice(&chunk[..room]);
+            return Ok(true);
+        }
+
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(false)
+}
+
+/// Records that the body of `node` couldn't be read, and hands back the error to bail with.
+async fn read_failed(state: &AppState, node: &SiteURLNode, err: reqwest::Error) -> anyhow::Error {
+    let err = anyhow::Error::new(err).context(format!("Failed to read the body of {:?}", node.url));
+
+    match state.record_failure(node.id.clone().unwrap(), &node.url, &err).await {
+        Ok(()) => err,
+        Err(db_err) => db_err,
+    }
+}
+
+/// Every link on a page pointing at the same url, rolled into one.
+struct FoundLink {
+    /// Only true if every one of them was nofollow.
+    nofollow: bool,
+    text: Option<String>,
+    count: u32,
+    external: bool,
+}
+
+/// Queues every url listed by a sitemap as a seed, following sitemap indexes. Returns how many were queued.
+pub(super) async fn seed_from_sitemap(sitemap_url: &str, state: &AppState) -> usize {
+    // indexes can point at more indexes, so don't let a loop keep us here forever.
+    const MAX_SITEMAPS: usize = 1000;
+
+    let mut pending = vec![sitemap_url.to_owned()];
+    let mut visited = 0;
+    let mut seeded = 0;
+
+    while let Some(sitemap_url) = pending.pop() {
+        visited += 1;
+        if visited > MAX_SITEMAPS {
+            error!("Stopped reading sitemaps after {MAX_SITEMAPS}");
+            break;
+        }
+
+        let result = async {
+            let url = Url::parse(&sitemap_url)?;
+            let body = fetch_page(Method::GET, &url, state).await?
+                .error_for_status()?
+                .bytes()
+                .await?;
+
+            sitemap::parse_sitemap(&body)
+        }
+        .await;
+
+        match result {
+            Ok(Sitemap::Urls(entries)) => {
+                debug!("Sitemap {sitemap_url:?} lists {} urls", entries.len());
+                let count = entries.len();
+
+                let items = entries
+                    .into_iter()
+                    .map(|entry| FrontierItem {
+                        url: entry.loc,
+                        lastmod: entry.lastmod,
+                        ..Default::default()
+                    })
+                    .collect();
+
+                match state.enqueue(items, None).await {
+                    Ok(()) => seeded += count,
+                    Err(err) => error!("Failed to queue the urls from sitemap {sitemap_url:?}: {err:?}"),
+                }
+            }
+            Ok(Sitemap::Index(sitemaps)) => {
+                debug!("Sitemap index {sitemap_url:?} lists {} sitemaps", sitemaps.len());
+                pending.extend(sitemaps);
+            }
+            Err(err) => error!("Failed to read sitemap {sitemap_url:?}: {err:#}"),
+        }
+    }
+
+    seeded
+}
+
+/// The product name of a User-Agent string (`findconn/0.1.0 (...)` -> `findconn`), which is what robots.txt groups name.
+pub(super) fn robots_agent(user_agent: &str) -> &str {
+    user_agent
+        .split(|c: char| c == '/' || c.is_whitespace())
+        .next()
+        .unwrap_or(user_agent)
+}
+
+/// Whether a HEAD request says `url` is worth a GET. Anything inconclusive falls back to fetching.
+async fn head_precheck(url: &Url, max_bytes: u64, state: &AppState) -> bool {
+    let res = match fetch_page(Method::HEAD, url, state).await {
+        Ok(res) if res.status() != StatusCode::METHOD_NOT_ALLOWED => res,
+        Ok(_) => return true,
+        Err(err) => {
+            debug!("HEAD precheck for {:?} failed, falling back to GET: {err:#}", url.as_str());
+            return true;
+        }
+    };
+
+    if let Some(content_type) = res.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+        if !is_text_content_type(content_type, &state.content_types) && !is_generic_content_type(content_type) {
+            debug!("Url {:?} is content-type {content_type:?} according to HEAD, not fetching", url.as_str());
+            return false;
+        }
+    }
+
+    // `Response::content_length` is the body we got, which is always empty for HEAD.
+    let content_length = res.headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+
+    if let Some(length) = content_length {
+        if length > max_bytes {
+            debug!("Url {:?} is {length} bytes according to HEAD, not fetching", url.as_str());
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Requests `url`, retrying connection errors, timeouts, and 5xx responses with exponential backoff.
+async fn fetch_page(method: Method, url: &Url, state: &AppState) -> anyhow::Result<Response> {
+    wait_for_host(url, state).await;
+    send_request(method, url, None, state).await
+}
+
+/// Waits until `--delay-ms` or the host's `Crawl-delay` allows another request to `url`'s host, and then for `--rate`.
+async fn wait_for_host(url: &Url, state: &AppState) {
+    if let Some(host) = url.host_str() {
+        state.host_health.wait(host).await;
+        state.politeness.wait(host).await;
+    }
+
+    if let Some(rate_limit) = &state.rate_limit {
+        rate_limit.wait().await;
+    }
+}
+
+/// Counts a failed fetch against `url`'s host, which might put it on a cooldown or bench it.
+fn host_failed(url: &Url, state: &AppState) {
+    let Some(host) = url.host_str() else {
+        return;
+    };
+
+    match state.host_health.failed(host) {
+        HostFailure::Counted => {}
+        HostFailure::CoolDown(cooldown) => {
+            debug!(%host, cooldown_secs = cooldown.as_secs(), "Host keeps failing, cooling down before the next request");
+        }
+        HostFailure::Benched => warn!(%host, "Host failed too many times in a row, skipping the rest of its urls"),
+    }
+}
+
+/// Whether a redirect that was followed to `url` ended up somewhere that would have been fetched if it had been linked to:
+/// it gets past [`screen`] without being recorded as external or excluded, and isn't skipped for its extension, for
+/// being at a private address, or by robots.txt.
+async fn fetchable_redirect(url: &str, state: &AppState) -> anyhow::Result<bool> {
+    let Some(Screened { url, external: false, excluded: false }) = screen(url, false, state)? else {
+        return Ok(false);
+    };
+
+    if state.extension_filter.as_ref().is_some_and(|filter| filter.skipped(&url).is_some()) {
+        return Ok(false);
+    }
+    if !state.allow_private && address::private_literal(&url).is_some() {
+        return Ok(false);
+    }
+    if let Some(robots) = &state.robots {
+        if !matches!(robots.check(&state.clients, &url).await, Verdict::Allowed { .. }) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// How long a 429, or a 503 with a `Retry-After`, asks to wait before the host is requested again.
+fn rate_limited(res: &Response) -> Option<Duration> {
+    // a 429 without one still means slow down, so it gets a guess.
+    const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+    match res.status() {
+        StatusCode::TOO_MANY_REQUESTS => Some(retry_after(res).unwrap_or(DEFAULT_RETRY_AFTER)),
+        StatusCode::SERVICE_UNAVAILABLE => retry_after(res),
+        _ => None,
+    }
+}
+
+/// The wait a `Retry-After` header gives, either as a number of seconds or as an HTTP date.
+fn retry_after(res: &Response) -> Option<Duration> {
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    // HTTP dates are always GMT, which RFC 2822 parsing takes as +0000. one that's already passed means no wait.
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.to_utc() - chrono::Utc::now()).to_std().unwrap_or_default())
+}
+
+/// Same as [`fetch_page`], for when the caller already waited for the host with [`wait_for_host`].
+///
+/// `validators` from an earlier fetch make it a conditional request, which can come back as 304 Not Modified.
+async fn send_request(method: Method, url: &Url, validators: Option<&Validators>, state: &AppState) -> anyhow::Result<Response> {
+    const BASE_BACKOFF: Duration = Duration::from_millis(500);
+    const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+    let mut attempt = 0;
+    loop {
+        // reqwest strips the Authorization header itself when a redirect leaves the original host,
+        // so credentials can't leak to a third party this way.
+        let (proxy, client) = state.clients.pick();
+        let req = client.request(method.clone(), url.as_str());
+        let req = match &state.auth {
+            Some(Auth::Basic { user, pass }) => req.basic_auth(user, pass.as_ref()),
+            Some(Auth::Bearer(token)) => req.bearer_auth(token),
+            None => req,
+        };
+        let req = match validators {
+            Some(Validators { etag, last_modified }) => {
+                let req = match etag {
+                    Some(etag) => req.header(IF_NONE_MATCH, etag),
+                    None => req,
+                };
+
+                match last_modified {
+                    Some(last_modified) => req.header(IF_MODIFIED_SINCE, last_modified),
+                    None => req,
+                }
+            }
+            None => req,
+        }
+        .build()?;
+
+        state.requests.fetch_add(1, Ordering::SeqCst);
+        let started = Instant::now();
+        #[cfg(feature = "metrics")]
+        state.in_flight.fetch_add(1, Ordering::SeqCst);
+        let result = client.execute(req).await;
+        #[cfg(feature = "metrics")]
+        state.in_flight.fetch_sub(1, Ordering::SeqCst);
+        let elapsed = started.elapsed();
+        state.clients.report(proxy, &result);
+
+        let retryable = match &result {
+            // a 503 saying when to come back is waited out by pausing the whole host instead.
+            Ok(res) => res.status().is_server_error() && rate_limited(res).is_none(),
+            Err(err) => (err.is_connect() || err.is_timeout()) && !address::is_blocked(err),
+        };
+
+        if !retryable || attempt >= state.retries {
+            return result.with_context(|| format!("Failed to fetch {:?} after {}ms", url.as_str(), elapsed.as_millis()));
+        }
+
+        let backoff = BASE_BACKOFF
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_BACKOFF);
+        let jitter = backoff.mul_f64(fastrand::f64());
+
+        match &result {
+            Ok(res) => debug!("Got {} from {:?}, retrying in {:?}", res.status(), url.as_str(), backoff + jitter),
+            Err(err) => debug!("Failed to fetch {:?} ({err}), retrying in {:?}", url.as_str(), backoff + jitter),
+        }
+
+        tokio::time::sleep(backoff + jitter).await;
+        wait_for_host(url, state).await;
+        attempt += 1;
+    }
+}
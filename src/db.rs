@@ -0,0 +1,705 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::Context;
+use serde::Deserialize;
+use surrealdb::{engine::any::{self, Any}, error::Db as DbError, opt::auth::Root, sql::{Duration as SurrealDuration, Thing}, Surreal};
+
+use crate::{
+    frontier::FrontierItem,
+    model::{FetchInfo, LinkData, PageLink, Record, Relation, RunOutcome, SiteURLNode, Validators},
+    summary::Summary,
+};
+
+/// Keeps the relations `$external` asks for, or all of them when it's NONE.
+/// Relations from before `external` was recorded count as internal.
+pub const LINK_SCOPE: &str = "($external IS NONE OR (external = true) = $external)";
+
+/// Whether `output` is the url of a SurrealDB server rather than a directory.
+pub fn is_remote(output: &str) -> bool {
+    const REMOTE_SCHEMES: [&str; 4] = ["ws://", "wss://", "http://", "https://"];
+
+    REMOTE_SCHEMES.iter().any(|scheme| output.starts_with(scheme))
+}
+
+/// Connects to a SurrealDB server if `output` is a ws/http url, and otherwise opens (or creates) a SurrealKV directory there.
+pub async fn connect(output: &str, credentials: Option<(String, String)>) -> anyhow::Result<Surreal<Any>> {
+    if !is_remote(output) {
+        return Ok(any::connect(format!("surrealkv://{output}")).await?);
+    }
+
+    let db = any::connect(output)
+        .await
+        .with_context(|| format!("Failed to connect to SurrealDB at {output:?}"))?;
+
+    if let Some((username, password)) = credentials {
+        db.signin(Root {
+            username: &username,
+            password: &password,
+        })
+        .await
+        .with_context(|| format!("Failed to sign in to {output:?} as {username:?}"))?;
+    }
+
+    Ok(db)
+}
+
+#[derive(Deserialize)]
+struct NamespaceInfo {
+    databases: BTreeMap<String, String>,
+}
+
+/// Switches to the database of a previous crawl, making sure it's actually there instead of quietly reading an empty one.
+/// With no `name`, the namespace's only database is used.
+pub async fn use_existing(db: &Surreal<Any>, ns: &str, name: Option<String>) -> anyhow::Result<()> {
+    db.use_ns(ns).await?;
+
+    let info: Option<NamespaceInfo> = db.query("INFO FOR NS").await?.take(0)?;
+    let databases: Vec<String> = info.map(|info| info.databases.into_keys().collect()).unwrap_or_default();
+    let found = || databases.iter().map(|name| format!("{name:?}")).collect::<Vec<_>>().join(", ");
+
+    let name = match name {
+        Some(name) => name,
+        None => match databases.as_slice() {
+            [only] => only.clone(),
+            [] => anyhow::bail!("Namespace {ns:?} doesn't have any databases. Is --output the right directory?"),
+            _ => anyhow::bail!("Namespace {ns:?} has more than one database, pick one with --db or --url: {}", found()),
+        },
+    };
+
+    if !databases.contains(&name) {
+        anyhow::bail!("There's no database named {name:?} in namespace {ns:?}. Found: {}", found());
+    }
+
+    db.use_db(name).await?;
+    Ok(())
+}
+
+/// An in-memory database for throwaway crawls.
+pub async fn connect_memory() -> anyhow::Result<Surreal<Any>> {
+    Ok(any::connect("mem://").await?)
+}
+
+/// The types of the fields written onto nodes. Nearly everything is optional, since nodes only get most of
+/// them once they're fetched, and nodes from older runs might not have them at all.
+const NODE_FIELDS: &[(&str, &str)] = &[
+    ("url", "string"),
+    ("depth", "option<int>"),
+    ("lastmod", "option<string>"),
+    ("final_url", "option<string>"),
+    ("first_seen_run", "option<record>"),
+    ("content_hash", "option<string>"),
+    ("duplicate_of", "option<record>"),
+    ("content_path", "option<string>"),
+    ("text", "option<string>"),
+    ("robots_blocked", "option<bool>"),
+    ("private_blocked", "option<bool>"),
+    ("excluded", "option<bool>"),
+    ("external", "option<bool>"),
+    ("status", "option<int>"),
+    ("title", "option<string>"),
+    ("content_type", "option<string>"),
+    ("content_length", "option<int>"),
+    ("fetch_ms", "option<int>"),
+    ("truncated", "option<bool>"),
+    ("noindex", "option<bool>"),
+    ("type_decided_by", "option<string>"),
+    ("encoding", "option<string>"),
+    ("etag", "option<string>"),
+    ("last_modified", "option<string>"),
+    ("error", "option<string>"),
+    ("error_kind", "option<string>"),
+    ("failed_at", "option<datetime>"),
+    ("fetched_at", "option<datetime>"),
+    ("last_checked_at", "option<datetime>"),
+];
+
+/// Same as [`NODE_FIELDS`], for relations.
+const RELATION_FIELDS: &[(&str, &str)] = &[
+    ("nofollow", "option<bool>"),
+    ("text", "option<string>"),
+    ("count", "option<int>"),
+    ("external", "option<bool>"),
+    ("run", "option<record>"),
+];
+
+/// Defines the node table's fields and indexes, unless they already are. Most importantly, `url` is unique
+/// across `table`, which is what lets [`claim_node`] be atomic.
+pub async fn define_schema(db: &Surreal<Any>, table: &str) -> anyhow::Result<()> {
+    db.query(format!("DEFINE INDEX IF NOT EXISTS {table}_url ON TABLE {table} FIELDS url UNIQUE"))
+        .await?
+        .check()
+        .with_context(|| format!("Failed to define a unique url index on {table:?} (does it already contain duplicate urls?)"))?;
+
+    // set by the db so every worker agrees on the clock.
+    db.query(format!("DEFINE FIELD IF NOT EXISTS discovered_at ON TABLE {table} TYPE option<datetime> DEFAULT time::now()"))
+        .await?
+        .check()?;
+
+    db.query(define_fields(table, NODE_FIELDS))
+        .query(format!("DEFINE INDEX IF NOT EXISTS {table}_status ON TABLE {table} FIELDS status"))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// Defines a full-text index over the nodes' `text` unless there already is one, so it can be searched with
+/// `WHERE text @@ 'keyword'`. Defining it indexes everything already in `table`, which is much quicker than keeping it
+/// up to date page by page while crawling.
+pub async fn define_text_index(db: &Surreal<Any>, table: &str) -> anyhow::Result<()> {
+    db.query("DEFINE ANALYZER IF NOT EXISTS page_text TOKENIZERS blank, class, punct FILTERS lowercase, ascii, snowball(english)")
+        .query(format!("DEFINE INDEX IF NOT EXISTS {table}_text ON TABLE {table} FIELDS text SEARCH ANALYZER page_text BM25 HIGHLIGHTS"))
+        .await?
+        .check()
+        .with_context(|| format!("Failed to define a full-text index on {table:?}"))?;
+
+    Ok(())
+}
+
+fn define_fields(table: &str, fields: &[(&str, &str)]) -> String {
+    fields
+        .iter()
+        .map(|(field, kind)| format!("DEFINE FIELD IF NOT EXISTS {field} ON TABLE {table} TYPE {kind};"))
+        .collect()
+}
+
+/// Saves a page's body in `content_table`, under its hash so identical bodies are only stored once.
+pub async fn save_content(db: &Surreal<Any>, content_table: &str, hash: &str, content: &str) -> anyhow::Result<()> {
+    db.query("UPSERT type::thing($table, $hash) SET body = $body RETURN NONE")
+        .bind(("table", content_table.to_owned()))
+        .bind(("hash", hash.to_owned()))
+        .bind(("body", content.to_owned()))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// Adds `address` to the email table unless it's already there. The address is the record's id, so this can't
+/// make duplicates. Returns the record's id.
+pub async fn save_email(db: &Surreal<Any>, email_table: &str, address: &str) -> anyhow::Result<Thing> {
+    let record: Option<Record> = db
+        .query("UPSERT type::thing($table, $address) SET address = $address RETURN id")
+        .bind(("table", email_table.to_owned()))
+        .bind(("address", address.to_owned()))
+        .await?
+        .take(0)?;
+
+    record
+        .map(|record| record.id)
+        .with_context(|| format!("Failed to save email address {address:?}"))
+}
+
+/// Defines the fields of a relate table, and makes sure there's only one relation from any node to another in it.
+/// That's what keeps [`relate`] from adding a second one when two workers relate the same pair at once.
+pub async fn define_relation_schema(db: &Surreal<Any>, relate_table: &str) -> anyhow::Result<()> {
+    db.query(format!("DEFINE INDEX IF NOT EXISTS {relate_table}_pair ON TABLE {relate_table} FIELDS in, out UNIQUE"))
+        .await?
+        .check()
+        .with_context(|| format!("Failed to define a unique index on {relate_table:?} (does it already contain duplicate relations?)"))?;
+
+    db.query(define_fields(relate_table, RELATION_FIELDS))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// Creates the node unless another one already has its url. Returns the node's id, and whether this call created it.
+pub async fn claim_node(db: &Surreal<Any>, table: &str, node: &SiteURLNode) -> anyhow::Result<(Thing, bool)> {
+    let created: Result<Option<Record>, _> = db
+        .create(table)
+        .content(node.clone())
+        .await;
+
+    match created {
+        Ok(record) => Ok((record.unwrap().id, true)),
+        Err(surrealdb::Error::Db(DbError::IndexExists { thing, .. })) => Ok((thing, false)),
+        // remote engines only hand back the error message, so go see whether that's what it was.
+        Err(err) => match find_node(db, table, &node.url).await? {
+            Some(id) => Ok((id, false)),
+            None => Err(err.into()),
+        },
+    }
+}
+
+async fn find_node(db: &Surreal<Any>, table: &str, url: &str) -> anyhow::Result<Option<Thing>> {
+    let mut res = db
+        .query("SELECT id FROM type::table($table) WHERE url = $url LIMIT 1")
+        .bind(("url", url.to_owned()))
+        .bind(("table", table.to_owned()))
+        .await?;
+
+    let findings: Vec<Record> = res.take(0)?;
+    Ok(findings.into_iter().next().map(|record| record.id))
+}
+
+#[derive(Deserialize)]
+struct Inserted {
+    id: Thing,
+    /// The record's id from before the insert, so `None` if it created the record.
+    existed: Option<Thing>,
+    /// The whole relation, for relations.
+    #[serde(default)]
+    relation: Option<Relation>,
+}
+
+/// A relation written by [`record_page`] that didn't exist before.
+pub struct CreatedRelation<'a> {
+    pub relate_table: &'a str,
+    pub id: Thing,
+    pub relation: Relation,
+}
+
+/// Writes everything a page led to in one transaction: its `fetch` info, a node for each of `nodes` (or a lower
+/// depth, for ones that already exist), and its relations to them, grouped by relate table.
+///
+/// Returns each node's id and whether this created it, in the same order as `nodes`, along with the relations
+/// that were created. Relations that already exist are updated instead, like [`relate`] does.
+pub async fn record_page<'a>(
+    db: &Surreal<Any>,
+    table: &str,
+    page: Thing,
+    fetch: Option<FetchInfo>,
+    nodes: Vec<SiteURLNode>,
+    links: Vec<(&'a str, Vec<PageLink>)>,
+) -> anyhow::Result<(Vec<(Thing, bool)>, Vec<CreatedRelation<'a>>)> {
+    let mut query = String::from("BEGIN TRANSACTION;");
+    let mut statements = 0;
+
+    if fetch.is_some() {
+        query += "UPDATE $page MERGE $fetch RETURN NONE; UPDATE $page SET last_checked_at = time::now() RETURN NONE;";
+        statements += 2;
+    }
+
+    let nodes_statement = (!nodes.is_empty()).then(|| {
+        query += &format!(
+            "INSERT INTO {table} $nodes ON DUPLICATE KEY UPDATE depth = math::min([depth ?? $input.depth, $input.depth]) \
+                RETURN id, $before.id AS existed;"
+        );
+        statements += 1;
+        statements - 1
+    });
+
+    let mut relation_statements = Vec::new();
+    for (i, (relate_table, links)) in links.iter().enumerate() {
+        if links.is_empty() {
+            continue;
+        }
+
+        query += &format!(
+            "INSERT RELATION INTO {relate_table} (SELECT *, $page AS in, \
+                (SELECT VALUE id FROM ONLY type::table($table) WHERE url = $parent.url LIMIT 1) AS out OMIT url FROM $links{i}) \
+                ON DUPLICATE KEY UPDATE nofollow = $input.nofollow, text = $input.text, count = $input.count, external = $input.external \
+                RETURN id, $before.id AS existed, $after AS relation;"
+        );
+        relation_statements.push((*relate_table, statements));
+        statements += 1;
+    }
+    query += "COMMIT TRANSACTION;";
+
+    let mut request = db
+        .query(query)
+        .bind(("table", table.to_owned()))
+        .bind(("page", page))
+        .bind(("fetch", fetch))
+        .bind(("nodes", nodes));
+    for (i, (_, links)) in links.into_iter().enumerate() {
+        request = request.bind((format!("links{i}"), links));
+    }
+    let mut res = request.await?;
+
+    let claimed = match nodes_statement {
+        Some(statement) => {
+            let inserted: Vec<Inserted> = res.take(statement)?;
+            inserted.into_iter().map(|node| (node.id, node.existed.is_none())).collect()
+        }
+        None => Vec::new(),
+    };
+
+    let mut created = Vec::new();
+    for (relate_table, statement) in relation_statements {
+        let inserted: Vec<Inserted> = res.take(statement)?;
+        created.extend(inserted.into_iter().filter(|inserted| inserted.existed.is_none()).filter_map(|inserted| {
+            Some(CreatedRelation {
+                relate_table,
+                id: inserted.id,
+                relation: inserted.relation?,
+            })
+        }));
+    }
+
+    Ok((claimed, created))
+}
+
+/// The url of the node `id`.
+pub async fn node_url(db: &Surreal<Any>, id: Thing) -> anyhow::Result<Option<String>> {
+    let url = db
+        .query("SELECT VALUE url FROM ONLY $id")
+        .bind(("id", id))
+        .await?
+        .take(0)?;
+
+    Ok(url)
+}
+
+/// Lowers the node's depth if `depth` is a shorter path to it, or sets it if the node doesn't have one.
+pub async fn lower_depth(db: &Surreal<Any>, id: Thing, depth: u32) -> anyhow::Result<()> {
+    db.query("UPDATE $id SET depth = $depth WHERE depth IS NONE OR depth > $depth")
+        .bind(("id", id))
+        .bind(("depth", depth))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+pub async fn record_fetch(db: &Surreal<Any>, id: Thing, info: FetchInfo) -> anyhow::Result<()> {
+    db.query("UPDATE $id MERGE $info; UPDATE $id SET last_checked_at = time::now()")
+        .bind(("id", id))
+        .bind(("info", info))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// Marks the node's fetch as started, unless that already happened (like in a run before a `--resume`).
+/// With `refresh`, it can still be fetched again if it was last checked longer ago than that.
+/// Returns whether it's ok to go ahead and fetch it.
+pub async fn start_fetch(db: &Surreal<Any>, id: Thing, refresh: Option<Duration>) -> anyhow::Result<bool> {
+    let started: Vec<Record> = db
+        .query("UPDATE $id SET fetched_at = time::now() WHERE fetched_at IS NONE OR ($refresh AND (last_checked_at ?? fetched_at) <= time::now() - $older_than) RETURN id")
+        .bind(("id", id))
+        .bind(("refresh", refresh.is_some()))
+        .bind(("older_than", SurrealDuration::from(refresh.unwrap_or_default())))
+        .await?
+        .take(0)?;
+
+    Ok(!started.is_empty())
+}
+
+/// Undoes [`start_fetch`] for a page that's going back in the frontier to be fetched later.
+pub async fn reset_fetch(db: &Surreal<Any>, id: Thing) -> anyhow::Result<()> {
+    db.query("UPDATE $id SET fetched_at = NONE")
+        .bind(("id", id))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// The node's validators from when it was last fetched.
+pub async fn validators(db: &Surreal<Any>, id: Thing) -> anyhow::Result<Option<Validators>> {
+    let validators = db
+        .query("SELECT etag, last_modified FROM ONLY $id")
+        .bind(("id", id))
+        .await?
+        .take(0)?;
+
+    Ok(validators)
+}
+
+/// Bumps `last_checked_at` for a page that responded 304, leaving the rest of it as it was.
+pub async fn mark_checked(db: &Surreal<Any>, id: Thing) -> anyhow::Result<()> {
+    db.query("UPDATE $id SET last_checked_at = time::now()")
+        .bind(("id", id))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// The urls the node links to, with what was recorded about each link.
+///
+/// This follows the node's own graph edges instead of scanning `relate_table`.
+pub async fn outgoing_links(db: &Surreal<Any>, relate_table: &str, id: Thing) -> anyhow::Result<Vec<(String, LinkData)>> {
+    #[derive(Deserialize)]
+    struct OutgoingLink {
+        url: String,
+        #[serde(flatten)]
+        link: LinkData,
+    }
+
+    let links: Vec<OutgoingLink> = db
+        .query(format!("SELECT out.url AS url, nofollow, text, count FROM $id->{relate_table}"))
+        .bind(("id", id))
+        .await?
+        .take(0)?;
+
+    Ok(links.into_iter().map(|OutgoingLink { url, link }| (url, link)).collect())
+}
+
+/// Deletes the node's relations to anything not in `urls`, returning how many went.
+pub async fn prune_links(db: &Surreal<Any>, relate_table: &str, id: Thing, urls: Vec<String>) -> anyhow::Result<usize> {
+    let pruned: Vec<Record> = db
+        .query(format!("DELETE (SELECT VALUE id FROM $id->{relate_table} WHERE out.url NOTINSIDE $urls) RETURN BEFORE"))
+        .bind(("id", id))
+        .bind(("urls", urls))
+        .await?
+        .take(0)?;
+
+    Ok(pruned.len())
+}
+
+/// Marks the node as not fetched because it's off the domains being crawled.
+pub async fn mark_external(db: &Surreal<Any>, id: Thing) -> anyhow::Result<()> {
+    db.query("UPDATE $id SET external = true")
+        .bind(("id", id))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// Marks the node as not fetched because it matched `--exclude`.
+pub async fn mark_excluded(db: &Surreal<Any>, id: Thing) -> anyhow::Result<()> {
+    db.query("UPDATE $id SET excluded = true")
+        .bind(("id", id))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// Marks the node as not fetched because it's at a private address.
+pub async fn mark_private_blocked(db: &Surreal<Any>, id: Thing) -> anyhow::Result<()> {
+    db.query("UPDATE $id SET private_blocked = true")
+        .bind(("id", id))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// Marks the node as not fetched because robots.txt disallows it.
+pub async fn mark_robots_blocked(db: &Surreal<Any>, id: Thing) -> anyhow::Result<()> {
+    db.query("UPDATE $id SET robots_blocked = true")
+        .bind(("id", id))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// Marks the node as failed, so failures can be queried (and retried) later.
+pub async fn record_failure(db: &Surreal<Any>, id: Thing, err: &anyhow::Error) -> anyhow::Result<()> {
+    const MAX_ERROR_LEN: usize = 1024;
+
+    let error: String = format!("{err:#}").chars().take(MAX_ERROR_LEN).collect();
+
+    db.query("UPDATE $id SET error = $error, error_kind = $kind, failed_at = time::now()")
+        .bind(("id", id))
+        .bind(("error", error))
+        .bind(("kind", error_kind(err)))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// Buckets an error by the first reqwest error in its chain.
+pub fn error_kind(err: &anyhow::Error) -> &'static str {
+    let Some(reqwest_err) = err.chain().find_map(|cause| cause.downcast_ref::<reqwest::Error>()) else {
+        return "http";
+    };
+
+    if reqwest_err.is_timeout() {
+        "timeout"
+    } else if reqwest_err.is_connect() {
+        // hyper doesn't expose these as types, so go by the messages further down the chain.
+        let causes = err.chain().map(|cause| cause.to_string().to_lowercase()).collect::<Vec<_>>().join(": ");
+
+        if causes.contains("dns") || causes.contains("lookup") {
+            "dns"
+        } else if causes.contains("certificate") || causes.contains("tls") || causes.contains("ssl") || causes.contains("handshake") {
+            "tls"
+        } else {
+            "connect"
+        }
+    } else if reqwest_err.is_body() || reqwest_err.is_decode() {
+        "body"
+    } else {
+        "http"
+    }
+}
+
+/// Relates `from` to `to`, or if they already are, updates that relation with `data` instead.
+/// Returns the relation's id if one was created.
+pub async fn relate(db: &Surreal<Any>, relate_table: &str, from: Thing, to: Thing, data: &LinkData) -> anyhow::Result<Option<Thing>> {
+    if update_relation(db, relate_table, from.clone(), to.clone(), data).await? {
+        return Ok(None);
+    }
+
+    let res = db
+        .query(format!("RELATE $sourceid->{relate_table}->$currentid CONTENT $data RETURN id"))
+        .bind(("sourceid", from.clone()))
+        .bind(("currentid", to.clone()))
+        .bind(("data", data.clone()))
+        .await;
+    let created: Result<Option<Record>, _> = match res {
+        Ok(mut res) => res.take(0),
+        Err(err) => Err(err),
+    };
+
+    match created {
+        Ok(relation) => Ok(relation.map(|relation| relation.id)),
+        // the unique index stopped us, because another worker related them in the meantime.
+        Err(err) => match update_relation(db, relate_table, from, to, data).await? {
+            true => Ok(None),
+            false => Err(err.into()),
+        },
+    }
+}
+
+/// Updates the relation from `from` to `to` with `data`, other than its run. Returns whether there was one.
+async fn update_relation(db: &Surreal<Any>, relate_table: &str, from: Thing, to: Thing, data: &LinkData) -> anyhow::Result<bool> {
+    // the relation stays with the run that created it, and a `None` isn't written at all.
+    let data = LinkData {
+        run: None,
+        ..data.clone()
+    };
+
+    let updated: Vec<Relation> = db
+        .query(format!("UPDATE (SELECT VALUE id FROM $sourceid->{relate_table} WHERE out = $currentid) MERGE $data"))
+        .bind(("sourceid", from))
+        .bind(("currentid", to))
+        .bind(("data", data))
+        .await?
+        .take(0)?;
+
+    Ok(!updated.is_empty())
+}
+
+/// Writes `items` to the frontier table and takes `done` off it in one transaction, so a crash can't lose
+/// the urls an item led to once it's done. Returns the items as written, ids included.
+pub async fn enqueue(db: &Surreal<Any>, frontier_table: &str, items: Vec<FrontierItem>, done: Option<Thing>) -> anyhow::Result<Vec<FrontierItem>> {
+    let items = db
+        .query(format!("BEGIN TRANSACTION; INSERT INTO {frontier_table} $items; DELETE $done; COMMIT TRANSACTION;"))
+        .bind(("items", items))
+        .bind(("done", Vec::from_iter(done)))
+        .await?
+        .take(0)?;
+
+    Ok(items)
+}
+
+/// Marks the frontier entry as the one that created its url's node, so a resumed crawl still fetches it.
+pub async fn take_ownership(db: &Surreal<Any>, entry: Thing) -> anyhow::Result<()> {
+    db.query("UPDATE $entry SET owner = true")
+        .bind(("entry", entry))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// Everything left in the frontier table, shallowest first.
+pub async fn load_frontier(db: &Surreal<Any>, frontier_table: &str) -> anyhow::Result<Vec<FrontierItem>> {
+    let items = db
+        .query("SELECT * FROM type::table($table) ORDER BY depth")
+        .bind(("table", frontier_table.to_owned()))
+        .await?
+        .take(0)?;
+
+    Ok(items)
+}
+
+/// How many urls are still waiting in the frontier table.
+pub async fn frontier_len(db: &Surreal<Any>, frontier_table: &str) -> anyhow::Result<usize> {
+    let count: Option<usize> = db
+        .query("RETURN count(SELECT VALUE id FROM type::table($table))")
+        .bind(("table", frontier_table.to_owned()))
+        .await?
+        .take(0)?;
+
+    Ok(count.unwrap_or(0))
+}
+
+/// Where each crawl into a db records how it was run, so the db keeps a history of how it was made.
+pub const RUNS_TABLE: &str = "runs";
+
+/// Records a crawl starting, with the tool `version` and `settings` it was run with. Returns the run's id.
+pub async fn start_run(db: &Surreal<Any>, version: &str, seeds: Vec<String>, settings: serde_json::Value) -> anyhow::Result<Thing> {
+    let record: Option<Record> = db
+        .query("CREATE type::table($table) SET version = $version, seeds = $seeds, settings = $settings, started_at = time::now() RETURN id")
+        .bind(("table", RUNS_TABLE))
+        .bind(("version", version.to_owned()))
+        .bind(("seeds", seeds))
+        .bind(("settings", settings))
+        .await?
+        .take(0)?;
+
+    record
+        .map(|record| record.id)
+        .context("Failed to record the crawl run")
+}
+
+/// Fills in how the run `id` ended, along with its summary if it got as far as having one.
+pub async fn finish_run(db: &Surreal<Any>, id: Thing, outcome: RunOutcome, summary: Option<Summary>, error: Option<String>) -> anyhow::Result<()> {
+    db.query("UPDATE $id SET ended_at = time::now(), outcome = $outcome, summary = $summary, error = $error")
+        .bind(("id", id))
+        .bind(("outcome", outcome))
+        .bind(("summary", summary))
+        .bind(("error", error))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_claims_create_one_node() {
+        let db = connect_memory().await.unwrap();
+        db.use_ns("findconn").use_db("test").await.unwrap();
+        define_schema(&db, "site").await.unwrap();
+
+        let claims: Vec<_> = (0..32)
+            .map(|_| {
+                let db = db.clone();
+                tokio::spawn(async move { claim_node(&db, "site", &SiteURLNode::new("https://example.com/".to_owned(), 0)).await.unwrap() })
+            })
+            .collect();
+
+        let mut ids = Vec::new();
+        let mut created = 0;
+        for claim in claims {
+            let (id, was_created) = claim.await.unwrap();
+            ids.push(id);
+            created += usize::from(was_created);
+        }
+
+        let rows: Vec<Record> = db.select("site").await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(created, 1);
+        assert!(ids.iter().all(|id| *id == rows[0].id));
+    }
+
+    #[tokio::test]
+    async fn nodes_without_depth_can_be_updated() {
+        let db = connect_memory().await.unwrap();
+        db.use_ns("findconn").use_db("test").await.unwrap();
+        // like a node from before depth was recorded.
+        db.query("CREATE site:old SET url = 'https://example.com/old'").await.unwrap().check().unwrap();
+        db.query("CREATE site:older SET url = 'https://example.com/older'").await.unwrap().check().unwrap();
+        define_schema(&db, "site").await.unwrap();
+
+        let old = Thing::from(("site", "old"));
+        record_fetch(&db, old.clone(), FetchInfo { status: Some(200), ..Default::default() }).await.unwrap();
+        lower_depth(&db, old.clone(), 3).await.unwrap();
+        lower_depth(&db, old.clone(), 5).await.unwrap();
+
+        let page = SiteURLNode::new("https://example.com/".to_owned(), 0);
+        let (page, _) = claim_node(&db, "site", &page).await.unwrap();
+        let older = SiteURLNode::new("https://example.com/older".to_owned(), 1);
+        record_page(&db, "site", page, None, vec![older], Vec::new()).await.unwrap();
+
+        let mut res = db.query("SELECT VALUE depth FROM [site:old, site:older]").await.unwrap();
+        let depths: Vec<Option<u32>> = res.take(0).unwrap();
+        assert_eq!(depths, [Some(3), Some(1)]);
+    }
+}
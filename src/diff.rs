@@ -0,0 +1,296 @@
+//! Compares two crawls of the same site: the pages and links that came and went, and the pages that broke or got fixed.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+};
+
+use serde::{Deserialize, Serialize};
+use surrealdb::{engine::any::Any, sql::Thing, Surreal};
+use url::Url;
+
+use crate::{db::RUNS_TABLE, normalize::UrlNormalizer, query::ensure_not_empty};
+
+/// How many nodes or relations are pulled from the db at a time.
+const PAGE_SIZE: usize = 1000;
+
+/// What a crawl had found, with every url normalized so crawls by different versions line up.
+#[derive(Default)]
+pub struct Snapshot {
+    /// Each page, with whether it was broken when it was last fetched. `None` for pages that never were.
+    pages: BTreeMap<String, Option<bool>>,
+    links: BTreeSet<(String, String)>,
+}
+
+#[derive(Deserialize)]
+struct SnapshotNode {
+    url: String,
+    status: Option<u16>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SnapshotLink {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct Link {
+    pub from: String,
+    pub to: String,
+}
+
+/// Everything that's different in the later crawl, sorted by url.
+#[derive(Serialize)]
+pub struct Diff {
+    pub pages_added: Vec<String>,
+    pub pages_removed: Vec<String>,
+    pub links_added: Vec<Link>,
+    pub links_removed: Vec<Link>,
+    /// Pages that were broken (404, 410, a 5xx, or not fetched at all) and aren't anymore.
+    pub pages_fixed: Vec<String>,
+    /// Pages that weren't broken and are now.
+    pub pages_broken: Vec<String>,
+}
+
+pub struct DiffOptions {
+    pub json: bool,
+    /// List at most this many of each kind of change.
+    pub limit: Option<usize>,
+    /// Only print how many of each kind of change there are.
+    pub summary_only: bool,
+}
+
+/// Parses a run id as `query runs` prints it, with or without the `runs:` in front.
+pub fn run_id(id: &str) -> Thing {
+    let id = id.strip_prefix(&format!("{RUNS_TABLE}:")).unwrap_or(id);
+    Thing::from((RUNS_TABLE, id))
+}
+
+/// What's in the db's `table` and `relate_table`. With a `run`, only what was there as of that run: what it and
+/// the runs before it created, along with anything from before runs were recorded.
+///
+/// Nodes only keep how they were last fetched, so the broken pages are as of the latest crawl either way.
+pub async fn snapshot(db: &Surreal<Any>, (table, relate_table): (&str, &str), run: Option<Thing>) -> anyhow::Result<Snapshot> {
+    ensure_not_empty(db, table).await?;
+
+    let runs: Option<Vec<Thing>> = match run {
+        Some(run) => {
+            let started: Option<Thing> = db
+                .query("SELECT VALUE id FROM ONLY $run")
+                .bind(("run", run.clone()))
+                .await?
+                .take(0)?;
+            if started.is_none() {
+                anyhow::bail!("There's no run {run} in this db. `findconn query runs` lists the ones there are.");
+            }
+
+            let runs: Vec<Thing> = db
+                .query("SELECT VALUE id FROM type::table($runs) WHERE started_at <= $run.started_at")
+                .bind(("runs", RUNS_TABLE))
+                .bind(("run", run))
+                .await?
+                .take(0)?;
+            Some(runs)
+        }
+        None => None,
+    };
+
+    let normalizer = UrlNormalizer {
+        normalize: true,
+        ..Default::default()
+    };
+    let normalize = |url: &str| match Url::parse(url) {
+        Ok(mut url) => {
+            normalizer.apply(&mut url);
+            url.to_string()
+        }
+        Err(_) => url.to_owned(),
+    };
+
+    let mut snapshot = Snapshot::default();
+
+    let mut start = 0;
+    loop {
+        let nodes: Vec<SnapshotNode> = db
+            .query("SELECT url, status, error FROM type::table($table) \
+                WHERE $runs IS NONE OR first_seen_run IS NONE OR first_seen_run IN $runs \
+                ORDER BY url LIMIT $limit START $start")
+            .bind(("table", table.to_owned()))
+            .bind(("runs", runs.clone()))
+            .bind(("limit", PAGE_SIZE))
+            .bind(("start", start))
+            .await?
+            .take(0)?;
+        let last_page = nodes.len() < PAGE_SIZE;
+
+        for node in nodes {
+            let broken = match (node.status, node.error) {
+                (Some(status), _) => Some(matches!(status, 404 | 410) || status >= 500),
+                (None, Some(_)) => Some(true),
+                (None, None) => None,
+            };
+            snapshot.pages.insert(normalize(&node.url), broken);
+        }
+
+        if last_page {
+            break;
+        }
+        start += PAGE_SIZE;
+    }
+
+    let mut start = 0;
+    loop {
+        let links: Vec<SnapshotLink> = db
+            .query("SELECT id, in.url AS from, out.url AS to FROM type::table($table) \
+                WHERE $runs IS NONE OR run IS NONE OR run IN $runs \
+                ORDER BY id LIMIT $limit START $start")
+            .bind(("table", relate_table.to_owned()))
+            .bind(("runs", runs.clone()))
+            .bind(("limit", PAGE_SIZE))
+            .bind(("start", start))
+            .await?
+            .take(0)?;
+        let last_page = links.len() < PAGE_SIZE;
+
+        // a relation to a node that's since been deleted has nothing to compare.
+        for link in links {
+            if let (Some(from), Some(to)) = (link.from, link.to) {
+                snapshot.links.insert((normalize(&from), normalize(&to)));
+            }
+        }
+
+        if last_page {
+            break;
+        }
+        start += PAGE_SIZE;
+    }
+
+    Ok(snapshot)
+}
+
+/// What changed going from the `from` crawl to the `to` one.
+pub fn diff(from: &Snapshot, to: &Snapshot) -> Diff {
+    let links = |a: &Snapshot, b: &Snapshot| {
+        a.links
+            .difference(&b.links)
+            .map(|(from, to)| Link {
+                from: from.clone(),
+                to: to.clone(),
+            })
+            .collect()
+    };
+
+    let mut pages_fixed = Vec::new();
+    let mut pages_broken = Vec::new();
+    for (url, before) in &from.pages {
+        match (before, to.pages.get(url)) {
+            (Some(true), Some(Some(false))) => pages_fixed.push(url.clone()),
+            (Some(false), Some(Some(true))) => pages_broken.push(url.clone()),
+            _ => {}
+        }
+    }
+
+    Diff {
+        pages_added: to.pages.keys().filter(|url| !from.pages.contains_key(*url)).cloned().collect(),
+        pages_removed: from.pages.keys().filter(|url| !to.pages.contains_key(*url)).cloned().collect(),
+        links_added: links(to, from),
+        links_removed: links(from, to),
+        pages_fixed,
+        pages_broken,
+    }
+}
+
+#[derive(Serialize)]
+struct Counts {
+    pages_added: usize,
+    pages_removed: usize,
+    links_added: usize,
+    links_removed: usize,
+    pages_fixed: usize,
+    pages_broken: usize,
+}
+
+impl Diff {
+    fn counts(&self) -> Counts {
+        Counts {
+            pages_added: self.pages_added.len(),
+            pages_removed: self.pages_removed.len(),
+            links_added: self.links_added.len(),
+            links_removed: self.links_removed.len(),
+            pages_fixed: self.pages_fixed.len(),
+            pages_broken: self.pages_broken.len(),
+        }
+    }
+
+    /// Cuts each list down to `limit`.
+    fn truncate(&mut self, limit: usize) {
+        self.pages_added.truncate(limit);
+        self.pages_removed.truncate(limit);
+        self.links_added.truncate(limit);
+        self.links_removed.truncate(limit);
+        self.pages_fixed.truncate(limit);
+        self.pages_broken.truncate(limit);
+    }
+}
+
+/// Prints `diff`, as a list of changes under a count for each kind, or as JSON with the counts alongside the lists.
+pub fn write(mut diff: Diff, options: &DiffOptions, out: &mut impl Write) -> anyhow::Result<()> {
+    let counts = diff.counts();
+    if options.summary_only {
+        diff.truncate(0);
+    } else if let Some(limit) = options.limit {
+        diff.truncate(limit);
+    }
+
+    if options.json {
+        #[derive(Serialize)]
+        struct Output {
+            counts: Counts,
+            #[serde(flatten)]
+            diff: Option<Diff>,
+        }
+
+        let output = Output {
+            counts,
+            diff: (!options.summary_only).then_some(diff),
+        };
+        writeln!(out, "{}", serde_json::to_string_pretty(&output)?)?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    let sections = [
+        ("Pages added", '+', counts.pages_added, diff.pages_added),
+        ("Pages removed", '-', counts.pages_removed, diff.pages_removed),
+        ("Pages fixed", '+', counts.pages_fixed, diff.pages_fixed),
+        ("Pages broken", '-', counts.pages_broken, diff.pages_broken),
+    ];
+    let link_sections = [
+        ("Links added", '+', counts.links_added, diff.links_added),
+        ("Links removed", '-', counts.links_removed, diff.links_removed),
+    ];
+
+    for (title, sign, count, urls) in sections {
+        writeln!(out, "{title}: {count}")?;
+        for url in &urls {
+            writeln!(out, "  {sign} {url}")?;
+        }
+        if urls.len() < count && !options.summary_only {
+            writeln!(out, "  ... and {} more", count - urls.len())?;
+        }
+    }
+    for (title, sign, count, links) in link_sections {
+        writeln!(out, "{title}: {count}")?;
+        for link in &links {
+            writeln!(out, "  {sign} {} -> {}", link.from, link.to)?;
+        }
+        if links.len() < count && !options.summary_only {
+            writeln!(out, "  ... and {} more", count - links.len())?;
+        }
+    }
+
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use linkify::{LinkFinder, LinkKind};
+use percent_encoding::percent_decode_str;
+
+/// Finds the plain email addresses in text, for `--collect-emails`.
+pub struct EmailFinder {
+    finder: LinkFinder,
+}
+
+impl EmailFinder {
+    pub fn new() -> Self {
+        let mut finder = LinkFinder::new();
+        finder.kinds(&[LinkKind::Email]);
+
+        Self { finder }
+    }
+
+    /// Every address in `text`, normalized, in the order they come up.
+    pub fn find<'a>(&'a self, text: &'a str) -> impl Iterator<Item = String> + 'a {
+        self.finder.links(text).filter_map(|link| normalize(link.as_str()))
+    }
+}
+
+/// The addresses in a `mailto:` href, or `None` if it isn't one.
+///
+/// A mailto can have several comma separated recipients, and anything after `?` (subject, cc, ...) is ignored.
+pub fn mailto_addresses(href: &str) -> Option<Vec<String>> {
+    let href = href.trim();
+    let (scheme, rest) = href.split_once(':')?;
+    if !scheme.eq_ignore_ascii_case("mailto") {
+        return None;
+    }
+
+    let recipients = rest.split('?').next().unwrap_or("");
+    let recipients = percent_decode_str(recipients).decode_utf8_lossy();
+
+    Some(recipients.split(',').filter_map(normalize).collect())
+}
+
+/// Lowercases the domain, which is case insensitive. The local part is left alone since it technically isn't.
+fn normalize(address: &str) -> Option<String> {
+    let (local, domain) = address.trim().rsplit_once('@')?;
+    if local.is_empty() || domain.is_empty() || local.contains(char::is_whitespace) || domain.contains(char::is_whitespace) {
+        return None;
+    }
+
+    Some(format!("{local}@{}", domain.to_lowercase()))
+}
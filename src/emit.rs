@@ -0,0 +1,82 @@
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use surrealdb::sql::Datetime;
+use tokio::{
+    io::{AsyncWriteExt, Stdout},
+    sync::Mutex,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum EmitFormat {
+    /// One JSON object per line.
+    Ndjson,
+}
+
+/// A relation found during the crawl, as `--emit` writes it.
+#[derive(Serialize)]
+pub struct Edge<'a> {
+    pub source: &'a str,
+    pub target: &'a str,
+    /// The target's depth, as far as this edge is concerned.
+    pub depth: u32,
+    pub ts: String,
+}
+
+impl<'a> Edge<'a> {
+    pub fn new(source: &'a str, target: &'a str, depth: u32) -> Self {
+        Self {
+            source,
+            target,
+            depth,
+            ts: Datetime::default().to_raw(),
+        }
+    }
+}
+
+/// Streams edges to stdout as they're found, for `--emit`.
+pub struct Emitter {
+    format: EmitFormat,
+    stdout: Mutex<Stdout>,
+    closed: AtomicBool,
+}
+
+impl Emitter {
+    pub fn new(format: EmitFormat) -> Self {
+        Self {
+            format,
+            stdout: Mutex::new(tokio::io::stdout()),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Writes `edge` and flushes it. Nothing is buffered, so when stdout is a slow pipe this waits on it.
+    ///
+    /// Once a write fails (most likely because whatever was reading went away), nothing else is written,
+    /// and only that first failure is returned.
+    pub async fn emit(&self, edge: &Edge<'_>) -> io::Result<()> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let mut line = match self.format {
+            EmitFormat::Ndjson => serde_json::to_vec(edge)?,
+        };
+        line.push(b'\n');
+
+        let mut stdout = self.stdout.lock().await;
+        let written = match stdout.write_all(&line).await {
+            Ok(()) => stdout.flush().await,
+            Err(err) => Err(err),
+        };
+
+        match written {
+            Err(err) if !self.closed.swap(true, Ordering::SeqCst) => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
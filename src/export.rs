@@ -0,0 +1,611 @@
+use std::{collections::HashSet, io::Write};
+
+use clap::ValueEnum;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use surrealdb::{engine::any::Any, sql::Thing, Surreal};
+
+use crate::db::LINK_SCOPE;
+
+/// How many rows each export query pulls at a time, so huge crawls don't have to fit in memory.
+const PAGE_SIZE: usize = 1000;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// A Graphviz digraph.
+    Dot,
+    /// GraphML, for Gephi, yEd, networkx and friends.
+    Graphml,
+    /// Gephi's own format.
+    Gexf,
+    /// A JSON array, shaped by `--shape`.
+    Json,
+    /// An edge list, sorted by source then target.
+    Csv,
+}
+
+/// How `--format json` lays out the graph.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum JsonShape {
+    /// One object per node, with the urls it links to.
+    Adjacency,
+    /// One object per relation.
+    Edges,
+}
+
+pub struct ExportOptions<'a> {
+    pub table: &'a str,
+    pub relate_table: &'a str,
+    pub format: ExportFormat,
+    pub shape: JsonShape,
+    /// Only export this many nodes, and the edges between them.
+    pub max_nodes: Option<usize>,
+    /// Only export external relations if true, internal ones if false.
+    pub external: Option<bool>,
+}
+
+/// The parts of a node that exports care about. Everything besides `url` is optional
+/// so databases from older runs (which may not have these fields) still export.
+#[derive(Deserialize)]
+struct ExportNode {
+    id: Thing,
+    url: String,
+    title: Option<String>,
+    status: Option<u16>,
+    depth: Option<u32>,
+    /// False for nodes that were only recorded, like external links or pages past --max-depth.
+    fetched: bool,
+    external: bool,
+}
+
+/// Whether a node was ever requested, worked out in the query since older dbs don't have `fetched_at`.
+const FETCHED: &str = "(fetched_at IS NOT NONE OR status IS NOT NONE OR error IS NOT NONE) AS fetched, external = true AS external";
+
+#[derive(Deserialize)]
+struct ExportEdge {
+    id: Thing,
+    #[serde(rename = "in")]
+    a_in: Thing,
+    out: Thing,
+    count: Option<u32>,
+    text: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NodeMeta {
+    title: Option<String>,
+    status: Option<u16>,
+    depth: Option<u32>,
+    content_type: Option<String>,
+    error: Option<String>,
+    fetched: bool,
+    external: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AdjacencyNode {
+    url: String,
+    id: String,
+    links_to: Vec<String>,
+    meta: NodeMeta,
+}
+
+#[derive(Deserialize)]
+struct CsvNode {
+    url: String,
+    id: String,
+    title: Option<String>,
+    status: Option<u16>,
+    depth: Option<u32>,
+    content_type: Option<String>,
+    error: Option<String>,
+    fetched: bool,
+    external: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EdgeObject {
+    source: String,
+    target: String,
+    count: Option<u32>,
+    text: Option<String>,
+}
+
+/// Writes the graph stored in `options.table` and `options.relate_table` to `out`.
+pub async fn export(db: &Surreal<Any>, options: &ExportOptions<'_>, out: &mut impl Write) -> anyhow::Result<()> {
+    match options.format {
+        ExportFormat::Json => export_json(db, options, out).await?,
+        ExportFormat::Csv => export_csv(db, options, out).await?,
+        format => export_graph(db, options, format, out).await?,
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+/// Pulls one page of `query`'s rows. `query` gets `$table`, `$relate_table` and `$external` bound, and should end
+/// with `LIMIT $limit START $start` plus an ORDER BY so the pages line up.
+async fn fetch_page<T: DeserializeOwned>(db: &Surreal<Any>, options: &ExportOptions<'_>, query: &str, start: usize) -> anyhow::Result<Vec<T>> {
+    let mut res = db
+        .query(query)
+        .bind(("table", options.table.to_owned()))
+        .bind(("relate_table", options.relate_table.to_owned()))
+        .bind(("limit", PAGE_SIZE))
+        .bind(("start", start))
+        .bind(("external", options.external))
+        .await?;
+
+    Ok(res.take(0)?)
+}
+
+async fn export_graph(db: &Surreal<Any>, options: &ExportOptions<'_>, format: ExportFormat, out: &mut impl Write) -> anyhow::Result<()> {
+    let edges_query = format!("SELECT id, in, out, count, text FROM type::table($relate_table) WHERE {LINK_SCOPE} ORDER BY id LIMIT $limit START $start");
+    let nodes_query = format!("SELECT id, url, title, status, depth, {FETCHED} FROM type::table($table) ORDER BY id LIMIT $limit START $start");
+
+    write_header(format, out)?;
+
+    // only needed to drop edges whose ends didn't make the cut.
+    let mut included = options.max_nodes.map(|_| HashSet::new());
+    let mut written = 0;
+    let mut start = 0;
+
+    'pages: loop {
+        let nodes: Vec<ExportNode> = fetch_page(db, options, &nodes_query, start).await?;
+        let last_page = nodes.len() < PAGE_SIZE;
+
+        for node in nodes {
+            if options.max_nodes.is_some_and(|max_nodes| written >= max_nodes) {
+                break 'pages;
+            }
+
+            write_node(format, &node, out)?;
+            written += 1;
+
+            if let Some(included) = &mut included {
+                included.insert(node.id);
+            }
+        }
+
+        if last_page {
+            break;
+        }
+        start += PAGE_SIZE;
+    }
+
+    write_between(format, out)?;
+
+    let mut start = 0;
+    loop {
+        let edges: Vec<ExportEdge> = fetch_page(db, options, &edges_query, start).await?;
+        let last_page = edges.len() < PAGE_SIZE;
+
+        for edge in edges {
+            if included.as_ref().is_none_or(|included| included.contains(&edge.a_in) && included.contains(&edge.out)) {
+                write_edge(format, &edge, out)?;
+            }
+        }
+
+        if last_page {
+            break;
+        }
+        start += PAGE_SIZE;
+    }
+
+    write_footer(format, out)?;
+    Ok(())
+}
+
+async fn export_json(db: &Surreal<Any>, options: &ExportOptions<'_>, out: &mut impl Write) -> anyhow::Result<()> {
+    match options.shape {
+        JsonShape::Adjacency => {
+            let (table, relate_table) = (options.table, options.relate_table);
+            let query = format!(
+                "SELECT url, <string> id AS id, array::distinct(->({relate_table} WHERE {LINK_SCOPE})->{table}.url) AS links_to, \
+                    {{ title: title, status: status, depth: depth, content_type: content_type, error: error, \
+                    fetched: fetched_at IS NOT NONE OR status IS NOT NONE OR error IS NOT NONE, external: external = true }} AS meta \
+                    FROM type::table($table) ORDER BY id LIMIT $limit START $start"
+            );
+
+            write_json_rows::<AdjacencyNode>(db, options, &query, options.max_nodes, out).await
+        }
+        JsonShape::Edges => {
+            let query = format!(
+                "SELECT id, in.url AS source, out.url AS target, count, text FROM type::table($relate_table) WHERE {LINK_SCOPE} \
+                    ORDER BY id LIMIT $limit START $start"
+            );
+
+            write_json_rows::<EdgeObject>(db, options, &query, None, out).await
+        }
+    }
+}
+
+/// Writes every row of `query` (see [`fetch_page`]) as an element of a JSON array, one per line.
+async fn write_json_rows<T: DeserializeOwned + Serialize>(
+    db: &Surreal<Any>,
+    options: &ExportOptions<'_>,
+    query: &str,
+    limit: Option<usize>,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    writeln!(out, "[")?;
+
+    let mut written = 0;
+    let mut start = 0;
+    'pages: loop {
+        let rows: Vec<T> = fetch_page(db, options, query, start).await?;
+        let last_page = rows.len() < PAGE_SIZE;
+
+        for row in rows {
+            if limit.is_some_and(|limit| written >= limit) {
+                break 'pages;
+            }
+
+            if written > 0 {
+                writeln!(out, ",")?;
+            }
+            write!(out, "  {}", serde_json::to_string(&row)?)?;
+            written += 1;
+        }
+
+        if last_page {
+            break;
+        }
+        start += PAGE_SIZE;
+    }
+
+    if written > 0 {
+        writeln!(out)?;
+    }
+    writeln!(out, "]")?;
+    Ok(())
+}
+
+async fn export_csv(db: &Surreal<Any>, options: &ExportOptions<'_>, out: &mut impl Write) -> anyhow::Result<()> {
+    // sorted so that diffing two exports of the same site actually shows what changed.
+    let edges_query = format!(
+        "SELECT in.url AS source, out.url AS target, count, text FROM type::table($relate_table) WHERE {LINK_SCOPE} \
+            ORDER BY source, target LIMIT $limit START $start"
+    );
+
+    writeln!(out, "source,target,count,text")?;
+
+    let mut start = 0;
+    loop {
+        let edges: Vec<EdgeObject> = fetch_page(db, options, &edges_query, start).await?;
+        let last_page = edges.len() < PAGE_SIZE;
+
+        for edge in edges {
+            writeln!(
+                out,
+                "{},{},{},{}",
+                csv_escape(&edge.source),
+                csv_escape(&edge.target),
+                edge.count.map(|count| count.to_string()).unwrap_or_default(),
+                csv_escape(edge.text.as_deref().unwrap_or("")),
+            )?;
+        }
+
+        if last_page {
+            break;
+        }
+        start += PAGE_SIZE;
+    }
+
+    Ok(())
+}
+
+/// Writes a CSV of every node and its metadata, sorted by url, to go along with the `--format csv` edge list.
+pub async fn export_nodes_csv(db: &Surreal<Any>, options: &ExportOptions<'_>, out: &mut impl Write) -> anyhow::Result<()> {
+    let nodes_query = format!(
+        "SELECT url, <string> id AS id, title, status, depth, content_type, error, {FETCHED} FROM type::table($table) \
+            ORDER BY url LIMIT $limit START $start"
+    );
+
+    writeln!(out, "url,id,title,status,depth,content_type,error,fetched,external")?;
+
+    let mut start = 0;
+    loop {
+        let nodes: Vec<CsvNode> = fetch_page(db, options, &nodes_query, start).await?;
+        let last_page = nodes.len() < PAGE_SIZE;
+
+        for node in nodes {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{}",
+                csv_escape(&node.url),
+                csv_escape(&node.id),
+                csv_escape(node.title.as_deref().unwrap_or("")),
+                node.status.map(|status| status.to_string()).unwrap_or_default(),
+                node.depth.map(|depth| depth.to_string()).unwrap_or_default(),
+                csv_escape(node.content_type.as_deref().unwrap_or("")),
+                csv_escape(node.error.as_deref().unwrap_or("")),
+                node.fetched,
+                node.external,
+            )?;
+        }
+
+        if last_page {
+            break;
+        }
+        start += PAGE_SIZE;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn write_header(format: ExportFormat, out: &mut impl Write) -> anyhow::Result<()> {
+    match format {
+        ExportFormat::Dot => writeln!(out, "digraph findconn {{")?,
+        ExportFormat::Graphml => {
+            writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+            writeln!(out, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+            writeln!(out, r#"  <key id="url" for="node" attr.name="url" attr.type="string"/>"#)?;
+            writeln!(out, r#"  <key id="title" for="node" attr.name="title" attr.type="string"/>"#)?;
+            writeln!(out, r#"  <key id="status" for="node" attr.name="status" attr.type="int"/>"#)?;
+            writeln!(out, r#"  <key id="depth" for="node" attr.name="depth" attr.type="int"/>"#)?;
+            writeln!(out, r#"  <key id="fetched" for="node" attr.name="fetched" attr.type="boolean"/>"#)?;
+            writeln!(out, r#"  <key id="external" for="node" attr.name="external" attr.type="boolean"/>"#)?;
+            writeln!(out, r#"  <key id="count" for="edge" attr.name="count" attr.type="int"/>"#)?;
+            writeln!(out, r#"  <key id="text" for="edge" attr.name="text" attr.type="string"/>"#)?;
+            writeln!(out, r#"  <graph id="findconn" edgedefault="directed">"#)?;
+        }
+        ExportFormat::Gexf => {
+            writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+            writeln!(out, r#"<gexf xmlns="http://gexf.net/1.3" version="1.3">"#)?;
+            writeln!(out, r#"  <graph mode="static" defaultedgetype="directed">"#)?;
+            writeln!(out, r#"    <attributes class="node">"#)?;
+            writeln!(out, r#"      <attribute id="url" title="url" type="string"/>"#)?;
+            writeln!(out, r#"      <attribute id="status" title="status" type="integer"/>"#)?;
+            writeln!(out, r#"      <attribute id="depth" title="depth" type="integer"/>"#)?;
+            writeln!(out, r#"      <attribute id="fetched" title="fetched" type="boolean"/>"#)?;
+            writeln!(out, r#"      <attribute id="external" title="external" type="boolean"/>"#)?;
+            writeln!(out, "    </attributes>")?;
+            writeln!(out, r#"    <attributes class="edge">"#)?;
+            writeln!(out, r#"      <attribute id="text" title="text" type="string"/>"#)?;
+            writeln!(out, "    </attributes>")?;
+            writeln!(out, "    <nodes>")?;
+        }
+        ExportFormat::Json | ExportFormat::Csv => unreachable!("json and csv aren't written node by node"),
+    }
+
+    Ok(())
+}
+
+fn write_node(format: ExportFormat, node: &ExportNode, out: &mut impl Write) -> anyhow::Result<()> {
+    let label = node.title.as_deref().unwrap_or(&node.url);
+
+    match format {
+        // dashed so the pages that were only recorded stand out from the ones actually crawled.
+        ExportFormat::Dot => writeln!(
+            out,
+            "    \"{}\" [label=\"{}\", URL=\"{}\"{}];",
+            dot_escape(&node.id.to_string()),
+            dot_escape(label),
+            dot_escape(&node.url),
+            if node.fetched { "" } else { ", style=dashed" },
+        )?,
+        ExportFormat::Graphml => {
+            writeln!(out, r#"    <node id="{}">"#, xml_escape(&node.id.to_string()))?;
+            writeln!(out, r#"      <data key="url">{}</data>"#, xml_escape(&node.url))?;
+            if let Some(title) = &node.title {
+                writeln!(out, r#"      <data key="title">{}</data>"#, xml_escape(title))?;
+            }
+            if let Some(status) = node.status {
+                writeln!(out, r#"      <data key="status">{status}</data>"#)?;
+            }
+            if let Some(depth) = node.depth {
+                writeln!(out, r#"      <data key="depth">{depth}</data>"#)?;
+            }
+            writeln!(out, r#"      <data key="fetched">{}</data>"#, node.fetched)?;
+            writeln!(out, r#"      <data key="external">{}</data>"#, node.external)?;
+            writeln!(out, "    </node>")?;
+        }
+        ExportFormat::Gexf => {
+            writeln!(
+                out,
+                r#"      <node id="{}" label="{}">"#,
+                xml_escape(&node.id.to_string()),
+                xml_escape(label),
+            )?;
+            writeln!(out, "        <attvalues>")?;
+            writeln!(out, r#"          <attvalue for="url" value="{}"/>"#, xml_escape(&node.url))?;
+            if let Some(status) = node.status {
+                writeln!(out, r#"          <attvalue for="status" value="{status}"/>"#)?;
+            }
+            if let Some(depth) = node.depth {
+                writeln!(out, r#"          <attvalue for="depth" value="{depth}"/>"#)?;
+            }
+            writeln!(out, r#"          <attvalue for="fetched" value="{}"/>"#, node.fetched)?;
+            writeln!(out, r#"          <attvalue for="external" value="{}"/>"#, node.external)?;
+            writeln!(out, "        </attvalues>")?;
+            writeln!(out, "      </node>")?;
+        }
+        ExportFormat::Json | ExportFormat::Csv => unreachable!("json and csv aren't written node by node"),
+    }
+
+    Ok(())
+}
+
+/// Whatever goes after the last node and before the first edge.
+fn write_between(format: ExportFormat, out: &mut impl Write) -> anyhow::Result<()> {
+    if let ExportFormat::Gexf = format {
+        writeln!(out, "    </nodes>")?;
+        writeln!(out, "    <edges>")?;
+    }
+
+    Ok(())
+}
+
+fn write_edge(format: ExportFormat, edge: &ExportEdge, out: &mut impl Write) -> anyhow::Result<()> {
+    match format {
+        ExportFormat::Dot => writeln!(
+            out,
+            "    \"{}\" -> \"{}\";",
+            dot_escape(&edge.a_in.to_string()),
+            dot_escape(&edge.out.to_string()),
+        )?,
+        ExportFormat::Graphml => {
+            writeln!(
+                out,
+                r#"    <edge id="{}" source="{}" target="{}">"#,
+                xml_escape(&edge.id.to_string()),
+                xml_escape(&edge.a_in.to_string()),
+                xml_escape(&edge.out.to_string()),
+            )?;
+            if let Some(count) = edge.count {
+                writeln!(out, r#"      <data key="count">{count}</data>"#)?;
+            }
+            if let Some(text) = &edge.text {
+                writeln!(out, r#"      <data key="text">{}</data>"#, xml_escape(text))?;
+            }
+            writeln!(out, "    </edge>")?;
+        }
+        ExportFormat::Gexf => {
+            // gephi uses weight for edge thickness, which is exactly what count is.
+            writeln!(
+                out,
+                r#"      <edge id="{}" source="{}" target="{}" weight="{}">"#,
+                xml_escape(&edge.id.to_string()),
+                xml_escape(&edge.a_in.to_string()),
+                xml_escape(&edge.out.to_string()),
+                edge.count.unwrap_or(1),
+            )?;
+            if let Some(text) = &edge.text {
+                writeln!(out, "        <attvalues>")?;
+                writeln!(out, r#"          <attvalue for="text" value="{}"/>"#, xml_escape(text))?;
+                writeln!(out, "        </attvalues>")?;
+            }
+            writeln!(out, "      </edge>")?;
+        }
+        ExportFormat::Json | ExportFormat::Csv => unreachable!("json and csv aren't written edge by edge"),
+    }
+
+    Ok(())
+}
+
+fn write_footer(format: ExportFormat, out: &mut impl Write) -> anyhow::Result<()> {
+    match format {
+        ExportFormat::Dot => writeln!(out, "}}")?,
+        ExportFormat::Graphml => {
+            writeln!(out, "  </graph>")?;
+            writeln!(out, "</graphml>")?;
+        }
+        ExportFormat::Gexf => {
+            writeln!(out, "    </edges>")?;
+            writeln!(out, "  </graph>")?;
+            writeln!(out, "</gexf>")?;
+        }
+        ExportFormat::Json | ExportFormat::Csv => unreachable!("json and csv aren't written edge by edge"),
+    }
+
+    Ok(())
+}
+
+/// Quotes a CSV field if it needs it. Urls can have both commas and quotes in them.
+pub fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn xml_escape(s: &str) -> String {
+    s.chars()
+        // control characters aren't allowed in XML 1.0 at all, even escaped.
+        .filter(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+        .fold(String::with_capacity(s.len()), |mut escaped, c| {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&apos;"),
+                c => escaped.push(c),
+            }
+            escaped
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    const TRICKY_URL: &str = r#"https://example.com/search?q="a"&page=<2>"#;
+
+    async fn exported(format: ExportFormat) -> String {
+        let db = db::connect_memory().await.unwrap();
+        db.use_ns("findconn").use_db("test").await.unwrap();
+        db.query("CREATE site:1 SET url = 'https://example.com/', depth = 0, title = 'Tom & Jerry\u{1}', status = 200")
+            .query("CREATE site:2 SET url = $url, depth = 1")
+            .query("RELATE site:1->containslink->site:2 SET count = 2, text = '<b>\"next\"</b> & more'")
+            .bind(("url", TRICKY_URL))
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let options = ExportOptions {
+            table: "site",
+            relate_table: "containslink",
+            format,
+            shape: JsonShape::Edges,
+            max_nodes: None,
+            external: None,
+        };
+        let mut out = Vec::new();
+        export(&db, &options, &mut out).await.unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[tokio::test]
+    async fn graphml_round_trips() {
+        let xml = exported(ExportFormat::Graphml).await;
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+
+        let data = |key: &str| -> Vec<String> {
+            doc.descendants()
+                .filter(|node| node.has_tag_name("data") && node.attribute("key") == Some(key))
+                .map(|node| node.text().unwrap_or("").to_owned())
+                .collect()
+        };
+        assert_eq!(data("url"), ["https://example.com/", TRICKY_URL]);
+        assert_eq!(data("title"), ["Tom & Jerry"]);
+        assert_eq!(data("text"), [r#"<b>"next"</b> & more"#]);
+        assert_eq!(data("count"), ["2"]);
+
+        let edge = doc.descendants().find(|node| node.has_tag_name("edge")).unwrap();
+        assert_eq!(edge.attribute("source"), Some("site:1"));
+        assert_eq!(edge.attribute("target"), Some("site:2"));
+    }
+
+    #[tokio::test]
+    async fn gexf_round_trips() {
+        let xml = exported(ExportFormat::Gexf).await;
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+
+        let labels: Vec<&str> = doc
+            .descendants()
+            .filter(|node| node.has_tag_name("node"))
+            .filter_map(|node| node.attribute("label"))
+            .collect();
+        assert_eq!(labels, ["Tom & Jerry", TRICKY_URL]);
+
+        let urls: Vec<&str> = doc
+            .descendants()
+            .filter(|node| node.has_tag_name("attvalue") && node.attribute("for") == Some("url"))
+            .filter_map(|node| node.attribute("value"))
+            .collect();
+        assert_eq!(urls, ["https://example.com/", TRICKY_URL]);
+
+        let edge = doc.descendants().find(|node| node.has_tag_name("edge")).unwrap();
+        assert_eq!(edge.attribute("weight"), Some("2"));
+        let text = edge.descendants().find(|node| node.has_tag_name("attvalue")).and_then(|node| node.attribute("value"));
+        assert_eq!(text, Some(r#"<b>"next"</b> & more"#));
+    }
+}
@@ -0,0 +1,434 @@
+use std::sync::LazyLock;
+
+use linkify::{LinkFinder, LinkKind};
+use roxmltree::Document;
+use scraper::{ElementRef, Html, Selector};
+
+/// Titles longer than this (in chars) get cut off.
+const MAX_TITLE_LEN: usize = 512;
+
+/// Same as [`MAX_TITLE_LEN`] but for anchor text, which there's a lot more of.
+const MAX_LINK_TEXT_LEN: usize = 256;
+
+/// Elements whose text isn't part of what a reader sees as the page: code, styling, and the navigation and
+/// other boilerplate around the content.
+const BOILERPLATE_ELEMENTS: &[&str] = &["head", "script", "style", "noscript", "template", "svg", "nav", "aside", "footer"];
+
+/// Elements that run on in the same line of text, so no space is added around them.
+const INLINE_ELEMENTS: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "cite", "code", "data", "del", "dfn", "em", "i", "ins", "kbd", "mark", "q", "s",
+    "samp", "small", "span", "strong", "sub", "sup", "time", "u", "var", "wbr",
+];
+
+static TITLE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse("title").unwrap());
+
+static BASE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse("base[href]").unwrap());
+
+static CANONICAL_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse("link[rel][href]").unwrap());
+
+static META_ROBOTS_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse("meta[name][content]").unwrap());
+
+static LINK_SELECTOR: LazyLock<Selector> = LazyLock::new(|| {
+    Selector::parse("a[href], link[href], script[src], img[src], iframe[src]").unwrap()
+});
+
+static URL_FINDER: LazyLock<LinkFinder> = LazyLock::new(|| {
+    let mut finder = LinkFinder::new();
+    finder.kinds(&[LinkKind::Url]);
+    finder
+});
+
+/// Everything we pull out of an HTML document.
+pub struct HtmlPage {
+    /// The text of the first `<title>`, with whitespace collapsed.
+    pub title: Option<String>,
+
+    /// The href of the first `<base>` element, which relative links resolve against instead of the page url.
+    pub base: Option<String>,
+
+    /// The href of `<link rel="canonical">`, if there is one.
+    pub canonical: Option<String>,
+
+    /// What `<meta name="robots">` (or a meta tag naming our agent) asks of crawlers.
+    pub robots: RobotsDirectives,
+
+    pub links: Vec<Link>,
+
+    /// The page's readable text with whitespace collapsed, when it was asked for.
+    pub text: Option<String>,
+
+    /// The text of every node, scripts and navigation included, for scanning for things that aren't links. Only there
+    /// when it was asked for.
+    pub all_text: Option<String>,
+}
+
+/// The directives we care about from a meta robots tag or an `X-Robots-Tag` header.
+#[derive(Clone, Copy, Default)]
+pub struct RobotsDirectives {
+    pub noindex: bool,
+    pub nofollow: bool,
+}
+
+impl RobotsDirectives {
+    /// Adds a single directive like `nofollow`, ignoring any we don't know.
+    fn add(&mut self, directive: &str) {
+        let directive = directive.trim();
+
+        if directive.eq_ignore_ascii_case("noindex") {
+            self.noindex = true;
+        } else if directive.eq_ignore_ascii_case("nofollow") {
+            self.nofollow = true;
+        } else if directive.eq_ignore_ascii_case("none") {
+            self.noindex = true;
+            self.nofollow = true;
+        }
+    }
+
+    /// Adds every directive in a comma separated list like `noindex, nofollow`.
+    fn add_all(&mut self, directives: &str) {
+        for directive in directives.split(',') {
+            self.add(directive);
+        }
+    }
+
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            noindex: self.noindex || other.noindex,
+            nofollow: self.nofollow || other.nofollow,
+        }
+    }
+}
+
+/// Directives in an `X-Robots-Tag` header that take a value after a colon, so it isn't mistaken for an agent name.
+const VALUED_DIRECTIVES: &[&str] = &["unavailable_after", "max-snippet", "max-image-preview", "max-video-preview"];
+
+/// Parses an `X-Robots-Tag` header, keeping only the directives meant for every crawler or for `agent`.
+///
+/// Directives can be scoped to an agent like `googlebot: noindex, nofollow`, in which case everything after it
+/// applies to that agent, up until the next one is named.
+pub fn robots_from_header(header: &str, agent: &str) -> RobotsDirectives {
+    let mut directives = RobotsDirectives::default();
+    let mut applies = true;
+
+    for token in header.split(',') {
+        let mut directive = token;
+
+        if let Some((name, rest)) = token.split_once(':') {
+            let name = name.trim();
+            if !VALUED_DIRECTIVES.iter().any(|valued| name.eq_ignore_ascii_case(valued)) {
+                applies = name.eq_ignore_ascii_case(agent);
+                directive = rest;
+            }
+        }
+
+        if applies {
+            directives.add(directive);
+        }
+    }
+
+    directives
+}
+
+pub struct Link {
+    /// The raw attribute value, which may still be relative.
+    pub url: String,
+
+    /// Whether the element's `rel` asks crawlers not to follow it (`nofollow`, `ugc`, or `sponsored`).
+    pub nofollow: bool,
+
+    /// The text inside an `<a>`, with whitespace collapsed.
+    pub text: Option<String>,
+}
+
+/// Parses `html`, using `agent` to pick out meta robots tags meant specifically for us. With `text_len`, the
+/// page's readable text is taken too, up to that many chars, and with `all_text` the text of the whole thing.
+pub fn parse_html(html: &str, agent: &str, text_len: Option<usize>, all_text: bool) -> HtmlPage {
+    let document = Html::parse_document(html);
+
+    let title = document
+        .select(&TITLE_SELECTOR)
+        .next()
+        .and_then(|element| collapsed_text(element, MAX_TITLE_LEN));
+
+    let base = document
+        .select(&BASE_SELECTOR)
+        .next()
+        .and_then(|element| element.value().attr("href"))
+        .map(|href| href.trim().to_owned());
+
+    let canonical = document
+        .select(&CANONICAL_SELECTOR)
+        .find(|element| element.value().attr("rel").is_some_and(|rel| has_rel(rel, &["canonical"])))
+        .and_then(|element| element.value().attr("href"))
+        .map(|href| href.trim().to_owned());
+
+    let mut robots = RobotsDirectives::default();
+    for element in document.select(&META_ROBOTS_SELECTOR) {
+        let element = element.value();
+        let name = element.attr("name").unwrap_or("").trim();
+
+        // tags for other crawlers (`googlebot`, `bingbot`, ...) aren't our business.
+        if name.eq_ignore_ascii_case("robots") || name.eq_ignore_ascii_case(agent) {
+            robots.add_all(element.attr("content").unwrap_or(""));
+        }
+    }
+
+    let links = document
+        .select(&LINK_SELECTOR)
+        .filter_map(|element_ref| {
+            let element = element_ref.value();
+            let attr = match element.name() {
+                "a" | "link" => "href",
+                _ => "src",
+            };
+
+            let url = element.attr(attr)?.trim();
+            if url.is_empty() {
+                return None;
+            }
+
+            let nofollow = element
+                .attr("rel")
+                .is_some_and(|rel| has_rel(rel, &["nofollow", "ugc", "sponsored"]));
+
+            let text = (element.name() == "a")
+                .then(|| collapsed_text(element_ref, MAX_LINK_TEXT_LEN))
+                .flatten();
+
+            Some(Link {
+                url: url.to_owned(),
+                nofollow,
+                text,
+            })
+        })
+        .collect();
+
+    let text = text_len.and_then(|max_len| readable_text(document.root_element(), max_len));
+    let all_text = all_text.then(|| document.root_element().text().collect::<Vec<_>>().join(" "));
+
+    HtmlPage { title, base, canonical, robots, links, text, all_text }
+}
+
+/// The absolute urls written out in `text`, for content with no markup to take links from.
+pub fn text_links(text: &str) -> Vec<Link> {
+    URL_FINDER
+        .links(text)
+        .map(|link| Link {
+            url: link.as_str().to_owned(),
+            nofollow: false,
+            text: None,
+        })
+        .collect()
+}
+
+/// All the text inside `element` with runs of whitespace squashed into one space, or `None` if there isn't any.
+fn collapsed_text(element: ElementRef, max_len: usize) -> Option<String> {
+    let text = element
+        .text()
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    (!text.is_empty()).then(|| text.chars().take(max_len).collect())
+}
+
+/// The text inside `element` that's shown to a reader, leaving out [`BOILERPLATE_ELEMENTS`] and anything hidden,
+/// with whitespace collapsed and cut off at `max_len` chars. `None` if there isn't any.
+fn readable_text(element: ElementRef, max_len: usize) -> Option<String> {
+    let mut text = String::new();
+    let mut len = 0;
+    // whether a space goes in before the next word, so words either side of a tag or a line break don't run together.
+    let mut space = false;
+
+    // walked with a stack instead of recursion, since there's no telling how deeply nested a page is.
+    let mut stack = vec![(false, element.children())];
+    while let Some((block, children)) = stack.last_mut() {
+        let Some(child) = children.next() else {
+            space |= *block;
+            stack.pop();
+            continue;
+        };
+
+        if let Some(element) = ElementRef::wrap(child) {
+            let node = element.value();
+            let hidden = node.attr("hidden").is_some() || node.attr("aria-hidden").is_some_and(|hidden| hidden.trim() == "true");
+            if hidden || BOILERPLATE_ELEMENTS.contains(&node.name()) {
+                continue;
+            }
+
+            let block = !INLINE_ELEMENTS.contains(&node.name());
+            space |= block;
+            stack.push((block, element.children()));
+        } else if let Some(chunk) = child.value().as_text() {
+            space |= chunk.starts_with(char::is_whitespace);
+            for word in chunk.split_whitespace() {
+                if space && !text.is_empty() {
+                    if len + 1 >= max_len {
+                        return Some(text);
+                    }
+                    text.push(' ');
+                    len += 1;
+                }
+                space = true;
+
+                for c in word.chars() {
+                    if len >= max_len {
+                        return Some(text);
+                    }
+                    text.push(c);
+                    len += 1;
+                }
+            }
+            space = chunk.ends_with(char::is_whitespace) || (space && chunk.trim().is_empty());
+        }
+    }
+
+    (!text.is_empty()).then_some(text)
+}
+
+/// Whether a `rel` attribute contains any of `kinds`.
+fn has_rel(rel: &str, kinds: &[&str]) -> bool {
+    // rel is a space separated list, so `rel="nofollowers"` shouldn't count.
+    rel.split_ascii_whitespace()
+        .any(|token| kinds.iter().any(|kind| token.eq_ignore_ascii_case(kind)))
+}
+
+/// Pulls the canonical url out of a `Link: <https://example.com/>; rel="canonical"` header.
+pub fn canonical_from_link_header(header: &str) -> Option<String> {
+    header.split(',').find_map(|entry| {
+        let (target, params) = entry.split_once(';')?;
+        let target = target.trim().strip_prefix('<')?.strip_suffix('>')?;
+
+        let is_canonical = params.split(';').any(|param| {
+            param
+                .split_once('=')
+                .is_some_and(|(name, value)| {
+                    name.trim().eq_ignore_ascii_case("rel") && has_rel(value.trim().trim_matches('"'), &["canonical"])
+                })
+        });
+
+        is_canonical.then(|| target.trim().to_owned())
+    })
+}
+
+/// Pulls the channel and entry links out of an RSS or Atom feed.
+///
+/// Returns `None` if `xml` isn't a well formed feed, so the caller can fall back to scanning it as text.
+pub fn parse_feed(xml: &str) -> Option<Vec<String>> {
+    let document = Document::parse(xml).ok()?;
+
+    // RSS 2.0, Atom, and RSS 1.0 (RDF) respectively.
+    if !matches!(document.root_element().tag_name().name(), "rss" | "feed" | "RDF") {
+        return None;
+    }
+
+    let links = document
+        .descendants()
+        .filter(|node| node.has_tag_name("link"))
+        .filter_map(|node| {
+            // Atom puts the url in href, RSS puts it in the element text.
+            node.attribute("href").or_else(|| node.text())
+        })
+        .map(str::trim)
+        .filter(|link| !link.is_empty())
+        .map(str::to_owned)
+        .collect();
+
+    Some(links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<!doctype html>
+<html>
+<head>
+  <title>
+    Tom &amp; Jerry &#8211; &quot;The Chase&quot;
+  </title>
+</head>
+<body><a href="/a?x=1&amp;y=2">Q&amp;A</a></body>
+</html>"#;
+
+    #[test]
+    fn decodes_entities_in_titles() {
+        let page = parse_html(PAGE, "findconn", None, false);
+
+        assert_eq!(page.title.as_deref(), Some("Tom & Jerry \u{2013} \"The Chase\""));
+        assert_eq!(page.links[0].url, "/a?x=1&y=2");
+        assert_eq!(page.links[0].text.as_deref(), Some("Q&A"));
+    }
+
+    const LINKS: &str = r#"<html>
+<head>
+  <link rel="stylesheet" href="/style.css">
+  <script src="https://cdn.example.com/app.js"></script>
+  <script>let api = "https://api.example.com/";</script>
+</head>
+<body>
+  <a href="/about">About
+    us</a>
+  <a href=" https://example.org/ " rel="nofollow">elsewhere</a>
+  <a>no href</a>
+  <a href="">empty</a>
+  <img src="/logo.png" alt="logo">
+  <iframe src="/embed"></iframe>
+  <video src="/clip.mp4"></video>
+  <p>Written out: https://example.net/text</p>
+</body>
+</html>"#;
+
+    fn link_parts(links: Vec<Link>) -> Vec<(String, bool, Option<String>)> {
+        links.into_iter().map(|link| (link.url, link.nofollow, link.text)).collect()
+    }
+
+    #[test]
+    fn takes_links_from_href_and_src() {
+        let links = link_parts(parse_html(LINKS, "findconn", None, false).links);
+
+        // urls in scripts and text aren't links in html, and neither is a <video>.
+        assert_eq!(
+            links,
+            [
+                ("/style.css".to_owned(), false, None),
+                ("https://cdn.example.com/app.js".to_owned(), false, None),
+                ("/about".to_owned(), false, Some("About us".to_owned())),
+                ("https://example.org/".to_owned(), true, Some("elsewhere".to_owned())),
+                ("/logo.png".to_owned(), false, None),
+                ("/embed".to_owned(), false, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn text_links_only_finds_absolute_urls() {
+        let json = r#"{"next": "https://example.com/page/2", "about": "/about", "help": "see https://example.com/docs.", "email": "hello@example.com"}"#;
+
+        let links = link_parts(text_links(json));
+        assert_eq!(
+            links,
+            [
+                ("https://example.com/page/2".to_owned(), false, None),
+                ("https://example.com/docs".to_owned(), false, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_title() {
+        assert_eq!(parse_html("<p>no title here</p>", "findconn", None, false).title, None);
+        assert_eq!(parse_html("<title>   </title>", "findconn", None, false).title, None);
+    }
+
+    #[test]
+    fn all_text_comes_from_the_same_parse() {
+        let html = "<html><body><nav>write to hello@example.com</nav><p>Some article.</p></body></html>";
+
+        let page = parse_html(html, "findconn", Some(1000), true);
+        assert_eq!(page.text.as_deref(), Some("Some article."));
+        assert!(page.all_text.unwrap().contains("hello@example.com"));
+
+        assert_eq!(parse_html(html, "findconn", None, false).all_text, None);
+    }
+}
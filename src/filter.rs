@@ -0,0 +1,415 @@
+use std::{collections::HashSet, fmt, str::FromStr, sync::LazyLock};
+
+use clap::ValueEnum;
+use publicsuffix::Psl;
+use regex::Regex;
+use url::{Host, Url};
+
+/// Mozilla's public suffix list, which is what says `example.co.uk` is one site but `co.uk` isn't.
+static PUBLIC_SUFFIXES: LazyLock<publicsuffix::List> =
+    LazyLock::new(|| include_str!("../data/public_suffix_list.dat").parse().unwrap());
+
+/// The part of `host` someone could register, like `example.com` for `blog.example.com`.
+///
+/// Hosts without one (IP addresses, `localhost`, a bare public suffix) are their own registrable domain.
+pub fn registrable_domain(host: &str) -> &str {
+    match Host::parse(host) {
+        Ok(Host::Domain(_)) => PUBLIC_SUFFIXES
+            .domain(host.as_bytes())
+            .map(|domain| &host[host.len() - domain.as_bytes().len()..])
+            .unwrap_or(host),
+        _ => host,
+    }
+}
+
+/// What a link on a page points to, from [`classify_link`].
+pub enum LinkTarget {
+    /// An http or https url, which can be crawled.
+    Web(Url),
+    /// A url with some other scheme, like `mailto:`, `tel:`, `javascript:` or `data:`. There's nothing to fetch.
+    Other(Url),
+    /// Something that isn't a url at all, even relative to the page.
+    Invalid(url::ParseError),
+}
+
+/// Resolves `href` against the page's `base` and sorts it by scheme.
+///
+/// A scheme-relative `//host/path` takes the page's scheme, so it's as crawlable as the page was.
+pub fn classify_link(base: &Url, href: &str) -> LinkTarget {
+    match base.join(href) {
+        Ok(url) if is_web_scheme(url.scheme()) => LinkTarget::Web(url),
+        Ok(url) => LinkTarget::Other(url),
+        Err(err) => LinkTarget::Invalid(err),
+    }
+}
+
+/// Whether urls with `scheme` can be crawled, i.e. it's http or https.
+pub fn is_web_scheme(scheme: &str) -> bool {
+    matches!(scheme, "http" | "https")
+}
+
+/// What counts as one site, for `--scope`.
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum Scope {
+    /// Only the exact same host, so `blog.example.com` and `www.example.com` are different sites.
+    #[default]
+    Host,
+    /// Every host under the same registrable domain, so `blog.example.co.uk` and `www.example.co.uk` are one site
+    /// but `example.co.uk` and `other.co.uk` aren't. IP addresses only match themselves.
+    Domain,
+    /// Whatever `--allow-domain` and `--deny-domain` let through is the site, and everything else is outside it.
+    Custom,
+}
+
+/// Decides which hosts are part of the same site, for everything that needs to know: `--same-domain`, whether a
+/// link is external, and what `--max-pages-per-domain` counts pages under.
+#[derive(Clone, Default)]
+pub struct SiteScope {
+    pub scope: Scope,
+    /// The `--allow-domain` and `--deny-domain` lists, which are everything with [`Scope::Custom`].
+    pub domains: DomainFilter,
+}
+
+impl SiteScope {
+    /// Whether `a` and `b` are on the same site. A url without a host is only on the same site as another one.
+    pub fn same_site(&self, a: Option<&str>, b: Option<&str>) -> bool {
+        match (self.scope, a, b) {
+            (Scope::Custom, _, b) => self.domains.check(b).is_ok(),
+            (_, Some(a), Some(b)) => self.site(a).eq_ignore_ascii_case(self.site(b)),
+            (_, a, b) => a == b,
+        }
+    }
+
+    /// Whether a link from `from` to `to` leaves the site.
+    pub fn is_external_link(&self, from: &Url, to: &Url) -> bool {
+        !self.same_site(from.host_str(), to.host_str())
+    }
+
+    /// What `host`'s pages are counted under: its registrable domain with [`Scope::Domain`], or otherwise itself.
+    pub fn site<'a>(&self, host: &'a str) -> &'a str {
+        match self.scope {
+            Scope::Domain => registrable_domain(host),
+            Scope::Host | Scope::Custom => host,
+        }
+    }
+}
+
+/// A host pattern given to `--allow-domain` or `--deny-domain`.
+#[derive(Clone, Debug)]
+pub enum DomainPattern {
+    /// Matches a single host exactly.
+    Exact(String),
+
+    /// `*.example.org`, matches any subdomain of `example.org` (but not `example.org` itself).
+    Suffix(String),
+}
+
+impl DomainPattern {
+    pub fn matches(&self, host: &str) -> bool {
+        match self {
+            Self::Exact(pattern) => host == pattern,
+            Self::Suffix(suffix) => host
+                .strip_suffix(suffix.as_str())
+                .is_some_and(|rest| rest.ends_with('.')),
+        }
+    }
+}
+
+impl FromStr for DomainPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+
+        if let Some(suffix) = s.strip_prefix("*.") {
+            if suffix.is_empty() || suffix.contains('*') {
+                return Err(format!("invalid domain pattern {s:?}"));
+            }
+
+            return Ok(Self::Suffix(suffix.to_owned()));
+        }
+
+        if s.is_empty() || s.contains('*') {
+            return Err(format!("invalid domain pattern {s:?}, wildcards are only allowed as a leading `*.`"));
+        }
+
+        Ok(Self::Exact(s))
+    }
+}
+
+impl fmt::Display for DomainPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact(host) => write!(f, "{host}"),
+            Self::Suffix(suffix) => write!(f, "*.{suffix}"),
+        }
+    }
+}
+
+/// Why a host was filtered out.
+pub enum DomainRejection<'a> {
+    Denied(&'a DomainPattern),
+    NotAllowed,
+}
+
+impl fmt::Display for DomainRejection<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Denied(pattern) => write!(f, "matched deny rule {pattern}"),
+            Self::NotAllowed => write!(f, "did not match any allow rule"),
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct DomainFilter {
+    pub allow: Vec<DomainPattern>,
+    pub deny: Vec<DomainPattern>,
+}
+
+impl DomainFilter {
+    pub fn check(&self, host: Option<&str>) -> Result<(), DomainRejection<'_>> {
+        let Some(host) = host else {
+            // nothing to match against, so only an allowlist can exclude it.
+            return if self.allow.is_empty() {
+                Ok(())
+            } else {
+                Err(DomainRejection::NotAllowed)
+            };
+        };
+
+        if let Some(pattern) = self.deny.iter().find(|p| p.matches(host)) {
+            return Err(DomainRejection::Denied(pattern));
+        }
+
+        if !self.allow.is_empty() && !self.allow.iter().any(|p| p.matches(host)) {
+            return Err(DomainRejection::NotAllowed);
+        }
+
+        Ok(())
+    }
+}
+
+/// What `--include` and `--exclude` decided about a url.
+pub enum FilterDecision<'a> {
+    Fetch,
+    /// There are include patterns and none of them matched.
+    NotIncluded,
+    Excluded(&'a Regex),
+}
+
+impl fmt::Display for FilterDecision<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fetch => write!(f, "fetch"),
+            Self::NotIncluded => write!(f, "did not match any --include pattern"),
+            Self::Excluded(pattern) => write!(f, "matched --exclude pattern {}", pattern.as_str()),
+        }
+    }
+}
+
+/// The `--include` and `--exclude` url patterns.
+#[derive(Default, Clone)]
+pub struct UrlFilter {
+    pub include: Vec<Regex>,
+    pub exclude: Vec<Regex>,
+}
+
+impl UrlFilter {
+    /// Includes are checked first, so a url has to match an include (if there are any) and then not match any
+    /// exclude to be fetched.
+    pub fn check(&self, url: &str) -> FilterDecision<'_> {
+        if !self.include.is_empty() && !self.include.iter().any(|pattern| pattern.is_match(url)) {
+            return FilterDecision::NotIncluded;
+        }
+
+        match self.exclude.iter().find(|pattern| pattern.is_match(url)) {
+            Some(pattern) => FilterDecision::Excluded(pattern),
+            None => FilterDecision::Fetch,
+        }
+    }
+}
+
+/// Extensions of files that are never worth downloading to look for links in.
+const DEFAULT_SKIP_EXTENSIONS: &[&str] = &[
+    // images
+    "png", "jpg", "jpeg", "gif", "webp", "bmp", "ico", "tif", "tiff", "avif", "heic",
+    // audio and video
+    "mp3", "wav", "ogg", "flac", "m4a", "aac", "mp4", "m4v", "mov", "avi", "mkv", "webm", "wmv", "flv",
+    // archives
+    "zip", "tar", "gz", "tgz", "bz2", "xz", "7z", "rar", "zst",
+    // documents
+    "pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "odt",
+    // executables and packages
+    "exe", "msi", "dmg", "iso", "bin", "apk", "deb", "rpm", "jar",
+    // fonts
+    "woff", "woff2", "ttf", "otf", "eot",
+];
+
+/// Skips urls whose path ends in a binary file extension, so they don't get downloaded just to be thrown away.
+pub struct ExtensionFilter {
+    skip: HashSet<String>,
+}
+
+impl ExtensionFilter {
+    /// The default list, plus `skip` and minus `allow`.
+    pub fn new(skip: &[String], allow: &[String]) -> Self {
+        let normalize = |ext: &String| ext.trim().trim_start_matches('.').to_lowercase();
+
+        let mut extensions: HashSet<String> = DEFAULT_SKIP_EXTENSIONS
+            .iter()
+            .map(|ext| ext.to_string())
+            .chain(skip.iter().map(normalize))
+            .collect();
+
+        for ext in allow.iter().map(normalize) {
+            extensions.remove(&ext);
+        }
+
+        Self { skip: extensions }
+    }
+
+    /// The extension `url` is skipped for, if it is.
+    pub fn skipped<'a>(&self, url: &'a Url) -> Option<&'a str> {
+        // the path leaves out the query and fragment, so `photo.png?size=large` still counts.
+        let file = url.path().rsplit('/').next()?;
+        let (_, ext) = file.rsplit_once('.')?;
+
+        self.skip.contains(&ext.to_lowercase()).then_some(ext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(base: &str, href: &str) -> String {
+        match classify_link(&Url::parse(base).unwrap(), href) {
+            LinkTarget::Web(url) => url.to_string(),
+            _ => panic!("{href:?} didn't resolve to a web url"),
+        }
+    }
+
+    #[test]
+    fn resolves_relative_links() {
+        let base = "https://example.com/docs/guide/intro.html?lang=en";
+        let cases = [
+            ("https://other.com/page", "https://other.com/page"),
+            ("/contact", "https://example.com/contact"),
+            ("setup.html", "https://example.com/docs/guide/setup.html"),
+            ("./setup.html", "https://example.com/docs/guide/setup.html"),
+            ("../index.html", "https://example.com/docs/index.html"),
+            ("//cdn.example.com/x", "https://cdn.example.com/x"),
+            ("?page=2", "https://example.com/docs/guide/intro.html?page=2"),
+            ("#install", "https://example.com/docs/guide/intro.html?lang=en#install"),
+        ];
+
+        for (href, expected) in cases {
+            assert_eq!(resolve(base, href), expected, "{href:?}");
+        }
+    }
+
+    #[test]
+    fn other_schemes_are_not_web_links() {
+        let base = Url::parse("https://example.com/page").unwrap();
+        let data = format!("data:image/png;base64,{}", "iVBORw0KGgo".repeat(2000));
+        let cases = ["javascript:void(0)", "mailto:someone@example.com", "tel:+441234567890", "ftp://example.com/file", &data];
+
+        for href in cases {
+            match classify_link(&base, href) {
+                LinkTarget::Other(url) => assert_eq!(url.as_str(), href),
+                _ => panic!("{:.40} wasn't classified as another scheme", href),
+            }
+        }
+    }
+
+    #[test]
+    fn scheme_relative_links_take_the_page_scheme() {
+        assert_eq!(resolve("http://example.com/", "//cdn.example.com/lib.js"), "http://cdn.example.com/lib.js");
+        assert_eq!(resolve("https://example.com/", "//cdn.example.com/lib.js"), "https://cdn.example.com/lib.js");
+    }
+
+    #[test]
+    fn garbage_is_invalid() {
+        let base = Url::parse("https://example.com/").unwrap();
+
+        assert!(matches!(classify_link(&base, "http://exa mple.com/"), LinkTarget::Invalid(_)));
+        assert!(matches!(classify_link(&base, "https://[::1/"), LinkTarget::Invalid(_)));
+    }
+
+    #[test]
+    fn registrable_domains_follow_the_public_suffix_list() {
+        let cases = [
+            ("www.example.com", "example.com"),
+            ("a.b.example.co.uk", "example.co.uk"),
+            ("example.co.uk", "example.co.uk"),
+            // nothing to register under a bare public suffix, or an IP address.
+            ("co.uk", "co.uk"),
+            ("192.168.1.10", "192.168.1.10"),
+            ("[::1]", "[::1]"),
+            ("localhost", "localhost"),
+        ];
+
+        for (host, expected) in cases {
+            assert_eq!(registrable_domain(host), expected, "{host:?}");
+        }
+    }
+
+    #[test]
+    fn domain_scope_includes_subdomains() {
+        let scope = SiteScope { scope: Scope::Domain, ..SiteScope::default() };
+
+        assert!(scope.same_site(Some("blog.example.com"), Some("www.example.com")));
+        assert!(scope.same_site(Some("shop.example.co.uk"), Some("example.co.uk")));
+        assert!(!scope.same_site(Some("example.co.uk"), Some("other.co.uk")));
+        assert!(!scope.same_site(Some("10.0.0.1"), Some("10.0.0.2")));
+        assert!(scope.same_site(Some("10.0.0.1"), Some("10.0.0.1")));
+    }
+
+    #[test]
+    fn host_scope_is_the_default() {
+        let scope = SiteScope::default();
+
+        assert!(!scope.same_site(Some("blog.example.com"), Some("www.example.com")));
+        assert!(scope.same_site(Some("WWW.example.com"), Some("www.example.com")));
+        assert_eq!(scope.site("blog.example.co.uk"), "blog.example.co.uk");
+    }
+
+    fn url_filter(include: &[&str], exclude: &[&str]) -> UrlFilter {
+        UrlFilter {
+            include: include.iter().map(|pattern| Regex::new(pattern).unwrap()).collect(),
+            exclude: exclude.iter().map(|pattern| Regex::new(pattern).unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn no_patterns_fetches_everything() {
+        assert!(matches!(url_filter(&[], &[]).check("https://example.com/anything"), FilterDecision::Fetch));
+    }
+
+    #[test]
+    fn include_then_exclude() {
+        let filter = url_filter(&[r"^https://docs\.example\.com/v2/", r"/blog/"], &[r"/v2/internal/", r"\?print"]);
+        let cases = [
+            ("https://docs.example.com/v2/guide", "fetch"),
+            ("https://example.com/blog/post", "fetch"),
+            ("https://docs.example.com/v1/guide", "did not match any --include pattern"),
+            // not being included wins over being excluded, since includes are checked first.
+            ("https://docs.example.com/v1/guide?print", "did not match any --include pattern"),
+            ("https://docs.example.com/v2/internal/secrets", r"matched --exclude pattern /v2/internal/"),
+            ("https://example.com/blog/post?print", r"matched --exclude pattern \?print"),
+        ];
+
+        for (url, expected) in cases {
+            assert_eq!(filter.check(url).to_string(), expected, "{url:?}");
+        }
+    }
+
+    #[test]
+    fn exclude_only() {
+        let filter = url_filter(&[], &[r"\.php$"]);
+
+        assert!(matches!(filter.check("https://example.com/index.php"), FilterDecision::Excluded(_)));
+        assert!(matches!(filter.check("https://example.com/index.html"), FilterDecision::Fetch));
+    }
+}
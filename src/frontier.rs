@@ -0,0 +1,158 @@
+use std::{
+    collections::VecDeque,
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::Thing;
+use tokio::sync::Notify;
+
+use crate::model::LinkData;
+
+/// A url waiting to be crawled.
+///
+/// These are also kept in the frontier table until they've been processed, which is what `--resume` picks back up.
+#[derive(Serialize, Deserialize, Default)]
+pub struct FrontierItem {
+    /// Its entry in the frontier table.
+    #[serde(skip_serializing)]
+    pub id: Option<Thing>,
+    /// The node that led here.
+    pub source: Option<Thing>,
+    pub url: String,
+    pub depth: u32,
+    /// What gets written on the relation from `source`.
+    #[serde(default)]
+    pub link: LinkData,
+    /// Whether `source` declared this as its canonical url rather than linking to it.
+    pub canonical: bool,
+    /// Whether `source` redirected here, with a redirect that wasn't followed.
+    #[serde(default)]
+    pub redirect: bool,
+    /// The `<lastmod>` given by a sitemap.
+    pub lastmod: Option<String>,
+    /// Set once this entry created the url's node, making it the one responsible for fetching it.
+    #[serde(default)]
+    pub owner: bool,
+    /// How many times its host has rate limited it already, so a host that always does can't keep it coming back.
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+/// Which queued url gets crawled next.
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum Strategy {
+    /// Oldest first, so every page at one depth is crawled before any at the next.
+    #[default]
+    Bfs,
+    /// Newest first, following each page's links all the way down before moving on to its siblings.
+    Dfs,
+}
+
+/// The queue of urls shared between crawl workers.
+///
+/// Every pushed item must be paired with a call to [`Frontier::finish`] once it has been processed.
+/// When nothing is queued or in progress the queue closes and [`Frontier::pop`] returns `None`.
+pub struct Frontier {
+    queue: Mutex<VecDeque<FrontierItem>>,
+    strategy: Strategy,
+    /// Woken whenever something is pushed, or the queue closes.
+    changed: Notify,
+    pending: AtomicUsize,
+    closed: AtomicBool,
+    stopped: AtomicBool,
+}
+
+impl Frontier {
+    pub fn new(strategy: Strategy) -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+            strategy,
+            changed: Notify::new(),
+            pending: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    /// Queues `items`, which keep their order relative to each other whichever the strategy.
+    pub fn extend(&self, items: Vec<FrontierItem>) {
+        if self.closed.load(Ordering::SeqCst) || items.is_empty() {
+            return;
+        }
+
+        self.pending.fetch_add(items.len(), Ordering::SeqCst);
+
+        let mut queue = self.queue.lock().unwrap();
+        match self.strategy {
+            Strategy::Bfs => queue.extend(items),
+            Strategy::Dfs => {
+                for item in items.into_iter().rev() {
+                    queue.push_front(item);
+                }
+            }
+        }
+        drop(queue);
+
+        self.changed.notify_waiters();
+    }
+
+    pub async fn pop(&self) -> Option<FrontierItem> {
+        loop {
+            // registered before looking, so a push between the check and the await still wakes us.
+            let mut changed = pin!(self.changed.notified());
+            changed.as_mut().enable();
+
+            if self.is_stopped() {
+                return None;
+            }
+            if let Some(item) = self.queue.lock().unwrap().pop_front() {
+                return Some(item);
+            }
+            if self.closed.load(Ordering::SeqCst) {
+                return None;
+            }
+
+            changed.await;
+        }
+    }
+
+    /// Closes the queue early. Whatever is still queued is left alone, and [`Frontier::pop`] returns `None` from now on.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.close();
+    }
+
+    /// Wakes up every worker waiting in pop, for good.
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.changed.notify_waiters();
+    }
+
+    /// How many items are queued, not counting the ones being processed.
+    #[cfg(feature = "metrics")]
+    pub fn queued(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    /// How many items are queued or still being processed.
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    pub fn finish(&self) {
+        if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // nobody is left to push anything, so every worker's pop can return.
+            self.close();
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Crawls sites for the links between their pages and writes them to a [`store::GraphStore`], like a SurrealDB
+//! database, as `findconn` does.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use site_connection_finder::{db, store::SurrealStore, Crawler};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let db = db::connect_memory().await?;
+//! db.use_ns("findconn").use_db("example").await?;
+//!
+//! let summary = Crawler::builder()
+//!     .seeds(["https://example.com"])
+//!     .same_domain(true)
+//!     .max_pages(100)
+//!     .build(Arc::new(SurrealStore::new(db.clone())))?
+//!     .crawl()
+//!     .await?;
+//! println!("{summary}");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Pages end up as nodes in the `site` table, with `containslink` relations between them. The [`model`] types
+//! are what's stored, and [`query`], [`report`] and [`export`] read it back out.
+
+mod address;
+pub mod archive;
+mod content;
+pub mod crawler;
+pub mod db;
+pub mod diff;
+mod email;
+pub mod emit;
+pub mod export;
+mod extract;
+pub mod filter;
+pub mod frontier;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod model;
+pub mod normalize;
+mod politeness;
+pub mod proxies;
+pub mod query;
+pub mod report;
+mod robots;
+mod sitemap;
+pub mod store;
+pub mod summary;
+mod visited;
+pub mod webhook;
+
+pub use crawler::{CrawlEvent, CrawlHandle, Crawler, CrawlerBuilder, DEFAULT_USER_AGENT};
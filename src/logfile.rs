@@ -0,0 +1,211 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// When `--log-file` moves on to a fresh file.
+#[derive(Clone, Copy)]
+pub enum Rotation {
+    /// At the first line of each new day, in UTC.
+    Daily,
+    /// Before the file would grow past this many bytes.
+    Size(u64),
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("daily") {
+            return Ok(Self::Daily);
+        }
+
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (amount, unit) = s.split_at(split);
+        let amount: u64 = amount
+            .parse()
+            .map_err(|_| format!("expected daily or a size like 100MB, got {s:?}"))?;
+        let unit_bytes: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+            "B" => 1,
+            "KB" => 1024,
+            "MB" => 1024 * 1024,
+            "GB" => 1024 * 1024 * 1024,
+            _ => return Err(format!("unknown unit {unit:?} in {s:?}, expected KB, MB or GB")),
+        };
+
+        match amount.saturating_mul(unit_bytes) {
+            0 => Err("the size to rotate at has to be more than 0".to_owned()),
+            bytes => Ok(Self::Size(bytes)),
+        }
+    }
+}
+
+enum Message {
+    Line(Vec<u8>),
+    Shutdown,
+}
+
+/// Hands log lines off to a thread that writes them to the file, so a slow disk never holds up the crawl.
+#[derive(Clone)]
+pub struct LogFile {
+    sender: Sender<Message>,
+}
+
+/// Flushes whatever's left to the file when dropped. Has to be kept around until the end of main.
+pub struct LogFileGuard {
+    sender: Sender<Message>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LogFile {
+    /// Opens `path` to append to, failing right away if it can't be written.
+    pub fn open(path: &Path, rotation: Rotation) -> anyhow::Result<(Self, LogFileGuard)> {
+        let file = RotatingFile::open(path.to_owned(), rotation)?;
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("log-file".to_owned())
+            .spawn(move || file.run(receiver))
+            .context("Failed to start the log file writer")?;
+
+        let guard = LogFileGuard {
+            sender: sender.clone(),
+            thread: Some(thread),
+        };
+        Ok((Self { sender }, guard))
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // once the writer is gone there's nowhere left for the line to go, which isn't worth failing the log call over.
+        let _ = self.sender.send(Message::Line(buf.to_vec()));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = LogFile;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+impl Drop for LogFileGuard {
+    fn drop(&mut self) {
+        let _ = self.sender.send(Message::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: BufWriter<File>,
+    /// How big the file is so far.
+    size: u64,
+    /// The day the file was started, for [`Rotation::Daily`].
+    day: NaiveDate,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, rotation: Rotation) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Can't write logs to {path:?}"))?;
+
+        // appending to a file from an earlier run carries on with its size and day.
+        let metadata = file.metadata().with_context(|| format!("Can't write logs to {path:?}"))?;
+        let day = metadata
+            .modified()
+            .map_or_else(|_| Utc::now().date_naive(), |modified| DateTime::<Utc>::from(modified).date_naive());
+
+        Ok(Self {
+            path,
+            rotation,
+            file: BufWriter::new(file),
+            size: metadata.len(),
+            day,
+        })
+    }
+
+    fn run(mut self, receiver: Receiver<Message>) {
+        while let Ok(message) = receiver.recv() {
+            let mut shutdown = false;
+
+            // everything that's already waiting goes out before the flush, so a busy crawl isn't flushing every line.
+            for message in std::iter::once(message).chain(receiver.try_iter()) {
+                match message {
+                    Message::Line(line) => {
+                        if let Err(err) = self.write(&line) {
+                            eprintln!("Failed to write to {:?}: {err}", self.path);
+                        }
+                    }
+                    Message::Shutdown => shutdown = true,
+                }
+            }
+
+            if let Err(err) = self.file.flush() {
+                eprintln!("Failed to write to {:?}: {err}", self.path);
+            }
+
+            if shutdown {
+                break;
+            }
+        }
+    }
+
+    fn write(&mut self, line: &[u8]) -> io::Result<()> {
+        let today = Utc::now().date_naive();
+        let rotate = match self.rotation {
+            Rotation::Daily => today != self.day,
+            Rotation::Size(max) => self.size > 0 && self.size + line.len() as u64 > max,
+        };
+        if rotate {
+            self.rotate(today)?;
+        }
+
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Moves the current file aside, named after the day it was for or when it filled up, and starts a new one.
+    fn rotate(&mut self, today: NaiveDate) -> io::Result<()> {
+        self.file.flush()?;
+
+        let suffix = match self.rotation {
+            Rotation::Daily => self.day.to_string(),
+            Rotation::Size(_) => Utc::now().format("%Y-%m-%dT%H-%M-%S").to_string(),
+        };
+        let mut rotated = PathBuf::from(format!("{}.{suffix}", self.path.display()));
+        let mut n = 1;
+        while rotated.exists() {
+            rotated = PathBuf::from(format!("{}.{suffix}.{n}", self.path.display()));
+            n += 1;
+        }
+        fs::rename(&self.path, &rotated)?;
+
+        self.file = BufWriter::new(OpenOptions::new().create(true).append(true).open(&self.path)?);
+        self.size = 0;
+        self.day = today;
+        Ok(())
+    }
+}
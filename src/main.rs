@@ -1,194 +1,1327 @@
-mod model;
-
-use std::{collections::HashSet, error::Error, path::PathBuf, sync::Arc};
-
-use async_recursion::async_recursion;
-use clap::Parser;
-use linkify::{LinkFinder, LinkKind};
-use model::{Record, Relation, SiteURLNode};
-use reqwest::{header::CONTENT_TYPE, Client, Proxy};
-use surrealdb::{engine::local::{Db, SurrealKV}, Surreal};
-use tracing::{debug, error, info, trace};
-use tracing_subscriber::EnvFilter;
+mod config;
+mod cookies;
+mod logfile;
+#[cfg(feature = "otlp")]
+mod otlp;
+mod progress;
+mod seeds;
+
+use std::{collections::HashMap, error::Error, fs::{self, File}, io::{self, BufWriter, IsTerminal, Write}, path::{Path, PathBuf}, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use clap::{ArgAction, ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use indicatif::ProgressBar;
+use logfile::{LogFile, Rotation};
+use progress::LogWriter;
+use regex::Regex;
+use reqwest::{header::{HeaderMap, HeaderName, HeaderValue}, Certificate};
+use reqwest_cookie_store::CookieStoreMutex;
+#[cfg(feature = "metrics")]
+use site_connection_finder::metrics;
+#[cfg(feature = "sqlite")]
+use site_connection_finder::store::SqliteStore;
+use site_connection_finder::{
+    archive::ContentStorage,
+    crawler, db,
+    diff::{self, DiffOptions},
+    emit::EmitFormat,
+    export::{self, ExportFormat, ExportOptions, JsonShape},
+    filter::{DomainPattern, Scope},
+    frontier::Strategy,
+    model::RunOutcome,
+    normalize::UrlNormalizer,
+    proxies,
+    query::{self, ListFilter, NodeKind},
+    report::{self, BrokenOptions, ReportFormat},
+    store::{FileFormat, FileStore, GraphStore, SurrealStore},
+    summary::Summary,
+    webhook::EventKind,
+    CrawlHandle, Crawler, DEFAULT_USER_AGENT,
+};
+use surrealdb::{engine::any::Any, sql::Thing, Surreal};
+use tracing::{error, info, trace};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
 use url::Url;
 
 #[derive(Parser)]
 #[command(
     name = "findconn",
     author = "HyperCodec",
-    about = "Finds all connected links (at least ones accessible via GET request) and dumps them into a SurrealDB database."
+    about = "Finds all connected links (at least ones accessible via GET request) and dumps them into a SurrealDB database.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    group = ArgGroup::new("url_patterns").multiple(true)
 )]
 struct Cli {
-    #[arg(short, long, help = "The output DB directory")]
-    output: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    #[arg(short, long, help = "The initial site to parse")]
-    url: String,
+    #[arg(long, help = "A TOML file of settings to use for anything not given on the command line. Its keys are the long flag names, e.g. `relate_table = \"links\"`.", value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    #[arg(short, long, help = "The output DB directory, or the url of a SurrealDB server to write to instead (ws://, wss://, http:// or https://). With --backend files, the directory the files go in, and with --backend sqlite, the file.", required_unless_present_any = ["memory", "dry_run"])]
+    output: Option<String>,
+
+    #[arg(long, help = "Keep the database in memory instead of writing it anywhere. Everything is gone once the crawl ends.", conflicts_with = "output")]
+    memory: bool,
+
+    #[arg(long, help = "Crawl as normal but keep everything in memory, then print how many pages each host had. Nothing is written anywhere.", conflicts_with_all = ["output", "resume"])]
+    dry_run: bool,
+
+    #[arg(long, help = "What the crawl is written to. files appends nodes and edges to plain files in --output as the crawl runs, with no database at all, but can't be resumed or refreshed. sqlite writes a single SQLite file at --output.", value_enum, default_value_t = Backend::Surrealdb)]
+    backend: Backend,
+
+    #[arg(long, help = "The format --backend files writes in", value_enum, default_value_t = FileFormat::Ndjson)]
+    file_format: FileFormat,
+
+    #[arg(long, help = "Let --backend files write over the files of a crawl already in --output")]
+    force: bool,
+
+    #[arg(long, help = "How log lines are written, to stderr and --log-file", value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    #[arg(long, help = "Also write logs to this file, appending to it if it's already there")]
+    log_file: Option<PathBuf>,
+
+    #[arg(long, help = "When --log-file moves on to a fresh file, daily or once it reaches a size like `100MB`. The old one is renamed after the day or time it was rotated.", default_value = "daily", requires = "log_file")]
+    log_rotate: Rotation,
+
+    #[arg(long, help = "Which logs go to --log-file, like `info` or `warn,site_connection_finder=debug`. Defaults to RUST_LOG, or info.", value_parser = parse_log_filter, requires = "log_file")]
+    log_file_level: Option<String>,
+
+    #[arg(long, help = "Which logs go to stderr, like `error` to only see what went wrong while --log-file gets the rest. Defaults to RUST_LOG, or info.", value_parser = parse_log_filter)]
+    stderr_level: Option<String>,
+
+    #[arg(long, help = "Show a live status line with the crawl's progress. When stderr isn't a terminal, a status line is logged every 10 seconds instead.")]
+    progress: bool,
+
+    #[cfg(feature = "otlp")]
+    #[arg(long, help = "Send tracing spans to an OpenTelemetry collector over OTLP/HTTP, like `http://localhost:4318/v1/traces`, with a span for each page and what happened to it inside. Which ones are sent goes by RUST_LOG, or info.", value_name = "ENDPOINT")]
+    trace_otlp: Option<String>,
+
+    #[cfg(feature = "metrics")]
+    #[arg(long, help = "Serve Prometheus metrics on http://<METRICS_ADDR>/metrics while crawling, e.g. `127.0.0.1:9100`")]
+    metrics_addr: Option<String>,
+
+    #[arg(long, help = "Stream every new relation to stdout as it's found, e.g. to pipe into jq along with --dry-run. Logs stay on stderr.", value_enum)]
+    emit: Option<EmitFormat>,
+
+    #[arg(long, help = "POST crawl events to this url as JSON arrays, batched every few seconds or 100 events. The crawl never waits on it, and batches that keep failing are dropped.")]
+    webhook: Option<Url>,
+
+    #[arg(long, help = "Which events to send to --webhook. Comma separated or repeated, all of them if not given.", value_enum, value_delimiter = ',', requires = "webhook")]
+    webhook_events: Vec<EventKind>,
+
+    #[arg(long, help = "Also write the end-of-run summary to this file as JSON")]
+    summary_json: Option<PathBuf>,
+
+    #[arg(long, help = "The user to sign in to a remote SurrealDB server as", env = "SURREAL_USER", requires = "db_pass")]
+    db_user: Option<String>,
+
+    #[arg(long, help = "The password for --db-user", env = "SURREAL_PASS", hide_env_values = true, requires = "db_user")]
+    db_pass: Option<String>,
+
+    #[arg(short, long, help = "The initial site to parse. Can be repeated to crawl several sites into the same db, which needs --db. With --resume, this only picks the default db name.", required_unless_present_any = ["resume", "url_file"])]
+    url: Vec<String>,
+
+    #[arg(long, help = "A file of initial sites to parse, one per line, or `-` to read them from stdin. Blank lines and # comments are ignored.", conflicts_with = "resume")]
+    url_file: Option<PathBuf>,
+
+    #[arg(long, help = "Stop if a line in --url-file isn't a valid url, instead of skipping it", requires = "url_file")]
+    strict: bool,
+
+    #[arg(long, help = "Carry on an interrupted crawl from the urls still in its frontier table, instead of starting from --url", conflicts_with_all = ["memory", "sitemap"])]
+    resume: bool,
+
+    #[arg(long, alias = "recrawl", help = "Crawl an existing db again, re-fetching the pages it already has as well as any new ones. If-None-Match/If-Modified-Since are sent so unchanged pages aren't downloaded twice.", conflicts_with_all = ["memory", "resume", "dry_run"])]
+    refresh: bool,
+
+    #[arg(long, help = "With --refresh, only re-fetch pages last checked longer ago than this, like `90s`, `30m`, `12h` or `7d`. The links of newer ones are still followed.", value_parser = parse_duration, requires = "refresh")]
+    older_than: Option<Duration>,
+
+    #[arg(long, help = "With --refresh, delete the relations for links that are no longer on a page. Otherwise they're kept, so the db ends up with every link ever seen.", requires = "refresh")]
+    prune_stale_edges: bool,
+
+    #[arg(long, help = "Define the tables' field types and indexes (unique urls and relations, status) before crawling, if they aren't already. Without them the same url or relation can end up written twice.", action = ArgAction::Set, default_value_t = true)]
+    define_schema: bool,
+
+    #[arg(long, help = "The table where urls waiting to be crawled are kept until they're done", default_value = "frontier")]
+    frontier_table: String,
 
     #[arg(short, long, help = "The namespace to use in surrealDB", default_value = "findconn")]
     ns: String,
 
-    #[arg(short, long, help = "The database name to use in surrealDB. Defaults to `tree-{url}` if not specified.")]
+    #[arg(short, long, help = "The database name to use in surrealDB. Defaults to `tree-{url}` if not specified, which only works with a single --url.")]
     db: Option<String>,
 
     #[arg(short, long, help = "The table where the urls will be stored", default_value = "site")]
     table: String,
 
-    #[arg(short, long, help = "A proxy so you dont blast your home IP across the web (recommended)")]
-    proxy: Option<String>,
+    #[arg(short, long, help = "A proxy so you dont blast your home IP across the web (recommended). http://, https://, socks5:// and socks5h:// (which resolves hostnames through the proxy) work. Can be repeated to rotate requests across several.")]
+    proxy: Vec<String>,
+
+    #[arg(long, help = "A file of proxies to rotate requests across, one per line. Blank lines and # comments are ignored.")]
+    proxy_file: Option<PathBuf>,
+
+    #[arg(short, long, help = "The table used for relations", default_value = "containslink")]
+    relate_table: String,
+
+    #[arg(long, help = "An XML sitemap (or sitemap index, optionally gzipped) whose urls are crawled alongside the initial site")]
+    sitemap: Option<String>,
+
+    #[arg(long, help = "Treat urls that only differ by #fragment as different pages")]
+    keep_fragments: bool,
+
+    #[arg(long, help = "Remove tracking query parameters (utm_*, fbclid, gclid, ...) from urls, along with any extra names given. Comma separated.", num_args = 0.., value_delimiter = ',', value_name = "EXTRA_PARAMS")]
+    strip_params: Option<Vec<String>>,
+
+    #[arg(long, help = "Remove the whole query string from every url")]
+    strip_all_params: bool,
+
+    #[arg(long, help = "Don't normalize urls (host case, default ports, duplicate slashes, percent-encoding) before comparing them")]
+    no_normalize: bool,
+
+    #[arg(long, help = "Treat /a/ and /a as the same page when there's no query string", conflicts_with = "no_normalize")]
+    fold_trailing_slashes: bool,
+
+    #[arg(long, help = "The maximum link depth to fetch from the initial site. Pages past this are recorded but not fetched. 0 only fetches the initial site.")]
+    max_depth: Option<u32>,
+
+    #[arg(long, help = "The maximum number of pages to fetch. Pages discovered after this is reached are not fetched.")]
+    max_pages: Option<usize>,
+
+    #[arg(long, help = "The maximum number of pages to fetch from any one host, so a single big site can't use up --max-pages. Pages past this are recorded but not fetched.")]
+    max_pages_per_domain: Option<usize>,
+
+    #[arg(long, help = "Count --max-pages-per-domain by registrable domain (example.co.uk) whatever --scope is, so subdomains share a cap", requires = "max_pages_per_domain")]
+    by_registrable_domain: bool,
+
+    #[arg(long, help = "Only fetch pages on the same site as one of the initial sites, going by --scope. Links to other sites are still recorded.")]
+    same_domain: bool,
+
+    #[arg(long, help = "What counts as the same site, for --same-domain, marking links as external, and --max-pages-per-domain. host is the exact host, domain is the registrable domain (example.co.uk) so subdomains are included, and custom is whatever --allow-domain and --deny-domain let through.", value_enum, default_value = "host")]
+    scope: Scope,
+
+    #[arg(long, help = "Only crawl hosts matching this pattern (e.g. `example.com` or `*.example.com`). Can be repeated.")]
+    allow_domain: Vec<DomainPattern>,
+
+    #[arg(long, help = "Never crawl hosts matching this pattern (e.g. `ads.example.com` or `*.example.com`). Can be repeated.")]
+    deny_domain: Vec<DomainPattern>,
+
+    #[arg(long, group = "url_patterns", help = "Only crawl urls matching one of these regular expressions (seeds are always crawled). Like --exclude it can match anywhere in the url. Checked before --exclude, so an excluded url is skipped even if it's included. Can be repeated.")]
+    include: Vec<Regex>,
+
+    #[arg(long, group = "url_patterns", help = "Never crawl urls matching this regular expression. It can match anywhere in the url, use ^ and $ to anchor it. Can be repeated.")]
+    exclude: Vec<Regex>,
+
+    #[arg(long, help = "Still record urls skipped by --include or --exclude as nodes (and links to them as relations), with excluded = true", requires = "url_patterns")]
+    record_excluded: bool,
+
+    #[arg(long, help = "More file extensions to never download, on top of the built-in list of images, media, archives and the like. Comma separated or repeated.", value_delimiter = ',')]
+    skip_ext: Vec<String>,
+
+    #[arg(long, help = "File extensions to take off the list of ones never downloaded. Comma separated or repeated.", value_delimiter = ',')]
+    allow_ext: Vec<String>,
+
+    #[arg(long, help = "Download urls whatever their file extension, leaving it to the content type check (or --head-precheck) to skip binary files", conflicts_with_all = ["skip_ext", "allow_ext"])]
+    no_ext_filter: bool,
+
+    #[arg(long, help = "Still record urls skipped by --allow-domain or --deny-domain as nodes (and links to them as relations), with external = true. They're never fetched. Urls off the --same-domain hosts are always recorded this way.")]
+    record_external: bool,
+
+    #[arg(long, help = "Record links with schemes other than http and https, like mailto:, tel: or javascript:, as nodes (and the links to them as relations). They're never fetched, only counted in the summary.")]
+    record_other_schemes: bool,
+
+    #[arg(long, help = "Fetch pages even if the site's robots.txt disallows it")]
+    ignore_robots: bool,
+
+    #[arg(long, help = "Follow links on pages even if a <meta name=\"robots\"> tag or X-Robots-Tag header says nofollow")]
+    ignore_meta_robots: bool,
+
+    #[arg(long, help = "The minimum delay in milliseconds between requests to the same host. A longer robots.txt Crawl-delay wins.")]
+    delay_ms: Option<u64>,
+
+    #[arg(long, help = "The longest robots.txt Crawl-delay in seconds to honor. Hosts asking for more are skipped instead of waited on.", default_value_t = 30)]
+    max_crawl_delay: u64,
+
+    #[arg(long, help = "How many failed fetches in a row a host gets before each further one makes it cool down, starting at 1s and doubling up to 60s. A success resets it.", default_value_t = 3)]
+    host_backoff_after: u32,
+
+    #[arg(long, help = "How many failed fetches in a row before a host is given up on for the rest of the crawl, its remaining urls skipped", default_value_t = 10)]
+    bench_host_after: u32,
+
+    #[arg(long, help = "The longest Retry-After on a 429 or 503 to wait out, like `90s` or `5m`. Hosts asking for more are given up on for the rest of the crawl.", value_parser = parse_duration, default_value = "300s")]
+    max_retry_after: Duration,
+
+    #[arg(long, help = "The number of crawl workers, i.e. the maximum number of pages fetched at once", default_value_t = 16)]
+    concurrency: usize,
+
+    #[arg(long, help = "The maximum number of pages fetched at once from the same host", default_value_t = 2)]
+    per_domain_concurrency: usize,
+
+    #[arg(long, help = "Which queued url to crawl next. bfs pairs with --max-depth and --max-pages to get a shallow sample of the whole site.", value_enum, default_value = "bfs")]
+    strategy: Strategy,
+
+    #[arg(long, help = "The most requests per second to send overall, spread out evenly. Can be fractional, like 0.5 for one every 2 seconds.", value_parser = parse_rate)]
+    rate: Option<f64>,
+
+    #[arg(long, help = "The timeout in seconds for a whole request", value_parser = clap::value_parser!(u64).range(1..), default_value_t = 30)]
+    timeout: u64,
+
+    #[arg(long, help = "The timeout in seconds for connecting to a host", value_parser = clap::value_parser!(u64).range(1..), default_value_t = 10)]
+    connect_timeout: u64,
+
+    #[arg(long, help = "How many redirects to follow in a row, 0 for none. A redirect that isn't followed, or that leaves the domains and urls being crawled, is recorded as a redirect to its target, which gets crawled (or filtered) like any other link.", default_value_t = 10)]
+    max_redirects: usize,
+
+    #[arg(long, help = "How many times to retry a request after a connection error, timeout, or 5xx response, and how many times to try again later after a page is rate limited", default_value_t = 2)]
+    retries: u32,
+
+    #[arg(long, help = "The User-Agent header sent with every request. Its product name is also what robots.txt rules are matched against.", default_value = DEFAULT_USER_AGENT)]
+    user_agent: String,
+
+    #[arg(long = "header", help = "An extra header sent with every request, formatted as `Name: value`. Can be repeated.", value_parser = parse_header)]
+    headers: Vec<(HeaderName, HeaderValue)>,
+
+    #[arg(long, help = "Keep cookies set by responses and send them back on later requests")]
+    cookies: bool,
+
+    #[arg(long, help = "A Netscape-format cookies.txt to load cookies from and save them back to at the end of the run. Implies --cookies.")]
+    cookies_file: Option<PathBuf>,
+
+    #[arg(long, help = "Send a HEAD request first and skip downloading pages that aren't text or are too large")]
+    head_precheck: bool,
+
+    #[arg(long, help = "The largest Content-Length in bytes that --head-precheck allows through", default_value_t = 10 * 1024 * 1024)]
+    head_max_bytes: u64,
+
+    #[arg(long, help = "The most bytes of a response body to download. Bigger pages are cut off there, and links are taken from what was read.", default_value_t = 10 * 1024 * 1024)]
+    max_body_bytes: u64,
+
+    #[arg(long, help = "Extra content types to parse for links besides text/*, JSON and XML, e.g. `application/x-ndjson` or `font/*`. Comma separated or repeated.", value_delimiter = ',')]
+    content_types: Vec<String>,
+
+    #[arg(long, help = "Still parse pages for links when they respond with an error status (4xx/5xx)")]
+    parse_errors: bool,
+
+    #[arg(long, help = "Don't follow links marked rel=\"nofollow\", \"ugc\", or \"sponsored\"")]
+    skip_nofollow: bool,
+
+    #[arg(long, help = "Still record links skipped by --skip-nofollow, with nofollow = true on the relation", requires = "skip_nofollow")]
+    record_nofollow: bool,
+
+    #[arg(long, help = "The table used for redirect relations", default_value = "redirectsto")]
+    redirect_table: String,
+
+    #[arg(long, help = "The table used for relations from a page to its declared canonical url", default_value = "canonicalof")]
+    canonical_table: String,
+
+    #[arg(long, help = "Treat a page's canonical url as already visited, so pages sharing a canonical url are only parsed once")]
+    follow_canonical: bool,
+
+    #[arg(long, help = "Don't parse pages whose body is exactly the same as one already parsed this crawl, so copies at other urls don't fan out the same links again. Every page's content_hash is recorded either way, and copies get a duplicate_of pointing at the first one.")]
+    skip_duplicate_content: bool,
+
+    #[arg(long, help = "Keep the body of every text page that's parsed, as the text it was decoded to (so no more than --max-body-bytes of it). Identical bodies are only kept once. `findconn query show --content` prints a page's.", value_enum)]
+    store_content: Option<StoreContent>,
+
+    #[arg(long, help = "Record the text a reader would see of each page that's parsed in its node's text field: HTML without its scripts, styles, navigation and hidden parts, with whitespace collapsed, and other text (besides XML) as it is. With --define-schema, the field gets a full-text index once the crawl is done.")]
+    extract_text: bool,
+
+    #[arg(long, help = "The most chars of a page's text --extract-text keeps", default_value_t = 100_000)]
+    max_text_chars: usize,
+
+    #[arg(long, help = "The table --store-content inline keeps bodies in, with the content hash as the record id", default_value = "content")]
+    content_table: String,
+
+    #[arg(long, help = "Record the email addresses on pages (mailto links and plain text) in --email-table. They're never fetched.")]
+    collect_emails: bool,
+
+    #[arg(long, help = "The table email addresses are stored in, with the address as the record id", default_value = "email")]
+    email_table: String,
+
+    #[arg(long, help = "The table used for relations from a page to the email addresses on it", default_value = "mentionsemail")]
+    email_relate_table: String,
+
+    #[arg(long, help = "Credentials for HTTP basic auth, formatted as `user:pass`", conflicts_with = "bearer_token")]
+    basic_auth: Option<String>,
+
+    #[arg(long, help = "A bearer token sent in the Authorization header")]
+    bearer_token: Option<String>,
+
+    #[arg(long, help = "Accept invalid and self-signed TLS certificates. Anyone in the middle can read and change what's fetched, so only use this on networks you trust.")]
+    insecure: bool,
+
+    #[arg(long, help = "Fetch pages on loopback, private (10.x, 192.168.x, ...), link-local and unique-local addresses. These are refused by default so links on untrusted pages can't reach internal hosts.")]
+    allow_private: bool,
+
+    #[arg(long, help = "A PEM file of one or more extra CA certificates to trust, e.g. for a private CA. Can be repeated.", value_name = "PEM_PATH")]
+    ca_cert: Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Backend {
+    /// A SurrealDB database, embedded or on a server.
+    Surrealdb,
+    /// nodes.ndjson and edges.ndjson, or .csv with --file-format csv.
+    Files,
+    /// A SQLite file, with `sites` and `edges` tables.
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum StoreContent {
+    /// In --content-table, in the db.
+    Inline,
+    /// As files in the content directory inside --output, with each page's content_path pointing at its file.
+    Dir,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormat {
+    /// Human-readable lines.
+    Text,
+    /// One JSON object per line, with each event's fields kept separate.
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Writes a crawl's graph out in a format other tools understand.
+    Export(ExportArgs),
+    /// Looks things up in a crawl's results.
+    Query(QueryArgs),
+    /// Summarizes problems found by a crawl.
+    Report(ReportArgs),
+    /// Compares two crawls of the same site, from two runs into one db or two dbs.
+    Diff(DiffArgs),
+    /// Works with `--config` files.
+    Config(ConfigArgs),
+}
+
+/// Where a previous crawl was written to, for the subcommands that read one back.
+#[derive(Args)]
+struct DbArgs {
+    #[arg(short, long, help = "The DB directory (or SurrealDB server url) the crawl was written to")]
+    output: String,
+
+    #[arg(long, help = "The user to sign in to a remote SurrealDB server as", env = "SURREAL_USER", requires = "db_pass")]
+    db_user: Option<String>,
+
+    #[arg(long, help = "The password for --db-user", env = "SURREAL_PASS", hide_env_values = true, requires = "db_user")]
+    db_pass: Option<String>,
+
+    #[arg(short, long, help = "The namespace the crawl used", default_value = "findconn")]
+    ns: String,
+
+    #[arg(short, long, help = "The database name the crawl used. If neither this nor --url is given, the namespace's only database is used.")]
+    db: Option<String>,
+
+    #[arg(short, long, help = "The initial site of the crawl, to work out the default `tree-{url}` database name")]
+    url: Option<String>,
+
+    #[arg(short, long, help = "The table the urls were stored in", default_value = "site")]
+    table: String,
 
     #[arg(short, long, help = "The table used for relations", default_value = "containslink")]
     relate_table: String,
 }
 
-struct AppState {
-    db: Surreal<Db>,
-    request_client: Client,
-    link_finder: LinkFinder,
+impl DbArgs {
+    async fn connect(&self) -> anyhow::Result<Surreal<Any>> {
+        db::connect(&self.output, self.db_user.clone().zip(self.db_pass.clone())).await
+    }
+
+    async fn open(&self) -> anyhow::Result<Surreal<Any>> {
+        let db = self.connect().await?;
+        let name = self.db.clone().or_else(|| self.url.as_deref().map(default_db_name));
+        db::use_existing(&db, &self.ns, name).await?;
+
+        Ok(db)
+    }
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    #[command(flatten)]
+    db: DbArgs,
+
+    #[arg(long, help = "The format to write", value_enum, default_value = "dot")]
+    format: ExportFormat,
+
+    #[arg(long, help = "Whether --format json writes one object per node or one per relation", value_enum, default_value = "adjacency")]
+    shape: JsonShape,
+
+    #[arg(long, help = "Where to write the export. Defaults to stdout.")]
+    file: Option<PathBuf>,
+
+    #[arg(long, help = "Also write every node and its metadata as CSV to this file")]
+    nodes_csv: Option<PathBuf>,
+
+    #[arg(long, help = "Only export this many nodes (and the edges between them), for graphs too big to render")]
+    max_nodes: Option<usize>,
+
+    #[command(flatten)]
+    scope: LinkScopeArgs,
+}
+
+#[derive(Args)]
+struct LinkScopeArgs {
+    #[arg(long, help = "Only include relations between pages on the same site, going by the --scope they were crawled with", conflicts_with = "external_only")]
+    internal_only: bool,
+
+    #[arg(long, help = "Only include relations that leave the site of the page they're on, going by the --scope they were crawled with")]
+    external_only: bool,
+}
+
+impl LinkScopeArgs {
+    /// What `external` has to be on a relation for it to be included, if they aren't all.
+    fn external(&self) -> Option<bool> {
+        if self.internal_only {
+            Some(false)
+        } else if self.external_only {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Args)]
+struct QueryArgs {
+    #[command(flatten)]
+    db: DbArgs,
+
+    #[command(subcommand)]
+    query: Query,
+}
+
+#[derive(Subcommand)]
+enum Query {
+    /// Lists crawled urls and what was recorded about them.
+    List(ListArgs),
+    /// Finds the shortest chain of links from one page to another.
+    Path(PathArgs),
+    /// Lists the pages with the most links pointing at them.
+    Top(TopArgs),
+    /// Lists the crawls that were run into the db, newest first, with how they were set up and how they ended.
+    Runs(RunsArgs),
+    /// Prints everything recorded about one url, or the body that was stored for it.
+    Show(ShowArgs),
+}
+
+#[derive(Args)]
+struct ListArgs {
+    #[arg(long = "match", help = "Only list urls containing this")]
+    pattern: Option<String>,
+
+    #[arg(long, help = "Treat --match as a regular expression", requires = "pattern")]
+    regex: bool,
+
+    #[arg(long, help = "Only list urls that responded with this status")]
+    status: Option<u16>,
+
+    #[arg(long, help = "Only list urls that were fetched, or only ones that were just recorded", value_enum)]
+    kind: Option<NodeKind>,
+
+    #[arg(long, help = "List at most this many urls")]
+    limit: Option<usize>,
+
+    #[arg(long, help = "Print a JSON array instead of a table")]
+    json: bool,
+}
+
+#[derive(Args)]
+struct PathArgs {
+    #[arg(long, help = "The url to start from")]
+    from: String,
+
+    #[arg(long, help = "The url to get to")]
+    to: String,
+
+    #[arg(long, help = "Give up after following this many links", default_value_t = 20)]
+    max_hops: usize,
+
+    #[arg(long, help = "Print every shortest path instead of just one")]
+    all: bool,
+}
+
+#[derive(Args)]
+struct TopArgs {
+    #[arg(long, help = "How many pages to list", default_value_t = 20)]
+    limit: usize,
+
+    #[arg(long, help = "Count a relation once for every time the page linked it, instead of just once")]
+    weighted: bool,
+
+    #[command(flatten)]
+    scope: LinkScopeArgs,
+
+    #[arg(long, help = "Print JSON instead of a table")]
+    json: bool,
+}
+
+#[derive(Args)]
+struct RunsArgs {
+    #[arg(long, help = "List at most this many runs")]
+    limit: Option<usize>,
+
+    #[arg(long, help = "Print JSON, including each run's settings and full summary, instead of a table")]
+    json: bool,
+}
+
+#[derive(Args)]
+struct ShowArgs {
+    #[arg(help = "The url to show")]
+    url: String,
+
+    #[arg(long, help = "Print the page's body as --store-content kept it, instead of the node")]
+    content: bool,
+
+    #[arg(long, help = "The table the crawl's --store-content inline kept bodies in", default_value = "content")]
+    content_table: String,
+}
+
+#[derive(Args)]
+struct ReportArgs {
+    #[command(flatten)]
+    db: DbArgs,
+
+    #[command(subcommand)]
+    report: Report,
+}
+
+#[derive(Subcommand)]
+enum Report {
+    /// Lists urls that responded with 404, 410 or a 5xx, grouped with the pages linking to them.
+    Broken(BrokenArgs),
+    /// Lists the groups of pages that had exactly the same content, biggest first.
+    Duplicates(DuplicatesArgs),
+}
+
+#[derive(Args)]
+struct BrokenArgs {
+    #[arg(long, help = "Also list urls that couldn't be fetched at all (dns, timeouts, ...)")]
+    include_errors: bool,
+
+    #[arg(long, help = "Only list broken urls on the same host as a page linking to them")]
+    internal_only: bool,
+
+    #[arg(long, help = "How to print the report", value_enum, default_value = "table")]
+    format: ReportFormat,
+}
+
+#[derive(Args)]
+struct DuplicatesArgs {
+    #[arg(long, help = "How to print the report", value_enum, default_value = "table")]
+    format: ReportFormat,
+}
+
+#[derive(Args)]
+#[command(group = ArgGroup::new("compare").required(true).args(["from", "from_db"]))]
+struct DiffArgs {
+    #[command(flatten)]
+    db: DbArgs,
+
+    #[arg(long, help = "The earlier run to compare, with its id as `query runs` lists it. Everything it and the runs before it created counts as its crawl.", requires = "to", conflicts_with = "from_db")]
+    from: Option<String>,
+
+    #[arg(long, help = "The later run to compare", requires = "from")]
+    to: Option<String>,
+
+    #[arg(long, help = "The database in --ns with the earlier crawl, to compare crawls written to separate databases instead of runs", requires = "to_db", conflicts_with_all = ["db", "url"])]
+    from_db: Option<String>,
+
+    #[arg(long, help = "The database in --ns with the later crawl", requires = "from_db")]
+    to_db: Option<String>,
+
+    #[arg(long, help = "List at most this many of each kind of change")]
+    limit: Option<usize>,
+
+    #[arg(long, help = "Only print how many of each kind of change there are")]
+    summary_only: bool,
+
+    #[arg(long, help = "Print JSON, with the counts alongside the changes")]
+    json: bool,
+}
+
+#[derive(Args)]
+struct ConfigArgs {
+    #[command(subcommand)]
+    config: ConfigCommand,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Writes out an example config file with every setting at its default.
+    Init(InitArgs),
+}
+
+#[derive(Args)]
+struct InitArgs {
+    #[arg(help = "Where to write it. Printed to stdout if not given.")]
+    path: Option<PathBuf>,
+
+    #[arg(long, help = "Overwrite the file if it already exists")]
+    force: bool,
+}
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s.parse().map_err(|err| format!("invalid rate {s:?}: {err}"))?;
+
+    if !rate.is_finite() || rate <= 0.0 {
+        return Err(format!("the rate has to be a positive number, got {s:?}"));
+    }
+
+    Ok(rate)
+}
+
+/// Parses a duration like `90s`, `30m`, `12h` or `7d`.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("expected a number followed by s, m, h or d, got {s:?}"))?;
+    let unit_secs = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown unit {unit:?} in {s:?}, expected s, m, h or d")),
+    };
+
+    Ok(Duration::from_secs(amount.saturating_mul(unit_secs)))
+}
+
+/// Checks that `s` is something [`EnvFilter`] understands, keeping it as a string since filters can't be cloned.
+fn parse_log_filter(s: &str) -> Result<String, String> {
+    EnvFilter::try_new(s)
+        .map(|_| s.to_owned())
+        .map_err(|err| format!("invalid log filter {s:?}: {err}"))
+}
+
+/// The filter given for one of the log outputs, or RUST_LOG, or just info.
+fn log_filter(filter: Option<&str>) -> EnvFilter {
+    match filter {
+        Some(filter) => EnvFilter::new(filter),
+        None => EnvFilter::try_from_default_env().unwrap_or(EnvFilter::from("INFO")),
+    }
+}
+
+fn parse_header(s: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected `Name: value`, got {s:?}"))?;
+
+    let name = HeaderName::try_from(name.trim())
+        .map_err(|err| format!("invalid header name {name:?}: {err}"))?;
+    let value = HeaderValue::try_from(value.trim())
+        .map_err(|err| format!("invalid header value for {name}: {err}"))?;
+
+    Ok((name, value))
+}
+
+/// Reads every certificate out of each PEM file given to `--ca-cert`.
+fn load_ca_certs(paths: &[PathBuf]) -> anyhow::Result<Vec<Certificate>> {
+    let mut certs = Vec::new();
+
+    for path in paths {
+        let pem = fs::read(path).with_context(|| format!("Failed to read CA certificate file {path:?}"))?;
+        let found = Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("Failed to load CA certificates from {path:?}"))?;
+
+        if found.is_empty() {
+            anyhow::bail!("No certificates found in {path:?}");
+        }
+
+        info!("Trusting {} CA certificates from {path:?}", found.len());
+        certs.extend(found);
+    }
+
+    Ok(certs)
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_default_env()
-            .unwrap_or(EnvFilter::from("INFO"))
-        )
-        .init();
-
-    let args = Cli::parse();
-
-    trace!("Setting up SurrealDB");
-    let db = Surreal::new::<SurrealKV>(args.output).await?;
-    
-    let db_name = if let Some(db_name) = args.db {
-        db_name
-    } else {
-        format!("tree-{}", args.url)
+    let (args, settings) = config::parse()?;
+
+    let status_bar = progress::status_bar(args.progress && io::stderr().is_terminal());
+
+    // stdout is for exports.
+    let stderr_layer = fmt::layer().with_writer(LogWriter(status_bar.clone()));
+    let stderr_layer = match args.log_format {
+        LogFormat::Text => stderr_layer.boxed(),
+        LogFormat::Json => stderr_layer.json().boxed(),
     };
-    info!("Using db name: {db_name:?}");
 
-    db.use_ns(args.ns).use_db(db_name).await?;
+    // held until main returns, which is what gets the last lines written out.
+    let mut _log_file_guard = None;
+    let file_layer = match &args.log_file {
+        Some(path) => {
+            let (log_file, guard) = LogFile::open(path, args.log_rotate)?;
+            _log_file_guard = Some(guard);
 
-    trace!("SurrealDB setup successfully");
+            let file_layer = fmt::layer().with_writer(log_file).with_ansi(false);
+            let file_layer = match args.log_format {
+                LogFormat::Text => file_layer.boxed(),
+                LogFormat::Json => file_layer.json().boxed(),
+            };
+            Some(file_layer.with_filter(log_filter(args.log_file_level.as_deref())))
+        }
+        None => None,
+    };
+
+    // held until main returns too, so the spans still waiting to go out are sent.
+    #[cfg(feature = "otlp")]
+    let mut _otlp_guard = None;
+    #[cfg(feature = "otlp")]
+    let otlp_layer = match &args.trace_otlp {
+        Some(endpoint) => {
+            let (layer, guard) = otlp::layer(endpoint).with_context(|| format!("Failed to set up exporting spans to {endpoint:?}"))?;
+            _otlp_guard = Some(guard);
+            Some(layer.with_filter(log_filter(None)))
+        }
+        None => None,
+    };
+
+    let registry = tracing_subscriber::registry()
+        .with(stderr_layer.with_filter(log_filter(args.stderr_level.as_deref())))
+        .with(file_layer);
+    #[cfg(feature = "otlp")]
+    let registry = registry.with(otlp_layer);
+    registry.init();
 
-    let mut link_finder = LinkFinder::new();
-    link_finder.kinds(&[LinkKind::Url]);
+    if let Some(command) = args.command {
+        return match command {
+            Command::Export(args) => run_export(args).await,
+            Command::Query(args) => run_query(args).await,
+            Command::Report(args) => run_report(args).await,
+            Command::Diff(args) => run_diff(args).await,
+            Command::Config(args) => run_config(args),
+        };
+    }
+
+    if let Some(path) = &args.config {
+        info!("Using settings from {path:?}");
+    }
 
-    let mut client_builder = Client::builder();
+    // clap makes sure there's a url to start from when there's no subcommand and we're not resuming.
+    let mut seeds = args.url;
+    if let Some(path) = &args.url_file {
+        seeds.extend(seeds::read_url_file(path, args.strict)?);
+    }
+    let seeds = crawler::dedupe_seeds(seeds)?;
 
-    if let Some(proxy) = args.proxy {
-        client_builder = client_builder.proxy(Proxy::all(proxy)?);
+    if seeds.is_empty() && !args.resume {
+        return Err("None of the lines in --url-file are urls, so there's nothing to crawl".into());
     }
 
-    let state = Arc::new(AppState {
-        db,
-        request_client: client_builder.build()?,
-        link_finder,
+    // only a db that outlives the crawl is worth keeping a record of it in.
+    let mut runs_db = None;
+    let store: Arc<dyn GraphStore> = match args.backend {
+        Backend::Files => {
+            if args.resume || args.refresh {
+                return Err("--backend files can't --resume or --refresh, since nothing is read back out of its files".into());
+            }
+            let Some(output) = &args.output else {
+                return Err("--backend files needs --output to know which directory to write to".into());
+            };
+
+            info!("Writing the crawl to files in {output:?}");
+            Arc::new(FileStore::create(Path::new(output), args.file_format, args.force)?)
+        }
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => {
+            let Some(output) = &args.output else {
+                return Err("--backend sqlite needs --output to know which file to write to".into());
+            };
+            if (args.resume || args.refresh) && !Path::new(output).exists() {
+                return Err(format!("There's no crawl to pick back up in {output:?}").into());
+            }
+
+            info!("Writing the crawl to {output:?}");
+            Arc::new(SqliteStore::open(Path::new(output))?)
+        }
+        Backend::Surrealdb => {
+            trace!("Setting up SurrealDB");
+            let db = match &args.output {
+                Some(output) => db::connect(output, args.db_user.zip(args.db_pass)).await?,
+                None => db::connect_memory().await?,
+            };
+
+            let db_name = match (args.db, seeds.as_slice()) {
+                (Some(name), _) => Some(name),
+                // several seeds don't have one obvious name, and quietly picking one would make the db hard to find again.
+                (None, [_, _, ..]) if !args.dry_run => return Err("--db is required when crawling more than one --url".into()),
+                (None, seeds) => seeds.first().map(|seed| default_db_name(seed)),
+            };
+            if args.resume || args.refresh {
+                // resuming into a fresh db would just do nothing, so make sure it's the crawl we think it is.
+                db::use_existing(&db, &args.ns, db_name).await?;
+            } else {
+                let db_name = db_name.unwrap();
+                info!("Using db name: {db_name:?}");
+                db.use_ns(args.ns).use_db(db_name).await?;
+            }
+
+            if args.output.is_some() {
+                runs_db = Some(db.clone());
+            }
+
+            trace!("SurrealDB setup successfully");
+            Arc::new(SurrealStore::new(db))
+        }
+    };
+
+    let store_content = match args.store_content {
+        Some(StoreContent::Inline) if matches!(args.backend, Backend::Files) => {
+            return Err("--store-content inline needs a db to put the content in, use --store-content dir with --backend files".into());
+        }
+        #[cfg(feature = "sqlite")]
+        Some(StoreContent::Dir) if matches!(args.backend, Backend::Sqlite) => {
+            return Err("--store-content dir needs --output to be a directory, use --store-content inline with --backend sqlite".into());
+        }
+        Some(StoreContent::Inline) => Some(ContentStorage::Inline(args.content_table)),
+        Some(StoreContent::Dir) => match &args.output {
+            Some(output) if !db::is_remote(output) => Some(ContentStorage::Dir(Path::new(output).join("content"))),
+            _ => return Err("--store-content dir needs --output to be a local directory to put the content directory in".into()),
+        },
+        None => None,
+    };
+
+    let cookie_jar = if args.cookies || args.cookies_file.is_some() {
+        let store = match &args.cookies_file {
+            Some(path) if path.exists() => cookies::load_netscape(path)?,
+            _ => Default::default(),
+        };
+
+        Some(Arc::new(CookieStoreMutex::new(store)))
+    } else {
+        None
+    };
+
+    let mut proxies = args.proxy;
+    if let Some(path) = &args.proxy_file {
+        proxies.extend(proxies::read_proxy_file(path)?);
+    }
+
+    let mut builder = Crawler::builder()
+        .seeds(seeds.clone())
+        .sitemap(args.sitemap)
+        .resume(args.resume)
+        .refresh(args.refresh.then(|| args.older_than.unwrap_or_default()))
+        .prune_stale_edges(args.prune_stale_edges)
+        .define_schema(args.define_schema)
+        .table(args.table.clone())
+        .relate_table(args.relate_table)
+        .redirect_table(args.redirect_table)
+        .canonical_table(args.canonical_table)
+        .frontier_table(args.frontier_table)
+        .email_table(args.email_table)
+        .email_relate_table(args.email_relate_table)
+        .strategy(args.strategy)
+        .concurrency(args.concurrency)
+        .per_host_concurrency(args.per_domain_concurrency)
+        .max_depth(args.max_depth)
+        .max_pages(args.max_pages)
+        .max_pages_per_domain(args.max_pages_per_domain, args.by_registrable_domain)
+        .same_domain(args.same_domain)
+        .scope(args.scope)
+        .include(args.include)
+        .exclude(args.exclude)
+        .record_excluded(args.record_excluded)
+        .allow_domains(args.allow_domain)
+        .deny_domains(args.deny_domain)
+        .record_external(args.record_external)
+        .record_other_schemes(args.record_other_schemes)
+        .extension_filter(!args.no_ext_filter)
+        .skip_extensions(args.skip_ext)
+        .allow_extensions(args.allow_ext)
+        .normalize(!args.no_normalize)
+        .fold_trailing_slashes(args.fold_trailing_slashes)
+        .keep_fragments(args.keep_fragments)
+        .strip_tracking_params(args.strip_params)
+        .strip_all_params(args.strip_all_params)
+        .robots(!args.ignore_robots, Duration::from_secs(args.max_crawl_delay))
+        .meta_robots(!args.ignore_meta_robots)
+        .delay(Duration::from_millis(args.delay_ms.unwrap_or(0)))
+        .rate(args.rate)
+        .retries(args.retries)
+        .host_errors(args.host_backoff_after, args.bench_host_after)
+        .max_retry_after(args.max_retry_after)
+        .user_agent(args.user_agent)
+        .headers(HeaderMap::from_iter(args.headers))
+        .timeout(Duration::from_secs(args.timeout))
+        .connect_timeout(Duration::from_secs(args.connect_timeout))
+        .max_redirects(args.max_redirects)
+        .ca_certs(load_ca_certs(&args.ca_cert)?)
+        .insecure(args.insecure)
+        .allow_private(args.allow_private)
+        .proxies(proxies)
+        .head_precheck(args.head_precheck.then_some(args.head_max_bytes))
+        .max_body_bytes(args.max_body_bytes)
+        .content_types(args.content_types)
+        .parse_errors(args.parse_errors)
+        .skip_nofollow(args.skip_nofollow, args.record_nofollow)
+        .follow_canonical(args.follow_canonical)
+        .skip_duplicate_content(args.skip_duplicate_content)
+        .store_content(store_content)
+        .extract_text(args.extract_text.then_some(args.max_text_chars))
+        .collect_emails(args.collect_emails)
+        .emit(args.emit)
+        .count_host_pages(args.dry_run);
+
+    if let Some(jar) = &cookie_jar {
+        builder = builder.cookies(jar.clone());
+    }
+    match (args.basic_auth, args.bearer_token) {
+        (Some(basic), _) => {
+            builder = match basic.split_once(':') {
+                Some((user, pass)) => builder.basic_auth(user, Some(pass.to_owned())),
+                None => builder.basic_auth(basic, None),
+            };
+        }
+        (None, Some(token)) => builder = builder.bearer_token(token),
+        (None, None) => {}
+    }
+    if let Some(url) = args.webhook {
+        builder = builder.webhook(url, args.webhook_events);
+    }
+
+    #[cfg(feature = "metrics")]
+    let metrics_listener = match &args.metrics_addr {
+        Some(addr) => {
+            let listener = tokio::net::TcpListener::bind(addr)
+                .await
+                .with_context(|| format!("Failed to listen for metrics on {addr:?}"))?;
+            info!("Serving metrics on http://{}/metrics", listener.local_addr()?);
+            Some(listener)
+        }
+        None => None,
+    };
+
+    // the run goes in first so what the crawl creates can be stamped with it.
+    let run = match &runs_db {
+        Some(db) => {
+            let id = db::start_run(db, env!("CARGO_PKG_VERSION"), seeds, settings).await?;
+            info!("Recording this crawl as run {id}");
+            builder = builder.run(id.clone());
+            Some((db, id))
+        }
+        None => None,
+    };
+
+    let crawler = match builder.build(store) {
+        Ok(crawler) => crawler,
+        Err(err) => {
+            if let Some((db, id)) = run {
+                finish_run(db, id, Err(&err)).await;
+            }
+            return Err(err.into());
+        }
+    };
+    let handle = crawler.handle();
+
+    #[cfg(feature = "metrics")]
+    let metrics_server = metrics_listener.map(|listener| {
+        let handle = handle.clone();
+        tokio::spawn(metrics::serve(listener, move || handle.metrics()))
     });
 
-    info!("Scraping for site linkages ...");
-    discover_sites(args.table, args.relate_table, None, args.url, state).await?;
-    info!("Done scraping connections");
+    tokio::spawn(stop_on_ctrl_c(handle.clone()));
+    let reporter = args.progress.then(|| tokio::spawn(report_progress(handle.clone(), status_bar.clone())));
+
+    let result = crawler.crawl().await;
+    if let Some((db, id)) = run {
+        finish_run(db, id, result.as_ref()).await;
+    }
+    let summary = result?;
+
+    if let Some(reporter) = reporter {
+        reporter.abort();
+    }
+    #[cfg(feature = "metrics")]
+    if let Some(metrics_server) = metrics_server {
+        metrics_server.abort();
+    }
+    status_bar.finish_and_clear();
+
+    for line in summary.to_string().lines() {
+        info!("{line}");
+    }
+
+    // built once everything's in, since keeping it up to date page by page slows a crawl down several times over.
+    if args.extract_text && args.define_schema {
+        if let Some(db) = &runs_db {
+            info!("Indexing the pages' text for full-text search");
+            db::define_text_index(db, &args.table).await?;
+        }
+    }
+
+    if let Some(path) = &args.summary_json {
+        let mut file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut file, &summary)?;
+        writeln!(file)?;
+        info!("Wrote the summary to {path:?}");
+    }
+
+    if let Some(host_pages) = handle.host_pages() {
+        // stdout is --emit's stream when there is one.
+        let out: &mut dyn Write = if args.emit.is_some() { &mut io::stderr() } else { &mut io::stdout() };
+        print_dry_run_summary(&host_pages, &summary, out)?;
+    }
+
+    if let (Some(jar), Some(path)) = (cookie_jar, &args.cookies_file) {
+        cookies::save_netscape(&jar.lock().unwrap(), path)?;
+        info!("Saved cookies to {path:?}");
+    }
+
+    Ok(())
+}
+
+/// Fills in how the run `id` ended, going by what the crawl returned. Failing to is only logged, so it can't hide
+/// how the crawl itself went.
+async fn finish_run(db: &Surreal<Any>, id: Thing, result: Result<&Summary, &anyhow::Error>) {
+    let (outcome, summary, err) = match result {
+        Ok(summary) if summary.stopped_early => (RunOutcome::Interrupted, Some(summary.clone()), None),
+        Ok(summary) => (RunOutcome::Completed, Some(summary.clone()), None),
+        Err(err) => (RunOutcome::Error, None, Some(format!("{err:#}"))),
+    };
+
+    if let Err(err) = db::finish_run(db, id.clone(), outcome, summary, err).await {
+        error!("Failed to record how run {id} ended: {err:#}");
+    }
+}
+
+/// Lets the pages in flight finish on the first Ctrl-C, so the db is left in one piece and the crawl can be resumed.
+/// A second one quits right away.
+async fn stop_on_ctrl_c(handle: CrawlHandle) {
+    if tokio::signal::ctrl_c().await.is_err() {
+        return;
+    }
+
+    info!("Stopping once the pages in flight are done, press Ctrl-C again to quit now");
+    handle.stop();
+
+    if tokio::signal::ctrl_c().await.is_ok() {
+        error!("Quitting without waiting for the pages in flight");
+        std::process::exit(130);
+    }
+}
+
+/// Keeps `--progress` up to date, redrawing the status bar on a terminal and logging a status line every so often otherwise.
+async fn report_progress(handle: CrawlHandle, status_bar: ProgressBar) {
+    let every = if status_bar.is_hidden() { Duration::from_secs(10) } else { Duration::from_millis(250) };
+    let mut interval = tokio::time::interval(every);
+    // the first tick is immediate, and there's nothing to say yet.
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let status = handle.status();
+
+        if status_bar.is_hidden() {
+            info!("{status}");
+        } else {
+            status_bar.set_message(status.to_string());
+            status_bar.tick();
+        }
+    }
+}
+
+/// Prints what a `--dry-run` found, busiest hosts first.
+fn print_dry_run_summary(host_pages: &HashMap<String, usize>, summary: &Summary, out: &mut dyn Write) -> io::Result<()> {
+    let mut hosts: Vec<_> = host_pages.iter().collect();
+    hosts.sort_by(|(a_host, a_count), (b_host, b_count)| b_count.cmp(a_count).then_with(|| a_host.cmp(b_host)));
+
+    writeln!(
+        out,
+        "Would fetch {} pages from {} hosts, taking about {:.1}s ({} more found but not fetched)",
+        summary.pages_fetched,
+        hosts.len(),
+        summary.duration_secs,
+        summary.pages_unfetched,
+    )?;
+
+    let width = hosts.iter().map(|(host, _)| host.len()).max().unwrap_or(0);
+    for (host, count) in hosts {
+        writeln!(out, "  {host:<width$}  {count}")?;
+    }
+
+    Ok(())
+}
+
+/// The db a crawl of `url` goes in when `--db` isn't given.
+fn default_db_name(url: &str) -> String {
+    format!("tree-{url}")
+}
+
+async fn run_export(args: ExportArgs) -> Result<(), Box<dyn Error>> {
+    let db = args.db.open().await?;
+
+    let options = ExportOptions {
+        table: &args.db.table,
+        relate_table: &args.db.relate_table,
+        format: args.format,
+        shape: args.shape,
+        max_nodes: args.max_nodes,
+        external: args.scope.external(),
+    };
+
+    match args.file {
+        Some(path) => export::export(&db, &options, &mut BufWriter::new(File::create(&path)?)).await?,
+        None => export::export(&db, &options, &mut BufWriter::new(io::stdout())).await?,
+    }
+
+    if let Some(path) = args.nodes_csv {
+        export::export_nodes_csv(&db, &options, &mut BufWriter::new(File::create(&path)?)).await?;
+    }
 
     Ok(())
 }
 
-#[async_recursion]
-async fn discover_sites(table: String, relate_table: String, source: Option<SiteURLNode>, url: String, state: Arc<AppState>) -> anyhow::Result<()> {
-    // helps with comparing string urls
-    let url = Url::parse(&url)?.to_string();
-
-    // check to make sure site isnt already there.
-    let mut res = state.db
-        .query("SELECT id FROM type::table($table) WHERE url = $url LIMIT 1")
-        .bind(("url", url.clone()))
-        .bind(("table", table.clone()))
-        .await?;
-
-    let findings: Vec<Record> = res.take(0)?;
-    if !findings.is_empty() {
-        debug!("Found url that was already searched, skipping");
-        
-        if let Some(source) = source {
-            let mut res = state.db
-                .query(format!("RELATE $sourceid->{relate_table}->$currentid"))
-                .bind(("sourceid", source.id.unwrap()))
-                .bind(("currentid", findings[0].clone().id))
-                .await?;
-            
-            // TODO maybe res.take_errors()
-            let _: Option<Relation> = res.take(0)?;
-        }
-        return Ok(());
-    }
-
-    let mut obj: SiteURLNode = SiteURLNode::new(url.clone());
-    let newsource: Record = state.db
-        .create(&table)
-        .content(obj.clone())
-        .await?
-        .unwrap();
-    obj.id = Some(newsource.id.clone());
-
-    if let Some(source) = source {
-        let mut res = state.db
-            .query(format!("RELATE $sourceid->{relate_table}->$currentid"))
-            .bind(("sourceid", source.id.unwrap()))
-            .bind(("currentid", newsource.id))
-            .await?;
-
-        // TODO maybe res.take_errors()
-        let _: Option<Relation> = res.take(0)?;
-    }
-
-    // get content
-    let req = state.request_client
-        .get(&url)
-        .build()?;
-
-    let res = state.request_client.execute(req).await?;
-
-    let content_type = res.headers().get(CONTENT_TYPE)
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
-
-    const APPLICATION_TEXT_HEADERS: [&str; 5] = [
-        "application/json",
-        "application/xml",
-        "application/javascript",
-        "application/ld+json",
-        "application/xtml+xml",
-    ];
-    if !content_type.starts_with("text/") && !APPLICATION_TEXT_HEADERS.contains(&content_type) {
-        debug!("Url {url:?} is content-type {content_type:?}, expected \"text/*\". Ignoring.");
-        return Ok(());
-    }
-
-    let content = res.text().await?;
-    
-    let mut handles = Vec::new();
-    let links: HashSet<String> = state.link_finder.links(&content)
-        .map(|link| link.as_str().to_owned())
-        .collect();
-    for link in links {
-        debug!("Found url: {link}");
-    
-        let state2 = state.clone();
-        let obj2 = obj.clone();
-        let table2 = table.clone();
-        let relate_table2 = relate_table.clone();
-        handles.push(tokio::spawn(async move {
-            discover_sites(table2, relate_table2, Some(obj2), link, state2).await
-        }));
-    }
-
-    for h in handles {
-        let result = h.await?;
-        if let Err(err) = result {
-            // i don't think there's any fatal errors here so it's ok to just log it instead of bubbling it up.
-            error!("{err:?}");
+async fn run_query(args: QueryArgs) -> Result<(), Box<dyn Error>> {
+    let db = args.db.open().await?;
+
+    match args.query {
+        Query::List(list) => {
+            let regex = match (&list.pattern, list.regex) {
+                (Some(pattern), true) => Some(Regex::new(pattern)?),
+                _ => None,
+            };
+            let filter = ListFilter {
+                pattern: list.pattern.filter(|_| !list.regex),
+                regex,
+                status: list.status,
+                kind: list.kind,
+                limit: list.limit,
+            };
+
+            query::list(&db, &args.db.table, &filter, list.json, &mut BufWriter::new(io::stdout())).await?;
+        }
+        Query::Path(path) => {
+            let (from, to) = (normalize_query_url(&path.from), normalize_query_url(&path.to));
+
+            let paths = query::shortest_paths(&db, &args.db.table, &args.db.relate_table, (&from, &to), path.max_hops, path.all).await?;
+            if paths.is_empty() {
+                println!("No path from {from} to {to} within {} hops", path.max_hops);
+            }
+
+            for (i, hops) in paths.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                for (depth, url) in hops.iter().enumerate() {
+                    if depth == 0 {
+                        println!("{url}");
+                    } else {
+                        println!("  -> {url}");
+                    }
+                }
+            }
+        }
+        Query::Top(top) => {
+            let tables = (args.db.table.as_str(), args.db.relate_table.as_str());
+            query::top(&db, tables, top.limit, top.weighted, top.scope.external(), top.json, &mut BufWriter::new(io::stdout())).await?;
+        }
+        Query::Runs(runs) => {
+            query::runs(&db, runs.limit, runs.json, &mut BufWriter::new(io::stdout())).await?;
+        }
+        Query::Show(show) => {
+            let content_table = show.content.then_some(show.content_table.as_str());
+            query::show(&db, &args.db.table, &normalize_query_url(&show.url), content_table, &mut BufWriter::new(io::stdout())).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Normalizes a url typed in to look something up, since urls are stored normalized.
+fn normalize_query_url(url: &str) -> String {
+    let normalizer = UrlNormalizer {
+        normalize: true,
+        ..Default::default()
+    };
+
+    match Url::parse(url) {
+        Ok(mut url) => {
+            normalizer.apply(&mut url);
+            url.to_string()
         }
+        Err(_) => url.to_owned(),
+    }
+}
+
+async fn run_report(args: ReportArgs) -> Result<(), Box<dyn Error>> {
+    let db = args.db.open().await?;
+    let tables = (args.db.table.as_str(), args.db.relate_table.as_str());
+
+    match args.report {
+        Report::Broken(broken) => {
+            let options = BrokenOptions {
+                include_errors: broken.include_errors,
+                internal_only: broken.internal_only,
+                format: broken.format,
+            };
+
+            report::broken(&db, tables, &options, &mut BufWriter::new(io::stdout())).await?;
+        }
+        Report::Duplicates(duplicates) => {
+            report::duplicates(&db, &args.db.table, duplicates.format, &mut BufWriter::new(io::stdout())).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_diff(args: DiffArgs) -> Result<(), Box<dyn Error>> {
+    let tables = (args.db.table.as_str(), args.db.relate_table.as_str());
+
+    let (from, to) = match (&args.from, &args.to, &args.from_db, &args.to_db) {
+        (Some(from), Some(to), _, _) => {
+            let db = args.db.open().await?;
+            let from = diff::snapshot(&db, tables, Some(diff::run_id(from))).await?;
+            let to = diff::snapshot(&db, tables, Some(diff::run_id(to))).await?;
+            (from, to)
+        }
+        (_, _, Some(from_db), Some(to_db)) => {
+            let db = args.db.connect().await?;
+            db::use_existing(&db, &args.db.ns, Some(from_db.clone())).await?;
+            let from = diff::snapshot(&db, tables, None).await?;
+            db::use_existing(&db, &args.db.ns, Some(to_db.clone())).await?;
+            let to = diff::snapshot(&db, tables, None).await?;
+            (from, to)
+        }
+        // clap makes sure one pair or the other is given.
+        _ => unreachable!(),
+    };
+
+    let options = DiffOptions {
+        json: args.json,
+        limit: args.limit,
+        summary_only: args.summary_only,
+    };
+    diff::write(diff::diff(&from, &to), &options, &mut BufWriter::new(io::stdout()))?;
+
+    Ok(())
+}
+
+fn run_config(args: ConfigArgs) -> Result<(), Box<dyn Error>> {
+    match args.config {
+        ConfigCommand::Init(init) => match init.path {
+            Some(path) if path.exists() && !init.force => {
+                return Err(format!("{path:?} already exists, pass --force to overwrite it").into());
+            }
+            Some(path) => {
+                fs::write(&path, config::example())?;
+                eprintln!("Wrote an example config to {path:?}");
+            }
+            None => print!("{}", config::example()),
+        },
     }
 
     Ok(())
@@ -0,0 +1,142 @@
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+use tracing::debug;
+
+/// The upper bounds of [`Histogram`]'s buckets, in seconds.
+const BUCKETS: [f64; 10] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// How long requests take, bucketed the way Prometheus expects.
+#[derive(Default)]
+pub struct Histogram {
+    /// How many were at most each of [`BUCKETS`], not counting the smaller buckets.
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|&bound| secs <= bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+/// A snapshot of the crawl's counters, for `--metrics-addr`.
+pub struct Metrics<'a> {
+    pub pages_fetched: usize,
+    /// New links between pages.
+    pub links_discovered: usize,
+    pub errors_by_kind: BTreeMap<&'static str, usize>,
+    pub bytes_downloaded: u64,
+    /// Queued but not yet picked up by a worker.
+    pub frontier_size: usize,
+    pub in_flight_requests: usize,
+    pub fetch_durations: &'a Histogram,
+}
+
+impl fmt::Display for Metrics<'_> {
+    /// Prometheus' text exposition format.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_metric(f, "findconn_pages_fetched_total", "counter", "Pages fetched with a 2xx status.", self.pages_fetched)?;
+        write_metric(f, "findconn_links_discovered_total", "counter", "New links found between pages.", self.links_discovered)?;
+
+        writeln!(f, "# HELP findconn_fetch_errors_total Pages that failed to be crawled, by what went wrong.")?;
+        writeln!(f, "# TYPE findconn_fetch_errors_total counter")?;
+        for (kind, count) in &self.errors_by_kind {
+            writeln!(f, "findconn_fetch_errors_total{{kind=\"{kind}\"}} {count}")?;
+        }
+
+        write_metric(f, "findconn_bytes_downloaded_total", "counter", "Bytes of response bodies downloaded.", self.bytes_downloaded)?;
+        write_metric(f, "findconn_frontier_size", "gauge", "Urls queued to be crawled.", self.frontier_size)?;
+        write_metric(f, "findconn_in_flight_requests", "gauge", "Requests waiting on a response.", self.in_flight_requests)?;
+
+        let histogram = self.fetch_durations;
+        writeln!(f, "# HELP findconn_fetch_duration_seconds How long pages took to respond.")?;
+        writeln!(f, "# TYPE findconn_fetch_duration_seconds histogram")?;
+        let mut cumulative = 0;
+        for (bound, bucket) in BUCKETS.iter().zip(&histogram.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            writeln!(f, "findconn_fetch_duration_seconds_bucket{{le=\"{bound}\"}} {cumulative}")?;
+        }
+        let count = histogram.count.load(Ordering::Relaxed);
+        writeln!(f, "findconn_fetch_duration_seconds_bucket{{le=\"+Inf\"}} {count}")?;
+        writeln!(f, "findconn_fetch_duration_seconds_sum {}", histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1e6)?;
+        writeln!(f, "findconn_fetch_duration_seconds_count {count}")
+    }
+}
+
+fn write_metric(f: &mut fmt::Formatter<'_>, name: &str, kind: &str, help: &str, value: impl fmt::Display) -> fmt::Result {
+    writeln!(f, "# HELP {name} {help}")?;
+    writeln!(f, "# TYPE {name} {kind}")?;
+    writeln!(f, "{name} {value}")
+}
+
+/// Serves whatever `metrics` renders on `/metrics` until the task is aborted.
+///
+/// It's only ever scraped by Prometheus, so this is just enough HTTP for that: one request per connection,
+/// and only the request line is looked at.
+pub async fn serve(listener: TcpListener, metrics: impl Fn() -> String + Send + Sync + 'static) {
+    let metrics = Arc::new(metrics);
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                debug!(error = %err, "Failed to accept a metrics connection");
+                continue;
+            }
+        };
+
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            let mut stream = BufReader::new(stream);
+            let mut request_line = String::new();
+            // the headers aren't needed, but they have to be read off before answering.
+            let mut line = String::new();
+            let read = async {
+                stream.read_line(&mut request_line).await?;
+                while stream.read_line(&mut line).await? > 2 {
+                    line.clear();
+                }
+                Ok::<_, io::Error>(())
+            };
+            if tokio::time::timeout(Duration::from_secs(5), read).await.is_err() {
+                return;
+            }
+
+            let mut parts = request_line.split_whitespace();
+            let response = match (parts.next(), parts.next().and_then(|path| path.split('?').next())) {
+                (Some("GET"), Some("/metrics")) => {
+                    let body = metrics();
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len(),
+                    )
+                }
+                _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned(),
+            };
+
+            if let Err(err) = stream.get_mut().write_all(response.as_bytes()).await {
+                debug!(%peer, error = %err, "Failed to answer a metrics request");
+            }
+            let _ = stream.get_mut().shutdown().await;
+        });
+    }
+}
@@ -1,21 +1,160 @@
 use serde::{Deserialize, Serialize};
-use surrealdb::sql::Thing;
+use surrealdb::sql::{Datetime, Thing};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SiteURLNode {
     pub url: String,
     pub id: Option<Thing>,
+    /// The fewest links it took to get here from a seed.
+    pub depth: u32,
+    /// Filled in by the db when the node is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discovered_at: Option<Datetime>,
+    #[serde(default)]
+    pub robots_blocked: bool,
+    /// Whether it wasn't fetched because it's at a loopback, private, or link-local address.
+    #[serde(default)]
+    pub private_blocked: bool,
+    /// Whether it wasn't fetched because of `--include` or `--exclude`. Only recorded with `--record-excluded`.
+    #[serde(default)]
+    pub excluded: bool,
+    /// Whether it wasn't fetched because it's off the domains being crawled (`--same-domain`, or with `--record-external`).
+    #[serde(default)]
+    pub external: bool,
+    /// The HTTP status it responded with. `None` if it was never fetched.
+    pub status: Option<u16>,
+    pub title: Option<String>,
+    pub content_type: Option<String>,
+    /// How many bytes of body were actually read.
+    pub content_length: Option<u64>,
+    /// How long the request took, retries included.
+    pub fetch_ms: Option<u64>,
+    /// Whether the body was cut off at `--max-body-bytes`.
+    #[serde(default)]
+    pub truncated: bool,
+    /// Whether a meta robots tag or `X-Robots-Tag` header asked for it not to be indexed.
+    #[serde(default)]
+    pub noindex: bool,
+    /// Whether its Content-Type header or a sniff of its body decided if it was parsed: `header` or `sniff`.
+    pub type_decided_by: Option<String>,
+    /// The character encoding its body was decoded with, like `UTF-8` or `windows-1251`.
+    pub encoding: Option<String>,
+    /// What went wrong fetching it, cut down to a reasonable length.
+    pub error: Option<String>,
+    /// A coarse category for `error`: dns, connect, timeout, tls, http, or body.
+    pub error_kind: Option<String>,
+    pub failed_at: Option<Datetime>,
+    /// The validators it responded with, sent back by `--refresh` so an unchanged page can answer 304.
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// When it last responded at all, bumped even when a `--refresh` finds it unchanged.
+    pub last_checked_at: Option<Datetime>,
+    /// When its fetch was started. This goes in before the request does, so nothing is fetched twice across a `--resume`.
+    pub fetched_at: Option<Datetime>,
+    pub lastmod: Option<String>,
+    /// Where its redirects ended up, when they were followed. That page is also a node, related with a redirect.
+    pub final_url: Option<String>,
+    /// The SHA-256 of its decoded body, for pages that were parsed.
+    pub content_hash: Option<String>,
+    /// The first page found with the same `content_hash` in this crawl, if it wasn't this one.
+    pub duplicate_of: Option<Thing>,
+    /// The file its body was written to, with `--store-content dir`.
+    pub content_path: Option<String>,
+    /// What a reader would see of it with `--extract-text`: an HTML page's text without scripts, navigation and the
+    /// like, or the whole of other text, cut off at `--max-text-chars`.
+    pub text: Option<String>,
+    /// The run (in [`crate::db::RUNS_TABLE`]) that first found it, when the crawl was told its run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_seen_run: Option<Thing>,
 }
 
 impl SiteURLNode {
-    pub fn new(url: String) -> Self {
+    pub fn new(url: String, depth: u32) -> Self {
         Self {
             url,
             id: None,
+            depth,
+            discovered_at: None,
+            robots_blocked: false,
+            private_blocked: false,
+            excluded: false,
+            external: false,
+            status: None,
+            title: None,
+            content_type: None,
+            content_length: None,
+            fetch_ms: None,
+            truncated: false,
+            noindex: false,
+            type_decided_by: None,
+            encoding: None,
+            error: None,
+            error_kind: None,
+            failed_at: None,
+            etag: None,
+            last_modified: None,
+            last_checked_at: None,
+            fetched_at: None,
+            lastmod: None,
+            final_url: None,
+            content_hash: None,
+            duplicate_of: None,
+            content_path: None,
+            text: None,
+            first_seen_run: None,
         }
     }
 }
 
+/// What fetching a page found out about it, merged onto its node in one go. Fields that are `None` leave what the
+/// node already had alone.
+#[derive(Serialize, Default, Clone)]
+pub struct FetchInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_length: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fetch_ms: Option<u64>,
+    pub truncated: bool,
+    pub noindex: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_decided_by: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub final_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<Thing>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// A node's `ETag` and `Last-Modified` from the last time it was fetched.
+#[derive(Deserialize)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn any(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Record {
     pub id: Thing,
@@ -26,4 +165,44 @@ pub struct Relation {
     #[serde(rename = "in")]
     pub a_in: Thing,
     pub out: Thing,
-}
\ No newline at end of file
+    pub nofollow: Option<bool>,
+    pub text: Option<String>,
+    pub count: Option<u32>,
+    #[serde(default)]
+    pub external: bool,
+    pub run: Option<Thing>,
+}
+
+/// A relation from a page to the node for `url`, written by [`crate::db::record_page`].
+#[derive(Serialize)]
+pub struct PageLink {
+    pub url: String,
+    #[serde(flatten)]
+    pub link: LinkData,
+}
+
+/// The fields written onto a relation when it's created.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct LinkData {
+    pub nofollow: Option<bool>,
+    /// The anchor text of the first link to it.
+    pub text: Option<String>,
+    /// How many times the page linked to it.
+    pub count: Option<u32>,
+    /// Whether it points off the source page's registrable domain.
+    #[serde(default)]
+    pub external: bool,
+    /// The run that created the relation. Updates to it keep the one it already had.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run: Option<Thing>,
+}
+
+/// How a crawl ended, as recorded in its run.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunOutcome {
+    Completed,
+    /// Ctrl-C stopped it before the frontier ran out.
+    Interrupted,
+    Error,
+}
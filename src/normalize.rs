@@ -0,0 +1,221 @@
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+/// Query parameters removed by `--strip-params`. Anything starting with `utm_` is stripped too.
+pub const TRACKING_PARAMS: [&str; 12] = [
+    "fbclid",
+    "gclid",
+    "gclsrc",
+    "dclid",
+    "msclkid",
+    "yclid",
+    "igshid",
+    "mc_cid",
+    "mc_eid",
+    "_ga",
+    "_gl",
+    "_hsenc",
+];
+
+/// Everything that decides which urls count as the same page.
+#[derive(Debug, Clone, Default)]
+pub struct UrlNormalizer {
+    /// Whether to run [`normalize_url`] at all.
+    pub normalize: bool,
+    pub fold_trailing_slashes: bool,
+    pub keep_fragments: bool,
+    pub strip_params: Option<Vec<String>>,
+    pub strip_all_params: bool,
+}
+
+impl UrlNormalizer {
+    /// Puts `url` in the form used as its dedup key.
+    pub fn apply(&self, url: &mut Url) {
+        if !self.keep_fragments {
+            // fragments never reach the server, so they're the same page.
+            url.set_fragment(None);
+        }
+
+        if self.strip_all_params {
+            url.set_query(None);
+        } else if let Some(params) = &self.strip_params {
+            strip_query_params(url, params);
+        }
+
+        if self.normalize {
+            normalize_url(url, self.fold_trailing_slashes);
+        }
+    }
+}
+
+/// Rewrites `url` so that equivalent spellings of it become identical: lowercase scheme and host,
+/// no default port, no repeated slashes and canonical percent-encoding. `fold_trailing_slashes` also
+/// turns `/a/` into `/a` when there's no query.
+pub fn normalize_url(url: &mut Url, fold_trailing_slashes: bool) {
+    // the url crate already handles scheme case and default ports for http(s), but not for every scheme.
+    let scheme = url.scheme().to_ascii_lowercase();
+    if scheme != url.scheme() {
+        let _ = url.set_scheme(&scheme);
+    }
+
+    if let Some(host) = url.host_str() {
+        let host = host.to_ascii_lowercase();
+        if Some(host.as_str()) != url.host_str() {
+            let _ = url.set_host(Some(&host));
+        }
+    }
+
+    if url.port().is_some() && url.port() == default_port(url.scheme()) {
+        let _ = url.set_port(None);
+    }
+
+    if url.cannot_be_a_base() {
+        return;
+    }
+
+    let mut path = normalize_percent_encoding(url.path());
+    while path.contains("//") {
+        path = path.replace("//", "/");
+    }
+    if fold_trailing_slashes && url.query().is_none() && path.len() > 1 && path.ends_with('/') {
+        path.pop();
+    }
+    if path != url.path() {
+        url.set_path(&path);
+    }
+
+    if let Some(query) = url.query() {
+        let query = normalize_percent_encoding(query);
+        if Some(query.as_str()) != url.query() {
+            url.set_query(Some(&query));
+        }
+    }
+}
+
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        "ftp" => Some(21),
+        _ => None,
+    }
+}
+
+/// Decodes escapes of unreserved characters (`%7E` -> `~`) and uppercases the hex of the rest.
+fn normalize_percent_encoding(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = String::with_capacity(input.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+                    out.push(byte as char);
+                } else {
+                    out.push_str(&format!("%{byte:02X}"));
+                }
+                i += 3;
+                continue;
+            }
+        }
+
+        // everything else is already ascii by the time the url crate hands it to us.
+        out.push(bytes[i] as char);
+        i += 1;
+    }
+
+    out
+}
+
+/// Removes the named query parameters (and any `utm_*` ones), leaving the rest of the query untouched.
+fn strip_query_params(url: &mut Url, params: &[String]) {
+    let Some(query) = url.query() else {
+        return;
+    };
+
+    // working on the raw query instead of query_pairs keeps everything else byte-for-byte the same.
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let name = pair.split('=').next().unwrap_or("");
+            let name = percent_decode_str(name).decode_utf8_lossy();
+
+            !name.starts_with("utm_") && !params.iter().any(|param| *param == name)
+        })
+        .collect();
+
+    if kept.is_empty() {
+        url.set_query(None);
+    } else {
+        let kept = kept.join("&");
+        url.set_query(Some(&kept));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(input: &str, fold_trailing_slashes: bool) -> String {
+        let mut url = Url::parse(input).unwrap();
+        normalize_url(&mut url, fold_trailing_slashes);
+        url.to_string()
+    }
+
+    #[test]
+    fn normalizes_urls() {
+        let cases = [
+            ("HTTP://Example.COM/", "http://example.com/"),
+            ("https://EXAMPLE.com/Path", "https://example.com/Path"),
+            ("http://example.com:80/a", "http://example.com/a"),
+            ("https://example.com:443/a", "https://example.com/a"),
+            ("https://example.com:8443/a", "https://example.com:8443/a"),
+            ("https://example.com//a///b", "https://example.com/a/b"),
+            ("https://example.com/%7Euser/%41", "https://example.com/~user/A"),
+            ("https://example.com/a%2fb/", "https://example.com/a%2Fb/"),
+            ("https://example.com/a%2Fb/", "https://example.com/a%2Fb/"),
+            ("https://example.com/?q=%7e%2f", "https://example.com/?q=~%2F"),
+            ("https://example.com/a/", "https://example.com/a/"),
+            ("mailto:Someone@Example.com", "mailto:Someone@Example.com"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(normalized(input, false), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn folds_trailing_slashes() {
+        let cases = [
+            ("https://example.com/a/", "https://example.com/a"),
+            ("https://example.com/", "https://example.com/"),
+            ("https://example.com/a/?page=2", "https://example.com/a/?page=2"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(normalized(input, true), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn strips_tracking_params() {
+        let normalizer = UrlNormalizer {
+            normalize: true,
+            strip_params: Some(TRACKING_PARAMS.map(str::to_owned).to_vec()),
+            ..Default::default()
+        };
+        let cases = [
+            ("https://example.com/?utm_source=x&id=3&fbclid=y#top", "https://example.com/?id=3"),
+            ("https://example.com/?utm_medium=x", "https://example.com/"),
+            ("https://example.com/?b=2&a=1", "https://example.com/?b=2&a=1"),
+        ];
+
+        for (input, expected) in cases {
+            let mut url = Url::parse(input).unwrap();
+            normalizer.apply(&mut url);
+            assert_eq!(url.as_str(), expected, "{input:?}");
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Sends tracing spans to an OpenTelemetry collector, for `--trace-otlp`.
+
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use tracing::Subscriber;
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+/// Shuts the exporter down once it's dropped, which sends off the spans it's still holding on to.
+pub struct OtlpGuard(TracerProvider);
+
+impl Drop for OtlpGuard {
+    fn drop(&mut self) {
+        // logging is on its way out too by now, so this is the one place left to say so.
+        if let Err(err) = self.0.shutdown() {
+            eprintln!("Failed to send the last spans to the OTLP endpoint: {err}");
+        }
+    }
+}
+
+/// A layer that exports spans, batched up, to the OTLP/HTTP `endpoint`. Spans stop being sent when the guard is dropped.
+pub fn layer<S>(endpoint: &str) -> anyhow::Result<(impl Layer<S>, OtlpGuard)>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", "findconn")]))
+        .build();
+
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("findconn"));
+    Ok((layer, OtlpGuard(provider)))
+}
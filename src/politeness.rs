@@ -0,0 +1,304 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{self, Instant},
+};
+
+#[derive(Default)]
+struct HostState {
+    last_request: Option<Instant>,
+}
+
+/// Enforces a minimum gap between requests to the same host, and holds off hosts that asked for a break.
+pub struct Politeness {
+    delay: Duration,
+    /// Per-host delays from robots.txt's `Crawl-delay`, used when they're longer than `delay`.
+    crawl_delays: Mutex<HashMap<String, Duration>>,
+    hosts: Mutex<HashMap<String, Arc<tokio::sync::Mutex<HostState>>>>,
+    /// The longest `Retry-After` that gets waited out.
+    max_retry_after: Duration,
+    /// When each host that sent a `Retry-After` can be requested again.
+    paused: Mutex<HashMap<String, Instant>>,
+    /// Hosts that asked to wait longer than `max_retry_after`, which aren't requested again this crawl.
+    abandoned: Mutex<HashSet<String>>,
+}
+
+impl Politeness {
+    pub fn new(delay: Duration, max_retry_after: Duration) -> Self {
+        Self {
+            delay,
+            crawl_delays: Mutex::new(HashMap::new()),
+            hosts: Mutex::new(HashMap::new()),
+            max_retry_after,
+            paused: Mutex::new(HashMap::new()),
+            abandoned: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Sets `host`'s `Crawl-delay`. Returns whether it's new, i.e. the first time it was set.
+    pub fn set_crawl_delay(&self, host: &str, delay: Duration) -> bool {
+        self.crawl_delays
+            .lock()
+            .unwrap()
+            .insert(host.to_owned(), delay)
+            .is_none()
+    }
+
+    /// The gap to leave between requests to `host`.
+    fn delay(&self, host: &str) -> Duration {
+        self.crawl_delays
+            .lock()
+            .unwrap()
+            .get(host)
+            .map_or(self.delay, |crawl_delay| self.delay.max(*crawl_delay))
+    }
+
+    /// Holds off every request to `host` for `wait`, as its `Retry-After` asked.
+    /// Returns `false` instead if that's longer than `max_retry_after`, giving up on the host for the rest of the crawl.
+    pub fn pause(&self, host: &str, wait: Duration) -> bool {
+        if wait > self.max_retry_after {
+            self.abandoned.lock().unwrap().insert(host.to_owned());
+            return false;
+        }
+
+        let until = Instant::now() + wait;
+        let mut paused = self.paused.lock().unwrap();
+        let paused_until = paused.entry(host.to_owned()).or_insert(until);
+        *paused_until = (*paused_until).max(until);
+        true
+    }
+
+    /// Whether `host` asked to wait longer than `max_retry_after`.
+    pub fn is_abandoned(&self, host: &str) -> bool {
+        self.abandoned.lock().unwrap().contains(host)
+    }
+
+    /// Waits until a request to `host` is allowed and marks it as requested.
+    pub async fn wait(&self, host: &str) {
+        let paused_until = self.paused.lock().unwrap().get(host).copied();
+        if let Some(until) = paused_until {
+            time::sleep_until(until).await;
+        }
+
+        let delay = self.delay(host);
+        if delay.is_zero() {
+            return;
+        }
+
+        let entry = self.hosts
+            .lock()
+            .unwrap()
+            .entry(host.to_owned())
+            .or_default()
+            .clone();
+
+        // holding the host lock while sleeping is what serializes tasks hitting the same host,
+        // otherwise they'd all see the same stale timestamp and fire together.
+        let mut host_state = entry.lock().await;
+
+        if let Some(last) = host_state.last_request {
+            time::sleep_until(last + delay).await;
+        }
+
+        host_state.last_request = Some(Instant::now());
+    }
+}
+
+/// Caps how many requests are in flight to the same host at once, on top of the global worker count.
+pub struct HostLimits {
+    per_host: usize,
+    hosts: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+}
+
+impl HostLimits {
+    pub fn new(per_host: usize) -> Self {
+        Self {
+            per_host,
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Waits for one of `host`'s slots. It's given back when the permit is dropped.
+    pub async fn acquire(&self, host: &str) -> HostPermit {
+        let semaphore = self.hosts
+            .lock()
+            .unwrap()
+            .entry(host.to_owned())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.per_host)))
+            .clone();
+
+        let permit = semaphore.acquire_owned().await.expect("host semaphores are never closed");
+
+        HostPermit {
+            host: host.to_owned(),
+            hosts: self.hosts.clone(),
+            permit: Some(permit),
+        }
+    }
+}
+
+/// A slot for one request to a host, from [`HostLimits::acquire`].
+pub struct HostPermit {
+    host: String,
+    hosts: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for HostPermit {
+    fn drop(&mut self) {
+        let mut hosts = self.hosts.lock().unwrap();
+
+        // the permit keeps its semaphore alive, so the map and this permit being the only references means
+        // nobody else is using or waiting on the host, and its entry can go instead of piling up forever.
+        if let Some(permit) = self.permit.take() {
+            let semaphore = permit.semaphore().clone();
+            drop(permit);
+
+            if Arc::strong_count(&semaphore) == 2 {
+                hosts.remove(&self.host);
+            }
+        }
+    }
+}
+
+/// Backs off hosts that keep failing, and benches them for the rest of the crawl once they've failed too often in a row.
+///
+/// Without it, a host that times out costs every one of its urls the full timeout.
+pub struct HostHealth {
+    /// Consecutive failures before each one comes with a cooldown.
+    backoff_after: u32,
+    /// Consecutive failures before the host is benched.
+    bench_after: u32,
+    hosts: Mutex<HashMap<String, HostErrors>>,
+}
+
+#[derive(Default)]
+struct HostErrors {
+    /// Failures since the host's last success.
+    consecutive: u32,
+    /// When the cooldown from its last failure is over.
+    retry_at: Option<Instant>,
+    /// How many urls were skipped since it was benched, or `None` if it wasn't.
+    skipped: Option<usize>,
+}
+
+/// What a failure did to its host, from [`HostHealth::failed`].
+pub enum HostFailure {
+    Counted,
+    /// The host's next request waits this long.
+    CoolDown(Duration),
+    /// That was one failure too many, the host isn't requested again.
+    Benched,
+}
+
+impl HostHealth {
+    const BASE_COOLDOWN: Duration = Duration::from_secs(1);
+    const MAX_COOLDOWN: Duration = Duration::from_secs(60);
+
+    pub fn new(backoff_after: u32, bench_after: u32) -> Self {
+        Self {
+            backoff_after,
+            bench_after,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Resets `host`'s failures, unless it's already benched.
+    pub fn succeeded(&self, host: &str) {
+        if let Some(errors) = self.hosts.lock().unwrap().get_mut(host).filter(|errors| errors.skipped.is_none()) {
+            errors.consecutive = 0;
+            errors.retry_at = None;
+        }
+    }
+
+    /// Counts a failure against `host`, doubling its cooldown every time past `backoff_after`.
+    pub fn failed(&self, host: &str) -> HostFailure {
+        let mut hosts = self.hosts.lock().unwrap();
+        let errors = hosts.entry(host.to_owned()).or_default();
+        if errors.skipped.is_some() {
+            return HostFailure::Counted;
+        }
+
+        errors.consecutive += 1;
+        if errors.consecutive >= self.bench_after {
+            errors.skipped = Some(0);
+            return HostFailure::Benched;
+        }
+
+        if errors.consecutive < self.backoff_after {
+            return HostFailure::Counted;
+        }
+
+        let cooldown = Self::BASE_COOLDOWN
+            .saturating_mul(2u32.saturating_pow(errors.consecutive - self.backoff_after))
+            .min(Self::MAX_COOLDOWN);
+        errors.retry_at = Some(Instant::now() + cooldown);
+        HostFailure::CoolDown(cooldown)
+    }
+
+    /// Waits out `host`'s cooldown, if it has one.
+    pub async fn wait(&self, host: &str) {
+        let retry_at = self.hosts.lock().unwrap().get(host).and_then(|errors| errors.retry_at);
+        if let Some(retry_at) = retry_at {
+            time::sleep_until(retry_at).await;
+        }
+    }
+
+    /// Whether `host` is benched, counting the url that would've been fetched as skipped if it is.
+    pub fn skip_if_benched(&self, host: &str) -> bool {
+        let mut hosts = self.hosts.lock().unwrap();
+        let Some(skipped) = hosts.get_mut(host).and_then(|errors| errors.skipped.as_mut()) else {
+            return false;
+        };
+
+        *skipped += 1;
+        true
+    }
+
+    /// Every benched host, with how many urls were skipped because of it.
+    pub fn benched(&self) -> BTreeMap<String, usize> {
+        self.hosts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(host, errors)| Some((host.clone(), errors.skipped?)))
+            .collect()
+    }
+}
+
+/// Spaces requests out to a steady rate across every host.
+///
+/// Each request gets the next free slot, `interval` after the one before it, so there are no bursts to average out.
+/// Slots are handed out in the order they're asked for, which keeps one busy part of the crawl from starving the rest.
+pub struct RateLimit {
+    interval: Duration,
+    next: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl RateLimit {
+    pub fn new(per_second: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / per_second),
+            next: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Waits for this request's slot.
+    pub async fn wait(&self) {
+        // only held long enough to claim a slot, tokio's mutex queues waiters fairly.
+        let slot = {
+            let mut next = self.next.lock().await;
+            let now = Instant::now();
+            let slot = next.map_or(now, |next| next.max(now));
+            *next = Some(slot + self.interval);
+            slot
+        };
+
+        time::sleep_until(slot).await;
+    }
+}
@@ -0,0 +1,470 @@
+use std::{collections::HashMap, io::Write};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use surrealdb::{engine::any::Any, sql::Thing, Surreal};
+
+use crate::{db::{LINK_SCOPE, RUNS_TABLE}, model::RunOutcome};
+
+/// How many rows are pulled from the db at a time.
+const PAGE_SIZE: usize = 1000;
+
+/// Whether a node was crawled or just recorded.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum NodeKind {
+    /// Requested at some point, whatever came back.
+    Fetched,
+    /// Only recorded, because of limits, filters, robots.txt and the like.
+    Unfetched,
+    /// Only recorded because it's off the domains being crawled.
+    External,
+}
+
+impl NodeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Fetched => "fetched",
+            Self::Unfetched => "unfetched",
+            Self::External => "external",
+        }
+    }
+}
+
+/// What `query list` narrows the urls down to. Every filter that's set has to match.
+pub struct ListFilter {
+    /// A substring of the url.
+    pub pattern: Option<String>,
+    /// A regex the url has to match.
+    pub regex: Option<Regex>,
+    pub status: Option<u16>,
+    pub kind: Option<NodeKind>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ListedNode {
+    url: String,
+    title: Option<String>,
+    status: Option<u16>,
+    depth: Option<u32>,
+    content_type: Option<String>,
+    error: Option<String>,
+    fetched: bool,
+    external: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Degrees {
+    url: String,
+    in_degree: u64,
+    out_degree: u64,
+}
+
+#[derive(Deserialize)]
+struct Edge {
+    #[serde(rename = "in")]
+    a_in: Thing,
+    out: Thing,
+}
+
+#[derive(Deserialize)]
+struct UrlOf {
+    id: Thing,
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct Count {
+    count: usize,
+}
+
+/// Bails out if `table` has nothing in it, since that almost always means the wrong table or db was picked.
+pub async fn ensure_not_empty(db: &Surreal<Any>, table: &str) -> anyhow::Result<()> {
+    let mut res = db
+        .query("SELECT count() FROM type::table($table) GROUP ALL")
+        .bind(("table", table.to_owned()))
+        .await?;
+
+    let count: Option<Count> = res.take(0)?;
+    if count.is_none_or(|count| count.count == 0) {
+        anyhow::bail!("There are no urls in table {table:?}. Check --table, --db and --url match what the crawl used.");
+    }
+
+    Ok(())
+}
+
+/// Prints every url in `table` that passes `filter`, either as a table or as a JSON array.
+pub async fn list(db: &Surreal<Any>, table: &str, filter: &ListFilter, json: bool, out: &mut impl Write) -> anyhow::Result<()> {
+    // the regex is checked here instead of in the query, so it can't be combined with LIMIT there.
+    // older dbs don't have fetched_at, but anything fetched at least has a status or an error.
+    const QUERY: &str = "SELECT url, title, status, depth, content_type, error, \
+            (fetched_at IS NOT NONE OR status IS NOT NONE OR error IS NOT NONE) AS fetched, external = true AS external \
+        FROM type::table($table) \
+        WHERE ($pattern IS NONE OR string::contains(url, $pattern)) AND ($status IS NONE OR status = $status) \
+            AND ($kind IS NONE \
+                OR ($kind = 'fetched' AND (fetched_at IS NOT NONE OR status IS NOT NONE OR error IS NOT NONE)) \
+                OR ($kind = 'unfetched' AND (fetched_at IS NOT NONE OR status IS NOT NONE OR error IS NOT NONE) = false) \
+                OR ($kind = 'external' AND external = true)) \
+        ORDER BY url LIMIT $limit START $start";
+
+    ensure_not_empty(db, table).await?;
+
+    if json {
+        writeln!(out, "[")?;
+    } else {
+        writeln!(out, "{:<6} {:<5} {:<60} TITLE", "STATUS", "DEPTH", "URL")?;
+    }
+
+    let mut written = 0;
+    let mut start = 0;
+    'pages: loop {
+        let mut res = db
+            .query(QUERY)
+            .bind(("table", table.to_owned()))
+            .bind(("pattern", filter.pattern.clone()))
+            .bind(("status", filter.status))
+            .bind(("kind", filter.kind.map(NodeKind::as_str)))
+            .bind(("limit", PAGE_SIZE))
+            .bind(("start", start))
+            .await?;
+
+        let nodes: Vec<ListedNode> = res.take(0)?;
+        let last_page = nodes.len() < PAGE_SIZE;
+
+        for node in nodes {
+            if filter.limit.is_some_and(|limit| written >= limit) {
+                break 'pages;
+            }
+
+            if filter.regex.as_ref().is_some_and(|regex| !regex.is_match(&node.url)) {
+                continue;
+            }
+
+            if json {
+                if written > 0 {
+                    writeln!(out, ",")?;
+                }
+                write!(out, "  {}", serde_json::to_string(&node)?)?;
+            } else {
+                writeln!(
+                    out,
+                    "{:<6} {:<5} {:<60} {}",
+                    match (node.status, node.external) {
+                        (Some(status), _) => status.to_string(),
+                        (None, true) => "ext".to_owned(),
+                        (None, false) => "-".to_owned(),
+                    },
+                    node.depth.map(|depth| depth.to_string()).unwrap_or_else(|| "-".to_owned()),
+                    node.url,
+                    node.title.as_deref().unwrap_or(""),
+                )?;
+            }
+            written += 1;
+        }
+
+        if last_page {
+            break;
+        }
+        start += PAGE_SIZE;
+    }
+
+    if json {
+        if written > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "]")?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+/// Finds the shortest chains of links from `from` to `to`, going at most `max_hops` links deep.
+/// Returns every shortest path if `all` is set and just one otherwise, or nothing if there's no path.
+pub async fn shortest_paths(
+    db: &Surreal<Any>,
+    table: &str,
+    relate_table: &str,
+    (from, to): (&str, &str),
+    max_hops: usize,
+    all: bool,
+) -> anyhow::Result<Vec<Vec<String>>> {
+    let from_id = node_id(db, table, from).await?;
+    let to_id = node_id(db, table, to).await?;
+
+    if from_id == to_id {
+        return Ok(vec![vec![from.to_owned()]]);
+    }
+
+    // a breadth-first search one hop at a time, so only the visited nodes need to be kept around
+    // instead of the whole edge table. keyed by the id's string form since Thing can't be a key.
+    let mut parents: HashMap<String, Vec<Thing>> = HashMap::from([(from_id.to_string(), Vec::new())]);
+    let mut frontier = vec![from_id];
+
+    for _ in 0..max_hops {
+        let mut res = db
+            .query("SELECT in, out FROM type::table($relate_table) WHERE in IN $frontier")
+            .bind(("relate_table", relate_table.to_owned()))
+            .bind(("frontier", frontier))
+            .await?;
+        let edges: Vec<Edge> = res.take(0)?;
+
+        let mut level: HashMap<String, (Thing, Vec<Thing>)> = HashMap::new();
+        for edge in edges {
+            let key = edge.out.to_string();
+            if parents.contains_key(&key) {
+                continue;
+            }
+
+            let (_, node_parents) = level.entry(key).or_insert_with(|| (edge.out, Vec::new()));
+            if !node_parents.contains(&edge.a_in) && (all || node_parents.is_empty()) {
+                node_parents.push(edge.a_in);
+            }
+        }
+
+        frontier = Vec::with_capacity(level.len());
+        for (key, (id, node_parents)) in level {
+            frontier.push(id);
+            parents.insert(key, node_parents);
+        }
+
+        if parents.contains_key(&to_id.to_string()) || frontier.is_empty() {
+            break;
+        }
+    }
+
+    if !parents.contains_key(&to_id.to_string()) {
+        return Ok(Vec::new());
+    }
+
+    // walk back up from the target, branching wherever there's a tie.
+    let mut paths = Vec::new();
+    let mut stack = vec![vec![to_id]];
+    while let Some(path) = stack.pop() {
+        let node_parents = &parents[&path.last().unwrap().to_string()];
+        if node_parents.is_empty() {
+            paths.push(path.into_iter().rev().collect::<Vec<_>>());
+            continue;
+        }
+
+        for parent in node_parents {
+            let mut path = path.clone();
+            path.push(parent.clone());
+            stack.push(path);
+        }
+    }
+
+    let ids: Vec<Thing> = paths.iter().flatten().cloned().collect();
+    let mut res = db.query("SELECT id, url FROM array::distinct($ids)").bind(("ids", ids)).await?;
+    let urls: HashMap<String, String> = res
+        .take::<Vec<UrlOf>>(0)?
+        .into_iter()
+        .map(|node| (node.id.to_string(), node.url))
+        .collect();
+
+    let mut paths: Vec<Vec<String>> = paths
+        .into_iter()
+        .map(|path| {
+            path.iter()
+                .map(|id| {
+                    let id = id.to_string();
+                    urls.get(&id).cloned().unwrap_or(id)
+                })
+                .collect()
+        })
+        .collect();
+    paths.sort();
+
+    Ok(paths)
+}
+
+async fn node_id(db: &Surreal<Any>, table: &str, url: &str) -> anyhow::Result<Thing> {
+    let mut res = db
+        .query("SELECT id, url FROM type::table($table) WHERE url = $url LIMIT 1")
+        .bind(("table", table.to_owned()))
+        .bind(("url", url.to_owned()))
+        .await?;
+
+    let node: Option<UrlOf> = res.take(0)?;
+    node.map(|node| node.id)
+        .ok_or_else(|| anyhow::anyhow!("There's no node for {url:?} in table {table:?}"))
+}
+
+#[derive(Deserialize)]
+struct StoredContent {
+    content_hash: Option<String>,
+    content_path: Option<String>,
+}
+
+/// Prints everything recorded about the node for `url` as JSON. With a `content_table`, prints the page's body as
+/// `--store-content` kept it instead, from its file or from that table.
+pub async fn show(db: &Surreal<Any>, table: &str, url: &str, content_table: Option<&str>, out: &mut impl Write) -> anyhow::Result<()> {
+    let Some(content_table) = content_table else {
+        let mut res = db
+            .query("SELECT * FROM type::table($table) WHERE url = $url LIMIT 1")
+            .bind(("table", table.to_owned()))
+            .bind(("url", url.to_owned()))
+            .await?;
+
+        // taken as a plain value so ids and every field the node has come out as JSON, however old the db is.
+        let nodes: surrealdb::Value = res.take(0)?;
+        let node = match nodes.into_inner().into_json() {
+            serde_json::Value::Array(nodes) => nodes.into_iter().next(),
+            _ => None,
+        };
+        let node = node.ok_or_else(|| anyhow::anyhow!("There's no node for {url:?} in table {table:?}"))?;
+        writeln!(out, "{}", serde_json::to_string_pretty(&node)?)?;
+        out.flush()?;
+        return Ok(());
+    };
+
+    let mut res = db
+        .query("SELECT content_hash, content_path FROM type::table($table) WHERE url = $url LIMIT 1")
+        .bind(("table", table.to_owned()))
+        .bind(("url", url.to_owned()))
+        .await?;
+    let stored: Option<StoredContent> = res.take(0)?;
+    let stored = stored.ok_or_else(|| anyhow::anyhow!("There's no node for {url:?} in table {table:?}"))?;
+
+    let content = match (stored.content_path, stored.content_hash) {
+        (Some(path), _) => tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read {url}'s content from {path:?}"))?,
+        (None, Some(hash)) => {
+            let body: Option<String> = db
+                .query("SELECT VALUE body FROM ONLY type::thing($table, $hash)")
+                .bind(("table", content_table.to_owned()))
+                .bind(("hash", hash))
+                .await?
+                .take(0)?;
+            body.ok_or_else(|| anyhow::anyhow!("{url}'s content isn't in table {content_table:?}. Check --content-table matches what the crawl used, and that it ran with --store-content."))?
+        }
+        (None, None) => anyhow::bail!("Nothing was stored for {url}. Only text pages fetched with --store-content have their content kept."),
+    };
+
+    write!(out, "{content}")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Prints the `limit` most linked-to pages with their in and out degrees. With `weighted`, a relation
+/// counts as many times as the page linked it (relations from before counts were recorded count once).
+/// `external` limits the degrees to just external or just internal relations.
+pub async fn top(
+    db: &Surreal<Any>,
+    (table, relate_table): (&str, &str),
+    limit: usize,
+    weighted: bool,
+    external: Option<bool>,
+    json: bool,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    ensure_not_empty(db, table).await?;
+
+    let degree = |direction: &str| {
+        if weighted {
+            format!("math::sum(({direction}({relate_table} WHERE {LINK_SCOPE}).count).map(|$count| $count ?? 1))")
+        } else {
+            format!("array::len({direction}({relate_table} WHERE {LINK_SCOPE}))")
+        }
+    };
+    let query = format!(
+        "SELECT url, {} AS in_degree, {} AS out_degree FROM type::table($table) ORDER BY in_degree DESC, url LIMIT $limit",
+        degree("<-"),
+        degree("->"),
+    );
+
+    let mut res = db
+        .query(query)
+        .bind(("table", table.to_owned()))
+        .bind(("limit", limit))
+        .bind(("external", external))
+        .await?;
+    let nodes: Vec<Degrees> = res.take(0)?;
+
+    if json {
+        writeln!(out, "{}", serde_json::to_string_pretty(&nodes)?)?;
+    } else {
+        writeln!(out, "{:<9} {:<10} URL", "IN", "OUT")?;
+        for node in nodes {
+            writeln!(out, "{:<9} {:<10} {}", node.in_degree, node.out_degree, node.url)?;
+        }
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+struct ListedRun {
+    id: String,
+    version: Option<String>,
+    seeds: Vec<String>,
+    started_at: String,
+    ended_at: Option<String>,
+    outcome: Option<RunOutcome>,
+    error: Option<String>,
+    summary: Option<serde_json::Value>,
+    settings: Option<serde_json::Value>,
+}
+
+/// Prints the crawls that were run into the db, newest first, either as a table or as JSON with their settings.
+pub async fn runs(db: &Surreal<Any>, limit: Option<usize>, json: bool, out: &mut impl Write) -> anyhow::Result<()> {
+    let mut query = String::from(
+        "SELECT <string> id AS id, version, seeds ?? [] AS seeds, <string> started_at AS started_at, \
+            (IF ended_at != NONE THEN <string> ended_at END) AS ended_at, outcome, error, summary, settings \
+        FROM type::table($table) ORDER BY started_at DESC",
+    );
+    if limit.is_some() {
+        query += " LIMIT $limit";
+    }
+
+    let mut res = db
+        .query(query)
+        .bind(("table", RUNS_TABLE))
+        .bind(("limit", limit))
+        .await?;
+    let runs: Vec<ListedRun> = res.take(0)?;
+
+    if json {
+        writeln!(out, "{}", serde_json::to_string_pretty(&runs)?)?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    if runs.is_empty() {
+        writeln!(out, "No runs are recorded in this db, so it was probably crawled by a findconn from before they were")?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    writeln!(out, "{:<28} {:<30} {:<12} {:<7} {:<9} SEEDS", "ID", "STARTED", "OUTCOME", "PAGES", "TOOK")?;
+    for run in runs {
+        let summary = |field: &str| run.summary.as_ref().and_then(|summary| summary.get(field)).cloned();
+        let outcome = match run.outcome {
+            Some(RunOutcome::Completed) => "completed",
+            Some(RunOutcome::Interrupted) => "interrupted",
+            Some(RunOutcome::Error) => "error",
+            // either still going, or it was killed before it could say.
+            None => "unfinished",
+        };
+
+        writeln!(
+            out,
+            "{:<28} {:<30} {:<12} {:<7} {:<9} {}",
+            run.id,
+            run.started_at,
+            outcome,
+            summary("pages_fetched").map_or_else(|| "-".to_owned(), |pages| pages.to_string()),
+            summary("duration_secs")
+                .and_then(|secs| secs.as_f64())
+                .map_or_else(|| "-".to_owned(), |secs| format!("{secs:.1}s")),
+            run.seeds.join(" "),
+        )?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
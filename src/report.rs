@@ -0,0 +1,185 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use surrealdb::{engine::any::Any, Surreal};
+use url::Url;
+
+use crate::{export::csv_escape, query::ensure_not_empty};
+
+/// How many broken urls are pulled from the db at a time.
+const PAGE_SIZE: usize = 500;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    /// Grouped by broken url, for reading.
+    Table,
+    /// One row per broken link.
+    Csv,
+    Json,
+}
+
+pub struct BrokenOptions {
+    /// Also count urls that couldn't be fetched at all instead of just HTTP errors.
+    pub include_errors: bool,
+    /// Skip broken urls that aren't on the same host as any page linking to them.
+    pub internal_only: bool,
+    pub format: ReportFormat,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BrokenUrl {
+    url: String,
+    status: Option<u16>,
+    error: Option<String>,
+    referrers: Vec<String>,
+}
+
+/// Prints every 404, 410 or 5xx url along with the pages linking to it.
+pub async fn broken(db: &Surreal<Any>, (table, relate_table): (&str, &str), options: &BrokenOptions, out: &mut impl Write) -> anyhow::Result<()> {
+    ensure_not_empty(db, table).await?;
+
+    let errors = if options.include_errors { " OR error IS NOT NONE" } else { "" };
+    let query = format!(
+        "SELECT url, status, error, array::sort(array::distinct(<-{relate_table}<-{table}.url)) AS referrers \
+            FROM type::table($table) WHERE status IN [404, 410] OR status >= 500{errors} \
+            ORDER BY url LIMIT $limit START $start"
+    );
+
+    match options.format {
+        ReportFormat::Table => {}
+        ReportFormat::Csv => writeln!(out, "target,status,error,source")?,
+        ReportFormat::Json => writeln!(out, "[")?,
+    }
+
+    let mut written = 0;
+    let mut start = 0;
+    loop {
+        let mut res = db
+            .query(&query)
+            .bind(("table", table.to_owned()))
+            .bind(("limit", PAGE_SIZE))
+            .bind(("start", start))
+            .await?;
+        let broken: Vec<BrokenUrl> = res.take(0)?;
+        let last_page = broken.len() < PAGE_SIZE;
+
+        for target in broken {
+            if options.internal_only && !is_internal(&target) {
+                continue;
+            }
+
+            write_broken(options.format, &target, written == 0, out)?;
+            written += 1;
+        }
+
+        if last_page {
+            break;
+        }
+        start += PAGE_SIZE;
+    }
+
+    match options.format {
+        ReportFormat::Table if written == 0 => writeln!(out, "No broken links found")?,
+        ReportFormat::Table | ReportFormat::Csv => {}
+        ReportFormat::Json => {
+            if written > 0 {
+                writeln!(out)?;
+            }
+            writeln!(out, "]")?;
+        }
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+/// Whether anything on the same host links to it.
+fn is_internal(target: &BrokenUrl) -> bool {
+    let host = |url: &str| Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_owned));
+    let target_host = host(&target.url);
+
+    target_host.is_some() && target.referrers.iter().any(|referrer| host(referrer) == target_host)
+}
+
+fn write_broken(format: ReportFormat, target: &BrokenUrl, first: bool, out: &mut impl Write) -> anyhow::Result<()> {
+    let status = target.status.map(|status| status.to_string()).unwrap_or_default();
+    let error = target.error.as_deref().unwrap_or("");
+
+    match format {
+        ReportFormat::Table => {
+            if !first {
+                writeln!(out)?;
+            }
+
+            let reason = if status.is_empty() { error } else { &status };
+            writeln!(out, "{} ({reason})", target.url)?;
+            for referrer in &target.referrers {
+                writeln!(out, "    <- {referrer}")?;
+            }
+        }
+        ReportFormat::Csv => {
+            for referrer in &target.referrers {
+                writeln!(out, "{},{status},{},{}", csv_escape(&target.url), csv_escape(error), csv_escape(referrer))?;
+            }
+        }
+        ReportFormat::Json => {
+            if !first {
+                writeln!(out, ",")?;
+            }
+            write!(out, "  {}", serde_json::to_string(target)?)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+struct DuplicateCluster {
+    content_hash: String,
+    urls: Vec<String>,
+}
+
+/// Prints every group of pages that had exactly the same content, biggest first.
+pub async fn duplicates(db: &Surreal<Any>, table: &str, format: ReportFormat, out: &mut impl Write) -> anyhow::Result<()> {
+    ensure_not_empty(db, table).await?;
+
+    let mut res = db
+        .query("SELECT * FROM (SELECT content_hash, array::group(url) AS urls, count() AS count \
+                FROM type::table($table) WHERE content_hash IS NOT NONE GROUP BY content_hash) \
+            WHERE count > 1 ORDER BY count DESC, content_hash")
+        .bind(("table", table.to_owned()))
+        .await?;
+    let mut clusters: Vec<DuplicateCluster> = res.take(0)?;
+    for cluster in &mut clusters {
+        cluster.urls.sort();
+    }
+
+    match format {
+        ReportFormat::Table if clusters.is_empty() => writeln!(out, "No duplicate content found")?,
+        ReportFormat::Table => {
+            for (i, cluster) in clusters.iter().enumerate() {
+                if i > 0 {
+                    writeln!(out)?;
+                }
+
+                writeln!(out, "{} pages with content {}", cluster.urls.len(), cluster.content_hash)?;
+                for url in &cluster.urls {
+                    writeln!(out, "    {url}")?;
+                }
+            }
+        }
+        ReportFormat::Csv => {
+            writeln!(out, "content_hash,url")?;
+            for cluster in &clusters {
+                for url in &cluster.urls {
+                    writeln!(out, "{},{}", cluster.content_hash, csv_escape(url))?;
+                }
+            }
+        }
+        ReportFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&clusters)?)?,
+    }
+
+    out.flush()?;
+    Ok(())
+}
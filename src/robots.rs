@@ -0,0 +1,118 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use reqwest::Client;
+use texting_robots::Robot;
+use tokio::sync::OnceCell;
+use tracing::{debug, trace, warn};
+use url::Url;
+
+use crate::proxies::ProxyPool;
+
+type RobotsEntry = Arc<OnceCell<Option<Robot>>>;
+
+/// What an origin's `robots.txt` says about fetching a url.
+pub enum Verdict {
+    /// Go ahead, waiting at least `crawl_delay` between requests if it has one.
+    Allowed { crawl_delay: Option<Duration> },
+    Disallowed,
+    /// Its `Crawl-delay` is over `--max-crawl-delay`, so the host isn't worth waiting on.
+    TooSlow(Duration),
+}
+
+/// Lazily fetches and caches `robots.txt` rules per origin.
+pub struct RobotsCache {
+    agent: String,
+    max_crawl_delay: Duration,
+    origins: Mutex<HashMap<String, RobotsEntry>>,
+}
+
+impl RobotsCache {
+    pub fn new(agent: impl Into<String>, max_crawl_delay: Duration) -> Self {
+        Self {
+            agent: agent.into(),
+            max_crawl_delay,
+            origins: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether `url` may be fetched. Fetches the origin's `robots.txt` the first time it is seen.
+    pub async fn check(&self, clients: &ProxyPool, url: &Url) -> Verdict {
+        let origin = url.origin().ascii_serialization();
+
+        // the lock is only held to grab the cell so concurrent tasks for the same origin
+        // wait on the same fetch instead of each requesting robots.txt.
+        let entry = self.origins
+            .lock()
+            .unwrap()
+            .entry(origin.clone())
+            .or_default()
+            .clone();
+
+        let robot = entry
+            .get_or_init(|| self.fetch(clients.pick().1, origin))
+            .await;
+
+        let Some(robot) = robot else {
+            return Verdict::Allowed { crawl_delay: None };
+        };
+
+        if !robot.allowed(url.as_str()) {
+            return Verdict::Disallowed;
+        }
+
+        match crawl_delay(robot) {
+            Some(delay) if delay > self.max_crawl_delay => Verdict::TooSlow(delay),
+            crawl_delay => Verdict::Allowed { crawl_delay },
+        }
+    }
+
+    async fn fetch(&self, client: &Client, origin: String) -> Option<Robot> {
+        let robots_url = format!("{origin}/robots.txt");
+        trace!("Fetching {robots_url:?}");
+
+        // any failure here means we treat the origin as allow-all.
+        let res = match client.get(&robots_url).send().await {
+            Ok(res) if res.status().is_success() => res,
+            Ok(res) => {
+                debug!("{robots_url:?} returned {}, allowing all", res.status());
+                return None;
+            }
+            Err(err) => {
+                debug!("Failed to fetch {robots_url:?}, allowing all: {err}");
+                return None;
+            }
+        };
+
+        let body = match res.bytes().await {
+            Ok(body) => body,
+            Err(err) => {
+                debug!("Failed to read {robots_url:?}, allowing all: {err}");
+                return None;
+            }
+        };
+
+        match Robot::new(&self.agent, &body) {
+            Ok(robot) => {
+                // only said once here, rather than for every page on the host that gets skipped.
+                if let Some(delay) = crawl_delay(&robot).filter(|delay| *delay > self.max_crawl_delay) {
+                    warn!(%origin, crawl_delay_secs = delay.as_secs_f32(), "Crawl-delay is over --max-crawl-delay, skipping the host");
+                }
+
+                Some(robot)
+            }
+            Err(err) => {
+                debug!("Failed to parse {robots_url:?}, allowing all: {err}");
+                None
+            }
+        }
+    }
+}
+
+/// The `Crawl-delay` for our agent, ignoring nonsense like negative numbers.
+fn crawl_delay(robot: &Robot) -> Option<Duration> {
+    robot.delay.and_then(|secs| Duration::try_from_secs_f32(secs).ok())
+}
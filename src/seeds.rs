@@ -0,0 +1,45 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use anyhow::Context;
+use tracing::error;
+use url::Url;
+
+/// Reads seed urls from a file with one per line, or from stdin if `path` is `-`.
+/// Blank lines and `#` comments are skipped, and so are lines that aren't urls, unless `strict` makes them an error.
+pub fn read_url_file(path: &Path, strict: bool) -> anyhow::Result<Vec<String>> {
+    let stdin = path == Path::new("-");
+    let source = if stdin { "stdin".to_owned() } else { format!("{path:?}") };
+
+    let content = if stdin {
+        let mut content = String::new();
+        io::stdin()
+            .read_to_string(&mut content)
+            .context("Failed to read seed urls from stdin")?;
+        content
+    } else {
+        fs::read_to_string(path)
+            .with_context(|| format!("Failed to read url file {path:?}"))?
+    };
+
+    let mut urls = Vec::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match Url::parse(line) {
+            Ok(_) => urls.push(line.to_owned()),
+            Err(err) if strict => anyhow::bail!("{source} line {}: invalid url {line:?}: {err}", i + 1),
+            Err(err) => error!("Skipping {source} line {}, invalid url {line:?}: {err}", i + 1),
+        }
+    }
+
+    Ok(urls)
+}
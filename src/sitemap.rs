@@ -0,0 +1,63 @@
+use std::io::Read;
+
+use anyhow::Context;
+use flate2::read::GzDecoder;
+use roxmltree::Document;
+
+pub struct SitemapEntry {
+    pub loc: String,
+    pub lastmod: Option<String>,
+}
+
+pub enum Sitemap {
+    /// A `<urlset>` listing pages.
+    Urls(Vec<SitemapEntry>),
+
+    /// A `<sitemapindex>` listing more sitemaps.
+    Index(Vec<String>),
+}
+
+/// Parses an XML sitemap, decompressing it first if it's gzipped.
+pub fn parse_sitemap(body: &[u8]) -> anyhow::Result<Sitemap> {
+    let mut decompressed = String::new();
+    let text = if body.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(body)
+            .read_to_string(&mut decompressed)
+            .context("Failed to decompress sitemap")?;
+        decompressed.as_str()
+    } else {
+        std::str::from_utf8(body).context("Sitemap is not valid UTF-8")?
+    };
+
+    let document = Document::parse(text).context("Failed to parse sitemap XML")?;
+    let root = document.root_element();
+
+    let child_text = |node: roxmltree::Node, name: &str| {
+        node.children()
+            .find(|child| child.has_tag_name(name))
+            .and_then(|child| child.text())
+            .map(|text| text.trim().to_owned())
+            .filter(|text| !text.is_empty())
+    };
+
+    match root.tag_name().name() {
+        "urlset" => Ok(Sitemap::Urls(
+            root.children()
+                .filter(|node| node.has_tag_name("url"))
+                .filter_map(|node| {
+                    Some(SitemapEntry {
+                        loc: child_text(node, "loc")?,
+                        lastmod: child_text(node, "lastmod"),
+                    })
+                })
+                .collect(),
+        )),
+        "sitemapindex" => Ok(Sitemap::Index(
+            root.children()
+                .filter(|node| node.has_tag_name("sitemap"))
+                .filter_map(|node| child_text(node, "loc"))
+                .collect(),
+        )),
+        other => anyhow::bail!("Expected a <urlset> or <sitemapindex>, got <{other}>"),
+    }
+}
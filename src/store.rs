@@ -0,0 +1,248 @@
+//! Where a crawl writes what it finds. [`SurrealStore`] is the one `findconn` uses, unless it's writing
+//! [`FileStore`]'s plain files or a SQLite file with `SqliteStore`.
+
+mod files;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use surrealdb::{engine::any::Any, sql::Thing, Surreal};
+
+pub use crate::db::CreatedRelation;
+pub use files::{FileFormat, FileStore};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+use crate::{
+    db,
+    frontier::FrontierItem,
+    model::{FetchInfo, LinkData, PageLink, SiteURLNode, Validators},
+};
+
+/// Everything the crawler reads and writes as it goes: url nodes, the relations between them, and the frontier of
+/// urls still to crawl.
+///
+/// `table` arguments name the node, relation or frontier table the crawl was configured with, for stores that
+/// keep more than one of each. Several workers call these at once, so claiming a url has to be atomic.
+#[async_trait]
+pub trait GraphStore: Send + Sync {
+    /// Gets ready to store nodes in `table` and relations in `relate_tables`, if there's anything to do for that.
+    async fn define_schema(&self, table: &str, relate_tables: &[&str]) -> anyhow::Result<()>;
+
+    /// Creates the node unless another one already has its url. Returns the node's id, and whether this call created it.
+    async fn claim_url(&self, table: &str, node: &SiteURLNode) -> anyhow::Result<(Thing, bool)>;
+
+    /// Writes everything a page led to at once: its `fetch` info, a node for each of `nodes` (or a lower depth,
+    /// for ones that already exist), and its relations to them, grouped by relate table.
+    ///
+    /// Returns each node's id and whether this created it, in the same order as `nodes`, along with the relations
+    /// that were created. Relations that already exist are updated instead, like [`GraphStore::record_edge`] does.
+    async fn record_page<'a>(
+        &self,
+        table: &str,
+        page: Thing,
+        fetch: Option<FetchInfo>,
+        nodes: Vec<SiteURLNode>,
+        links: Vec<(&'a str, Vec<PageLink>)>,
+    ) -> anyhow::Result<(Vec<(Thing, bool)>, Vec<CreatedRelation<'a>>)>;
+
+    /// Relates `from` to `to`, or if they already are, updates that relation with `data` instead.
+    /// Returns the relation's id if one was created.
+    async fn record_edge(&self, relate_table: &str, from: Thing, to: Thing, data: &LinkData) -> anyhow::Result<Option<Thing>>;
+
+    /// The url of the node `id`.
+    async fn node_url(&self, id: Thing) -> anyhow::Result<Option<String>>;
+
+    /// Lowers the node's depth if `depth` is a shorter path to it.
+    async fn lower_depth(&self, id: Thing, depth: u32) -> anyhow::Result<()>;
+
+    async fn record_fetch(&self, id: Thing, info: FetchInfo) -> anyhow::Result<()>;
+
+    /// Marks the node's fetch as started, unless that already happened (like in a run before a resume).
+    /// With `refresh`, it can still be fetched again if it was last checked longer ago than that.
+    /// Returns whether it's ok to go ahead and fetch it.
+    async fn start_fetch(&self, id: Thing, refresh: Option<Duration>) -> anyhow::Result<bool>;
+
+    /// Undoes [`GraphStore::start_fetch`] for a page that's going back in the frontier to be fetched later.
+    async fn reset_fetch(&self, id: Thing) -> anyhow::Result<()>;
+
+    /// The node's validators from when it was last fetched.
+    async fn validators(&self, id: Thing) -> anyhow::Result<Option<Validators>>;
+
+    /// Bumps the node's last checked time for a page that responded 304, leaving the rest of it as it was.
+    async fn mark_checked(&self, id: Thing) -> anyhow::Result<()>;
+
+    /// The urls the node links to, with what was recorded about each link.
+    async fn outgoing_links(&self, relate_table: &str, id: Thing) -> anyhow::Result<Vec<(String, LinkData)>>;
+
+    /// Deletes the node's relations to anything not in `urls`, returning how many went.
+    async fn prune_links(&self, relate_table: &str, id: Thing, urls: Vec<String>) -> anyhow::Result<usize>;
+
+    /// Marks the node as not fetched because it's off the domains being crawled.
+    async fn mark_external(&self, id: Thing) -> anyhow::Result<()>;
+
+    /// Marks the node as not fetched because it matched an exclude pattern.
+    async fn mark_excluded(&self, id: Thing) -> anyhow::Result<()>;
+
+    /// Marks the node as not fetched because it's at a private address.
+    async fn mark_private_blocked(&self, id: Thing) -> anyhow::Result<()>;
+
+    /// Marks the node as not fetched because robots.txt disallows it.
+    async fn mark_robots_blocked(&self, id: Thing) -> anyhow::Result<()>;
+
+    /// Marks the node as failed with `err`.
+    async fn record_failure(&self, id: Thing, err: &anyhow::Error) -> anyhow::Result<()>;
+
+    /// Saves a page's body under its content hash, for [`ContentStorage::Inline`](crate::archive::ContentStorage::Inline).
+    async fn save_content(&self, content_table: &str, hash: &str, content: &str) -> anyhow::Result<()>;
+
+    /// Saves an email address found on a page, returning its id. The same address always gets the same id.
+    async fn save_email(&self, email_table: &str, address: &str) -> anyhow::Result<Thing>;
+
+    /// Writes `items` to the frontier and takes `done` off it at once, so a crash can't lose the urls an item led
+    /// to once it's done. Returns the items as written, ids included.
+    async fn enqueue(&self, frontier_table: &str, items: Vec<FrontierItem>, done: Option<Thing>) -> anyhow::Result<Vec<FrontierItem>>;
+
+    /// Marks the frontier entry as the one that created its url's node, so a resumed crawl still fetches it.
+    async fn take_ownership(&self, entry: Thing) -> anyhow::Result<()>;
+
+    /// Everything left in the frontier, shallowest first.
+    async fn load_frontier(&self, frontier_table: &str) -> anyhow::Result<Vec<FrontierItem>>;
+
+    /// How many urls are still waiting in the frontier.
+    async fn frontier_len(&self, frontier_table: &str) -> anyhow::Result<usize>;
+
+    /// Called once the crawl is over, to write out anything that's still buffered.
+    async fn flush(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Stores the crawl in SurrealDB, with the nodes and relations as records in their tables. See [`db`] for the queries.
+#[derive(Clone)]
+pub struct SurrealStore {
+    db: Surreal<Any>,
+}
+
+impl SurrealStore {
+    /// Writes into `db`, which should already be using the namespace and db to crawl into.
+    pub fn new(db: Surreal<Any>) -> Self {
+        Self { db }
+    }
+
+    pub fn db(&self) -> &Surreal<Any> {
+        &self.db
+    }
+}
+
+#[async_trait]
+impl GraphStore for SurrealStore {
+    async fn define_schema(&self, table: &str, relate_tables: &[&str]) -> anyhow::Result<()> {
+        db::define_schema(&self.db, table).await?;
+        for relate_table in relate_tables {
+            db::define_relation_schema(&self.db, relate_table).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn claim_url(&self, table: &str, node: &SiteURLNode) -> anyhow::Result<(Thing, bool)> {
+        db::claim_node(&self.db, table, node).await
+    }
+
+    async fn record_page<'a>(
+        &self,
+        table: &str,
+        page: Thing,
+        fetch: Option<FetchInfo>,
+        nodes: Vec<SiteURLNode>,
+        links: Vec<(&'a str, Vec<PageLink>)>,
+    ) -> anyhow::Result<(Vec<(Thing, bool)>, Vec<CreatedRelation<'a>>)> {
+        db::record_page(&self.db, table, page, fetch, nodes, links).await
+    }
+
+    async fn record_edge(&self, relate_table: &str, from: Thing, to: Thing, data: &LinkData) -> anyhow::Result<Option<Thing>> {
+        db::relate(&self.db, relate_table, from, to, data).await
+    }
+
+    async fn node_url(&self, id: Thing) -> anyhow::Result<Option<String>> {
+        db::node_url(&self.db, id).await
+    }
+
+    async fn lower_depth(&self, id: Thing, depth: u32) -> anyhow::Result<()> {
+        db::lower_depth(&self.db, id, depth).await
+    }
+
+    async fn record_fetch(&self, id: Thing, info: FetchInfo) -> anyhow::Result<()> {
+        db::record_fetch(&self.db, id, info).await
+    }
+
+    async fn start_fetch(&self, id: Thing, refresh: Option<Duration>) -> anyhow::Result<bool> {
+        db::start_fetch(&self.db, id, refresh).await
+    }
+
+    async fn reset_fetch(&self, id: Thing) -> anyhow::Result<()> {
+        db::reset_fetch(&self.db, id).await
+    }
+
+    async fn validators(&self, id: Thing) -> anyhow::Result<Option<Validators>> {
+        db::validators(&self.db, id).await
+    }
+
+    async fn mark_checked(&self, id: Thing) -> anyhow::Result<()> {
+        db::mark_checked(&self.db, id).await
+    }
+
+    async fn outgoing_links(&self, relate_table: &str, id: Thing) -> anyhow::Result<Vec<(String, LinkData)>> {
+        db::outgoing_links(&self.db, relate_table, id).await
+    }
+
+    async fn prune_links(&self, relate_table: &str, id: Thing, urls: Vec<String>) -> anyhow::Result<usize> {
+        db::prune_links(&self.db, relate_table, id, urls).await
+    }
+
+    async fn mark_external(&self, id: Thing) -> anyhow::Result<()> {
+        db::mark_external(&self.db, id).await
+    }
+
+    async fn mark_excluded(&self, id: Thing) -> anyhow::Result<()> {
+        db::mark_excluded(&self.db, id).await
+    }
+
+    async fn mark_private_blocked(&self, id: Thing) -> anyhow::Result<()> {
+        db::mark_private_blocked(&self.db, id).await
+    }
+
+    async fn mark_robots_blocked(&self, id: Thing) -> anyhow::Result<()> {
+        db::mark_robots_blocked(&self.db, id).await
+    }
+
+    async fn record_failure(&self, id: Thing, err: &anyhow::Error) -> anyhow::Result<()> {
+        db::record_failure(&self.db, id, err).await
+    }
+
+    async fn save_content(&self, content_table: &str, hash: &str, content: &str) -> anyhow::Result<()> {
+        db::save_content(&self.db, content_table, hash, content).await
+    }
+
+    async fn save_email(&self, email_table: &str, address: &str) -> anyhow::Result<Thing> {
+        db::save_email(&self.db, email_table, address).await
+    }
+
+    async fn enqueue(&self, frontier_table: &str, items: Vec<FrontierItem>, done: Option<Thing>) -> anyhow::Result<Vec<FrontierItem>> {
+        db::enqueue(&self.db, frontier_table, items, done).await
+    }
+
+    async fn take_ownership(&self, entry: Thing) -> anyhow::Result<()> {
+        db::take_ownership(&self.db, entry).await
+    }
+
+    async fn load_frontier(&self, frontier_table: &str) -> anyhow::Result<Vec<FrontierItem>> {
+        db::load_frontier(&self.db, frontier_table).await
+    }
+
+    async fn frontier_len(&self, frontier_table: &str) -> anyhow::Result<usize> {
+        db::frontier_len(&self.db, frontier_table).await
+    }
+}
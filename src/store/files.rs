@@ -0,0 +1,612 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use clap::ValueEnum;
+use serde::Serialize;
+use surrealdb::sql::{Datetime, Id, Thing};
+use tracing::warn;
+
+use super::{CreatedRelation, GraphStore};
+use crate::{
+    db,
+    export::csv_escape,
+    frontier::FrontierItem,
+    model::{FetchInfo, LinkData, PageLink, Relation, SiteURLNode, Validators},
+};
+
+/// How often what's been written is flushed to disk, so a crash loses at most this much of it.
+const FLUSH_EVERY: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum FileFormat {
+    /// One JSON object per line.
+    Ndjson,
+    /// Comma separated, with a header row.
+    Csv,
+}
+
+impl FileFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            FileFormat::Ndjson => "ndjson",
+            FileFormat::Csv => "csv",
+        }
+    }
+}
+
+/// A node as it's written to the nodes file.
+#[derive(Serialize)]
+struct NodeLine<'a> {
+    url: &'a str,
+    depth: u32,
+    status: Option<u16>,
+    final_url: Option<&'a str>,
+    title: Option<&'a str>,
+    content_type: Option<&'a str>,
+    content_length: Option<u64>,
+    content_hash: Option<&'a str>,
+    content_path: Option<&'a str>,
+    fetch_ms: Option<u64>,
+    error_kind: Option<&'a str>,
+    error: Option<&'a str>,
+    external: bool,
+    excluded: bool,
+    private_blocked: bool,
+    robots_blocked: bool,
+    discovered_at: Option<String>,
+    fetched_at: Option<String>,
+    text: Option<&'a str>,
+}
+
+const NODE_COLUMNS: &str = "url,depth,status,final_url,title,content_type,content_length,content_hash,content_path,fetch_ms,error_kind,error,external,excluded,private_blocked,robots_blocked,discovered_at,fetched_at,text";
+
+impl<'a> NodeLine<'a> {
+    fn new(node: &'a SiteURLNode) -> Self {
+        Self {
+            url: &node.url,
+            depth: node.depth,
+            status: node.status,
+            final_url: node.final_url.as_deref(),
+            title: node.title.as_deref(),
+            content_type: node.content_type.as_deref(),
+            content_length: node.content_length,
+            content_hash: node.content_hash.as_deref(),
+            content_path: node.content_path.as_deref(),
+            fetch_ms: node.fetch_ms,
+            error_kind: node.error_kind.as_deref(),
+            error: node.error.as_deref(),
+            external: node.external,
+            excluded: node.excluded,
+            private_blocked: node.private_blocked,
+            robots_blocked: node.robots_blocked,
+            discovered_at: node.discovered_at.as_ref().map(Datetime::to_raw),
+            fetched_at: node.fetched_at.as_ref().map(Datetime::to_raw),
+            text: node.text.as_deref(),
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        [
+            csv_escape(self.url),
+            self.depth.to_string(),
+            optional(self.status.map(|status| status.to_string())),
+            csv_escape(self.final_url.unwrap_or("")),
+            csv_escape(self.title.unwrap_or("")),
+            csv_escape(self.content_type.unwrap_or("")),
+            optional(self.content_length.map(|length| length.to_string())),
+            self.content_hash.unwrap_or("").to_owned(),
+            csv_escape(self.content_path.unwrap_or("")),
+            optional(self.fetch_ms.map(|ms| ms.to_string())),
+            self.error_kind.unwrap_or("").to_owned(),
+            csv_escape(self.error.unwrap_or("")),
+            self.external.to_string(),
+            self.excluded.to_string(),
+            self.private_blocked.to_string(),
+            self.robots_blocked.to_string(),
+            optional(self.discovered_at.clone()),
+            optional(self.fetched_at.clone()),
+            csv_escape(self.text.unwrap_or("")),
+        ]
+        .join(",")
+    }
+}
+
+/// A relation as it's written to the edges file.
+#[derive(Serialize)]
+struct EdgeLine<'a> {
+    /// The relate table it would have gone in, like `containslink` or `redirectsto`.
+    table: &'a str,
+    source: &'a str,
+    target: &'a str,
+    nofollow: Option<bool>,
+    text: Option<&'a str>,
+    count: Option<u32>,
+    external: bool,
+}
+
+const EDGE_COLUMNS: &str = "table,source,target,nofollow,text,count,external";
+
+impl EdgeLine<'_> {
+    fn to_csv(&self) -> String {
+        [
+            csv_escape(self.table),
+            csv_escape(self.source),
+            csv_escape(self.target),
+            self.nofollow.map(|nofollow| nofollow.to_string()).unwrap_or_default(),
+            csv_escape(self.text.unwrap_or("")),
+            self.count.map(|count| count.to_string()).unwrap_or_default(),
+            self.external.to_string(),
+        ]
+        .join(",")
+    }
+}
+
+struct Node {
+    node: SiteURLNode,
+    /// Whether it's been written to the nodes file. That happens once the crawl is done with it.
+    written: bool,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: i64,
+    /// Node ids by url.
+    urls: HashMap<String, String>,
+    nodes: HashMap<String, Node>,
+    /// Email addresses by id.
+    emails: HashMap<String, String>,
+    /// By relate table, source id and target id.
+    edges: HashMap<(String, String, String), LinkData>,
+    frontier: HashSet<String>,
+}
+
+struct Files {
+    format: FileFormat,
+    nodes: BufWriter<File>,
+    edges: BufWriter<File>,
+}
+
+impl Files {
+    fn write_node(&mut self, node: &SiteURLNode) -> anyhow::Result<()> {
+        let line = NodeLine::new(node);
+        match self.format {
+            FileFormat::Ndjson => {
+                serde_json::to_writer(&mut self.nodes, &line)?;
+                writeln!(self.nodes)?;
+            }
+            FileFormat::Csv => writeln!(self.nodes, "{}", line.to_csv())?,
+        }
+
+        Ok(())
+    }
+
+    fn write_edge(&mut self, edge: &EdgeLine<'_>) -> anyhow::Result<()> {
+        match self.format {
+            FileFormat::Ndjson => {
+                serde_json::to_writer(&mut self.edges, edge)?;
+                writeln!(self.edges)?;
+            }
+            FileFormat::Csv => writeln!(self.edges, "{}", edge.to_csv())?,
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.nodes.flush()?;
+        self.edges.flush()?;
+
+        Ok(())
+    }
+}
+
+struct Inner {
+    state: State,
+    files: Files,
+}
+
+impl Inner {
+    fn new_id(&mut self, table: &str) -> Thing {
+        self.state.next_id += 1;
+        Thing::from((table.to_owned(), Id::from(self.state.next_id)))
+    }
+
+    fn node(&mut self, id: &Thing) -> anyhow::Result<&mut SiteURLNode> {
+        self.state.nodes
+            .get_mut(&id.to_string())
+            .map(|node| &mut node.node)
+            .ok_or_else(|| anyhow::anyhow!("There's no node {id}"))
+    }
+
+    /// Writes the node out, if it hasn't been already. Anything that happens to it after this isn't recorded.
+    fn settle(&mut self, id: &Thing) -> anyhow::Result<()> {
+        let Some(node) = self.state.nodes.get_mut(&id.to_string()).filter(|node| !node.written) else {
+            return Ok(());
+        };
+
+        node.written = true;
+        self.files.write_node(&node.node)
+    }
+
+    fn update(&mut self, id: &Thing, update: impl FnOnce(&mut SiteURLNode)) -> anyhow::Result<()> {
+        update(self.node(id)?);
+        self.settle(id)
+    }
+
+    fn claim(&mut self, table: &str, node: &SiteURLNode) -> (Thing, bool) {
+        if let Some(id) = self.state.urls.get(&node.url) {
+            let id = self.state.nodes[id].node.id.clone().unwrap();
+            return (id, false);
+        }
+
+        let id = self.new_id(table);
+        let mut node = node.clone();
+        node.id = Some(id.clone());
+        node.discovered_at = Some(Datetime::default());
+
+        self.state.urls.insert(node.url.clone(), id.to_string());
+        self.state.nodes.insert(id.to_string(), Node { node, written: false });
+        (id, true)
+    }
+
+    fn record_fetch(&mut self, id: &Thing, info: FetchInfo) -> anyhow::Result<()> {
+        // like a MERGE, what the fetch didn't find out leaves the node as it was.
+        fn set<T>(field: &mut Option<T>, value: Option<T>) {
+            if value.is_some() {
+                *field = value;
+            }
+        }
+
+        self.update(id, |node| {
+            set(&mut node.status, info.status);
+            set(&mut node.etag, info.etag);
+            set(&mut node.last_modified, info.last_modified);
+            set(&mut node.title, info.title);
+            set(&mut node.content_type, info.content_type);
+            set(&mut node.content_length, info.content_length);
+            set(&mut node.fetch_ms, info.fetch_ms);
+            node.truncated = info.truncated;
+            node.noindex = info.noindex;
+            set(&mut node.type_decided_by, info.type_decided_by.map(str::to_owned));
+            set(&mut node.encoding, info.encoding.map(str::to_owned));
+            set(&mut node.final_url, info.final_url);
+            set(&mut node.content_hash, info.content_hash);
+            set(&mut node.duplicate_of, info.duplicate_of);
+            set(&mut node.content_path, info.content_path);
+            set(&mut node.text, info.text);
+            node.last_checked_at = Some(Datetime::default());
+        })
+    }
+
+    /// The relation's id if this created it, after writing it out.
+    fn relate(&mut self, relate_table: &str, from: &Thing, to: &Thing, data: &LinkData) -> anyhow::Result<Option<Thing>> {
+        let key = (relate_table.to_owned(), from.to_string(), to.to_string());
+        if let Some(existing) = self.state.edges.get_mut(&key) {
+            *existing = data.clone();
+            return Ok(None);
+        }
+
+        let id = self.new_id(relate_table);
+        let name = |id: &String| {
+            self.state.nodes
+                .get(id)
+                .map(|node| node.node.url.clone())
+                .or_else(|| self.state.emails.get(id).map(|address| format!("mailto:{address}")))
+                .unwrap_or_else(|| id.clone())
+        };
+        let (source, target) = (name(&key.1), name(&key.2));
+
+        self.files.write_edge(&EdgeLine {
+            table: relate_table,
+            source: &source,
+            target: &target,
+            nofollow: data.nofollow,
+            text: data.text.as_deref(),
+            count: data.count,
+            external: data.external,
+        })?;
+        self.state.edges.insert(key, data.clone());
+
+        Ok(Some(id))
+    }
+}
+
+/// Writes the crawl to a nodes file and an edges file in a directory, with nothing kept but what the crawl needs
+/// to know which urls it's been to.
+///
+/// Edges are written as they're found. A node is written once the crawl is done with it (it's been fetched,
+/// failed, or been skipped for a reason that's recorded on it), or when the crawl ends for the rest, so its status
+/// is in the same line. Whatever's written is flushed every couple of seconds.
+///
+/// Nothing about a crawl can be read back out of the files, so this can't resume or refresh one.
+pub struct FileStore {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl FileStore {
+    /// Starts writing to `nodes.<format>` and `edges.<format>` in `dir`, creating it if it's not there.
+    /// Refuses to write over files that already have something in them unless `force` is set.
+    pub fn create(dir: &Path, format: FileFormat, force: bool) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {dir:?}"))?;
+
+        let open = |name: &str| -> anyhow::Result<BufWriter<File>> {
+            let path: PathBuf = dir.join(format!("{name}.{}", format.extension()));
+            if !force && fs::metadata(&path).is_ok_and(|metadata| metadata.len() > 0) {
+                anyhow::bail!("{path:?} already has a crawl in it, pass --force to overwrite it");
+            }
+
+            let file = File::create(&path).with_context(|| format!("Failed to create {path:?}"))?;
+            Ok(BufWriter::new(file))
+        };
+        let mut files = Files {
+            format,
+            nodes: open("nodes")?,
+            edges: open("edges")?,
+        };
+
+        if let FileFormat::Csv = format {
+            writeln!(files.nodes, "{NODE_COLUMNS}")?;
+            writeln!(files.edges, "{EDGE_COLUMNS}")?;
+        }
+
+        let inner = Arc::new(Mutex::new(Inner {
+            state: State::default(),
+            files,
+        }));
+        tokio::spawn(flush_periodically(Arc::downgrade(&inner)));
+
+        Ok(Self { inner })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap()
+    }
+}
+
+/// Flushes every [`FLUSH_EVERY`] until the store is dropped.
+async fn flush_periodically(inner: Weak<Mutex<Inner>>) {
+    let mut interval = tokio::time::interval(FLUSH_EVERY);
+
+    loop {
+        interval.tick().await;
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+
+        let flushed = inner.lock().unwrap().files.flush();
+        if let Err(err) = flushed {
+            warn!(error = format!("{err:#}"), "Failed to flush the crawl's files");
+        }
+    }
+}
+
+#[async_trait]
+impl GraphStore for FileStore {
+    async fn define_schema(&self, _table: &str, _relate_tables: &[&str]) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn claim_url(&self, table: &str, node: &SiteURLNode) -> anyhow::Result<(Thing, bool)> {
+        Ok(self.lock().claim(table, node))
+    }
+
+    async fn record_page<'a>(
+        &self,
+        table: &str,
+        page: Thing,
+        fetch: Option<FetchInfo>,
+        nodes: Vec<SiteURLNode>,
+        links: Vec<(&'a str, Vec<PageLink>)>,
+    ) -> anyhow::Result<(Vec<(Thing, bool)>, Vec<CreatedRelation<'a>>)> {
+        let mut inner = self.lock();
+
+        if let Some(fetch) = fetch {
+            inner.record_fetch(&page, fetch)?;
+        }
+
+        let mut claimed = Vec::new();
+        for node in &nodes {
+            let (id, created) = inner.claim(table, node);
+            if !created {
+                let existing = inner.node(&id)?;
+                existing.depth = existing.depth.min(node.depth);
+            }
+            claimed.push((id, created));
+        }
+
+        let mut created = Vec::new();
+        for (relate_table, links) in links {
+            for PageLink { url, link } in links {
+                let Some(out) = inner.state.urls.get(&url).and_then(|id| inner.state.nodes[id].node.id.clone()) else {
+                    continue;
+                };
+
+                if let Some(id) = inner.relate(relate_table, &page, &out, &link)? {
+                    let relation = Relation {
+                        a_in: page.clone(),
+                        out,
+                        nofollow: link.nofollow,
+                        text: link.text,
+                        count: link.count,
+                        external: link.external,
+                        run: link.run,
+                    };
+                    created.push(CreatedRelation { relate_table, id, relation });
+                }
+            }
+        }
+
+        Ok((claimed, created))
+    }
+
+    async fn record_edge(&self, relate_table: &str, from: Thing, to: Thing, data: &LinkData) -> anyhow::Result<Option<Thing>> {
+        self.lock().relate(relate_table, &from, &to, data)
+    }
+
+    async fn node_url(&self, id: Thing) -> anyhow::Result<Option<String>> {
+        Ok(self.lock().state.nodes.get(&id.to_string()).map(|node| node.node.url.clone()))
+    }
+
+    async fn lower_depth(&self, id: Thing, depth: u32) -> anyhow::Result<()> {
+        let mut inner = self.lock();
+        let node = inner.node(&id)?;
+        node.depth = node.depth.min(depth);
+
+        Ok(())
+    }
+
+    async fn record_fetch(&self, id: Thing, info: FetchInfo) -> anyhow::Result<()> {
+        self.lock().record_fetch(&id, info)
+    }
+
+    async fn start_fetch(&self, id: Thing, _refresh: Option<Duration>) -> anyhow::Result<bool> {
+        let mut inner = self.lock();
+        let node = inner.node(&id)?;
+        if node.fetched_at.is_some() {
+            return Ok(false);
+        }
+
+        node.fetched_at = Some(Datetime::default());
+        Ok(true)
+    }
+
+    async fn reset_fetch(&self, id: Thing) -> anyhow::Result<()> {
+        self.lock().node(&id)?.fetched_at = None;
+        Ok(())
+    }
+
+    async fn validators(&self, id: Thing) -> anyhow::Result<Option<Validators>> {
+        let mut inner = self.lock();
+        let node = inner.node(&id)?;
+
+        Ok(Some(Validators {
+            etag: node.etag.clone(),
+            last_modified: node.last_modified.clone(),
+        }))
+    }
+
+    async fn mark_checked(&self, id: Thing) -> anyhow::Result<()> {
+        self.lock().update(&id, |node| node.last_checked_at = Some(Datetime::default()))
+    }
+
+    async fn outgoing_links(&self, relate_table: &str, id: Thing) -> anyhow::Result<Vec<(String, LinkData)>> {
+        let inner = self.lock();
+        let from = id.to_string();
+
+        let links = inner.state.edges
+            .iter()
+            .filter(|((table, source, _), _)| table == relate_table && *source == from)
+            .filter_map(|((_, _, target), data)| Some((inner.state.nodes.get(target)?.node.url.clone(), data.clone())))
+            .collect();
+
+        Ok(links)
+    }
+
+    /// Only forgets the relations, since they've already been written.
+    async fn prune_links(&self, relate_table: &str, id: Thing, urls: Vec<String>) -> anyhow::Result<usize> {
+        let mut inner = self.lock();
+        let from = id.to_string();
+        let keep: HashSet<&String> = urls.iter().filter_map(|url| inner.state.urls.get(url)).collect();
+
+        let stale: Vec<_> = inner.state.edges
+            .keys()
+            .filter(|(table, source, target)| table == relate_table && *source == from && !keep.contains(target))
+            .cloned()
+            .collect();
+        for key in &stale {
+            inner.state.edges.remove(key);
+        }
+
+        Ok(stale.len())
+    }
+
+    async fn mark_external(&self, id: Thing) -> anyhow::Result<()> {
+        self.lock().update(&id, |node| node.external = true)
+    }
+
+    async fn mark_excluded(&self, id: Thing) -> anyhow::Result<()> {
+        self.lock().update(&id, |node| node.excluded = true)
+    }
+
+    async fn mark_private_blocked(&self, id: Thing) -> anyhow::Result<()> {
+        self.lock().update(&id, |node| node.private_blocked = true)
+    }
+
+    async fn mark_robots_blocked(&self, id: Thing) -> anyhow::Result<()> {
+        self.lock().update(&id, |node| node.robots_blocked = true)
+    }
+
+    async fn record_failure(&self, id: Thing, err: &anyhow::Error) -> anyhow::Result<()> {
+        self.lock().update(&id, |node| {
+            node.error = Some(format!("{err:#}").chars().take(1024).collect());
+            node.error_kind = Some(db::error_kind(err).to_owned());
+            node.failed_at = Some(Datetime::default());
+        })
+    }
+
+    async fn save_content(&self, _content_table: &str, _hash: &str, _content: &str) -> anyhow::Result<()> {
+        anyhow::bail!("Plain files can't hold page bodies inline, store them in a directory instead")
+    }
+
+    async fn save_email(&self, email_table: &str, address: &str) -> anyhow::Result<Thing> {
+        let id = Thing::from((email_table.to_owned(), Id::from(address)));
+        self.lock().state.emails.insert(id.to_string(), address.to_owned());
+
+        Ok(id)
+    }
+
+    async fn enqueue(&self, frontier_table: &str, mut items: Vec<FrontierItem>, done: Option<Thing>) -> anyhow::Result<Vec<FrontierItem>> {
+        let mut inner = self.lock();
+
+        for item in &mut items {
+            let id = inner.new_id(frontier_table);
+            inner.state.frontier.insert(id.to_string());
+            item.id = Some(id);
+        }
+        if let Some(done) = done {
+            inner.state.frontier.remove(&done.to_string());
+        }
+
+        Ok(items)
+    }
+
+    async fn take_ownership(&self, _entry: Thing) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn load_frontier(&self, _frontier_table: &str) -> anyhow::Result<Vec<FrontierItem>> {
+        Ok(Vec::new())
+    }
+
+    async fn frontier_len(&self, _frontier_table: &str) -> anyhow::Result<usize> {
+        Ok(self.lock().state.frontier.len())
+    }
+
+    /// Writes every node that's still waiting to be, then flushes both files.
+    async fn flush(&self) -> anyhow::Result<()> {
+        let mut inner = self.lock();
+
+        let mut unwritten: Vec<(u32, &str, Thing)> = inner.state.nodes
+            .values()
+            .filter(|node| !node.written)
+            .filter_map(|node| Some((node.node.depth, node.node.url.as_str(), node.node.id.clone()?)))
+            .collect();
+        unwritten.sort();
+        let unwritten: Vec<Thing> = unwritten.into_iter().map(|(_, _, id)| id).collect();
+
+        for id in unwritten {
+            inner.settle(&id)?;
+        }
+
+        inner.files.flush()
+    }
+}
@@ -0,0 +1,481 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use surrealdb::sql::{Id, Thing};
+
+use super::{CreatedRelation, GraphStore};
+use crate::{
+    db,
+    frontier::FrontierItem,
+    model::{FetchInfo, LinkData, PageLink, Relation, SiteURLNode, Validators},
+};
+
+/// Times are written as RFC 3339 text in UTC, which sorts the same as the times do.
+const NOW: &str = "strftime('%Y-%m-%dT%H:%M:%fZ', 'now')";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sites (
+    id INTEGER PRIMARY KEY,
+    url TEXT NOT NULL UNIQUE,
+    depth INTEGER,
+    status INTEGER,
+    title TEXT,
+    content_type TEXT,
+    content_length INTEGER,
+    fetch_ms INTEGER,
+    truncated INTEGER NOT NULL DEFAULT 0,
+    noindex INTEGER NOT NULL DEFAULT 0,
+    type_decided_by TEXT,
+    encoding TEXT,
+    final_url TEXT,
+    content_hash TEXT,
+    duplicate_of INTEGER REFERENCES sites (id),
+    content_path TEXT,
+    text TEXT,
+    etag TEXT,
+    last_modified TEXT,
+    lastmod TEXT,
+    error TEXT,
+    error_kind TEXT,
+    external INTEGER NOT NULL DEFAULT 0,
+    excluded INTEGER NOT NULL DEFAULT 0,
+    private_blocked INTEGER NOT NULL DEFAULT 0,
+    robots_blocked INTEGER NOT NULL DEFAULT 0,
+    discovered_at TEXT,
+    fetched_at TEXT,
+    last_checked_at TEXT,
+    failed_at TEXT
+);
+CREATE INDEX IF NOT EXISTS sites_status ON sites (status);
+CREATE INDEX IF NOT EXISTS sites_depth ON sites (depth);
+
+CREATE TABLE IF NOT EXISTS edges (
+    id INTEGER PRIMARY KEY,
+    relation TEXT NOT NULL,
+    source INTEGER NOT NULL REFERENCES sites (id),
+    target INTEGER NOT NULL REFERENCES sites (id),
+    nofollow INTEGER,
+    text TEXT,
+    count INTEGER,
+    external INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (relation, source, target)
+);
+CREATE INDEX IF NOT EXISTS edges_target ON edges (target);
+
+CREATE TABLE IF NOT EXISTS emails (
+    address TEXT PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS email_edges (
+    id INTEGER PRIMARY KEY,
+    relation TEXT NOT NULL,
+    source INTEGER NOT NULL REFERENCES sites (id),
+    address TEXT NOT NULL REFERENCES emails (address),
+    nofollow INTEGER,
+    text TEXT,
+    count INTEGER,
+    external INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (relation, source, address)
+);
+
+CREATE TABLE IF NOT EXISTS content (
+    hash TEXT PRIMARY KEY,
+    body TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS frontier (
+    id INTEGER PRIMARY KEY,
+    depth INTEGER NOT NULL,
+    owner INTEGER NOT NULL DEFAULT 0,
+    item TEXT NOT NULL
+);
+";
+
+/// Writes the crawl to a single SQLite file, which can be resumed or refreshed like a SurrealDB one.
+///
+/// Nodes go in `sites`, one per url, and relations between them go in `edges`, one per relate table (the
+/// `relation` column, like `containslink` or `redirectsto`), source and target. Both match what the SurrealDB
+/// schema keeps unique. Email addresses go in `emails`, related to the pages they're on in `email_edges`.
+/// Page bodies stored inline go in `content` by hash, and `frontier` holds the urls still to crawl.
+/// Times are RFC 3339 text in UTC.
+///
+/// The table names a crawl is configured with only show up in the ids it hands out, like `site:12`.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Opens the SQLite file at `path`, creating it (and its tables) if it's not there yet.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let conn = Connection::open(path).with_context(|| format!("Failed to open {path:?}"))?;
+
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL; PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)
+            .with_context(|| format!("Failed to create the crawl's tables in {path:?}"))?;
+
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+}
+
+/// The row id behind one of the ids this store handed out.
+fn row_id(id: &Thing) -> anyhow::Result<i64> {
+    match id.id {
+        Id::Number(id) => Ok(id),
+        _ => anyhow::bail!("{id} isn't a SQLite row"),
+    }
+}
+
+fn claim(conn: &Connection, table: &str, node: &SiteURLNode) -> anyhow::Result<(Thing, bool)> {
+    let created = conn
+        .prepare_cached(&format!(
+            "INSERT INTO sites (url, depth, lastmod, external, excluded, private_blocked, robots_blocked, discovered_at) \
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, {NOW}) ON CONFLICT (url) DO NOTHING"
+        ))?
+        .execute(params![node.url, node.depth, node.lastmod, node.external, node.excluded, node.private_blocked, node.robots_blocked])?
+        == 1;
+
+    let id = if created {
+        conn.last_insert_rowid()
+    } else {
+        conn.prepare_cached("SELECT id FROM sites WHERE url = ?1")?
+            .query_row([&node.url], |row| row.get(0))?
+    };
+
+    Ok((Thing::from((table.to_owned(), Id::from(id))), created))
+}
+
+fn lower_depth(conn: &Connection, id: i64, depth: u32) -> anyhow::Result<()> {
+    conn.prepare_cached("UPDATE sites SET depth = ?2 WHERE id = ?1 AND (depth IS NULL OR depth > ?2)")?
+        .execute(params![id, depth])?;
+
+    Ok(())
+}
+
+/// Only what `info` has is written, so fetching a page again doesn't blank out what's already known about it.
+fn record_fetch(conn: &Connection, id: i64, info: &FetchInfo) -> anyhow::Result<()> {
+    let duplicate_of = info.duplicate_of.as_ref().map(row_id).transpose()?;
+
+    conn.prepare_cached(&format!(
+        "UPDATE sites SET status = coalesce(?2, status), etag = coalesce(?3, etag), last_modified = coalesce(?4, last_modified), \
+            title = coalesce(?5, title), content_type = coalesce(?6, content_type), content_length = coalesce(?7, content_length), \
+            fetch_ms = coalesce(?8, fetch_ms), truncated = ?9, noindex = ?10, type_decided_by = coalesce(?11, type_decided_by), \
+            encoding = coalesce(?12, encoding), final_url = coalesce(?13, final_url), content_hash = coalesce(?14, content_hash), \
+            duplicate_of = coalesce(?15, duplicate_of), content_path = coalesce(?16, content_path), text = coalesce(?17, text), \
+            last_checked_at = {NOW} \
+            WHERE id = ?1"
+    ))?
+    .execute(params![
+        id,
+        info.status,
+        info.etag,
+        info.last_modified,
+        info.title,
+        info.content_type,
+        info.content_length,
+        info.fetch_ms,
+        info.truncated,
+        info.noindex,
+        info.type_decided_by,
+        info.encoding,
+        info.final_url,
+        info.content_hash,
+        duplicate_of,
+        info.content_path,
+        info.text,
+    ])?;
+
+    Ok(())
+}
+
+/// The relation's id if this created it.
+fn relate(conn: &Connection, relate_table: &str, from: &Thing, to: &Thing, data: &LinkData) -> anyhow::Result<Option<Thing>> {
+    let source = row_id(from)?;
+    // emails are the only things kept by something other than a row id.
+    let (table, target_column, target): (&str, &str, &dyn rusqlite::ToSql) = match &to.id {
+        Id::Number(id) => ("edges", "target", id),
+        Id::String(address) => ("email_edges", "address", address),
+        _ => anyhow::bail!("{to} isn't something this store made"),
+    };
+
+    let created = conn
+        .prepare_cached(&format!(
+            "INSERT INTO {table} (relation, source, {target_column}, nofollow, text, count, external) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7) \
+                ON CONFLICT (relation, source, {target_column}) DO NOTHING"
+        ))?
+        .execute(params![relate_table, source, target, data.nofollow, data.text, data.count, data.external])?
+        == 1;
+    if created {
+        return Ok(Some(Thing::from((relate_table.to_owned(), Id::from(conn.last_insert_rowid())))));
+    }
+
+    conn.prepare_cached(&format!(
+        "UPDATE {table} SET nofollow = ?4, text = ?5, count = ?6, external = ?7 WHERE relation = ?1 AND source = ?2 AND {target_column} = ?3"
+    ))?
+    .execute(params![relate_table, source, target, data.nofollow, data.text, data.count, data.external])?;
+
+    Ok(None)
+}
+
+fn set_flag(conn: &Connection, id: &Thing, flag: &str) -> anyhow::Result<()> {
+    conn.execute(&format!("UPDATE sites SET {flag} = 1 WHERE id = ?1"), [row_id(id)?])?;
+    Ok(())
+}
+
+#[async_trait]
+impl GraphStore for SqliteStore {
+    async fn define_schema(&self, _table: &str, _relate_tables: &[&str]) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn claim_url(&self, table: &str, node: &SiteURLNode) -> anyhow::Result<(Thing, bool)> {
+        claim(&self.lock(), table, node)
+    }
+
+    async fn record_page<'a>(
+        &self,
+        table: &str,
+        page: Thing,
+        fetch: Option<FetchInfo>,
+        nodes: Vec<SiteURLNode>,
+        links: Vec<(&'a str, Vec<PageLink>)>,
+    ) -> anyhow::Result<(Vec<(Thing, bool)>, Vec<CreatedRelation<'a>>)> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+
+        if let Some(fetch) = &fetch {
+            record_fetch(&tx, row_id(&page)?, fetch)?;
+        }
+
+        let mut claimed = Vec::new();
+        for node in &nodes {
+            let (id, created) = claim(&tx, table, node)?;
+            if !created {
+                lower_depth(&tx, row_id(&id)?, node.depth)?;
+            }
+            claimed.push((id, created));
+        }
+
+        let mut created = Vec::new();
+        for (relate_table, links) in links {
+            for PageLink { url, link } in links {
+                let out: Option<i64> = tx
+                    .prepare_cached("SELECT id FROM sites WHERE url = ?1")?
+                    .query_row([&url], |row| row.get(0))
+                    .optional()?;
+                let Some(out) = out else {
+                    continue;
+                };
+                let out = Thing::from((table.to_owned(), Id::from(out)));
+
+                if let Some(id) = relate(&tx, relate_table, &page, &out, &link)? {
+                    let relation = Relation {
+                        a_in: page.clone(),
+                        out,
+                        nofollow: link.nofollow,
+                        text: link.text,
+                        count: link.count,
+                        external: link.external,
+                        run: link.run,
+                    };
+                    created.push(CreatedRelation { relate_table, id, relation });
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok((claimed, created))
+    }
+
+    async fn record_edge(&self, relate_table: &str, from: Thing, to: Thing, data: &LinkData) -> anyhow::Result<Option<Thing>> {
+        relate(&self.lock(), relate_table, &from, &to, data)
+    }
+
+    async fn node_url(&self, id: Thing) -> anyhow::Result<Option<String>> {
+        let url = self.lock()
+            .prepare_cached("SELECT url FROM sites WHERE id = ?1")?
+            .query_row([row_id(&id)?], |row| row.get(0))
+            .optional()?;
+
+        Ok(url)
+    }
+
+    async fn lower_depth(&self, id: Thing, depth: u32) -> anyhow::Result<()> {
+        lower_depth(&self.lock(), row_id(&id)?, depth)
+    }
+
+    async fn record_fetch(&self, id: Thing, info: FetchInfo) -> anyhow::Result<()> {
+        record_fetch(&self.lock(), row_id(&id)?, &info)
+    }
+
+    async fn start_fetch(&self, id: Thing, refresh: Option<Duration>) -> anyhow::Result<bool> {
+        let older_than = format!("-{} seconds", refresh.unwrap_or_default().as_secs_f64());
+        let started = self.lock()
+            .prepare_cached(&format!(
+                "UPDATE sites SET fetched_at = {NOW} WHERE id = ?1 AND (fetched_at IS NULL OR \
+                    (?2 AND coalesce(last_checked_at, fetched_at) <= strftime('%Y-%m-%dT%H:%M:%fZ', 'now', ?3)))"
+            ))?
+            .execute(params![row_id(&id)?, refresh.is_some(), older_than])?;
+
+        Ok(started == 1)
+    }
+
+    async fn reset_fetch(&self, id: Thing) -> anyhow::Result<()> {
+        self.lock().execute("UPDATE sites SET fetched_at = NULL WHERE id = ?1", [row_id(&id)?])?;
+        Ok(())
+    }
+
+    async fn validators(&self, id: Thing) -> anyhow::Result<Option<Validators>> {
+        let validators = self.lock()
+            .prepare_cached("SELECT etag, last_modified FROM sites WHERE id = ?1")?
+            .query_row([row_id(&id)?], |row| {
+                Ok(Validators {
+                    etag: row.get(0)?,
+                    last_modified: row.get(1)?,
+                })
+            })
+            .optional()?;
+
+        Ok(validators)
+    }
+
+    async fn mark_checked(&self, id: Thing) -> anyhow::Result<()> {
+        self.lock().execute(&format!("UPDATE sites SET last_checked_at = {NOW} WHERE id = ?1"), [row_id(&id)?])?;
+        Ok(())
+    }
+
+    async fn outgoing_links(&self, relate_table: &str, id: Thing) -> anyhow::Result<Vec<(String, LinkData)>> {
+        let conn = self.lock();
+        let mut statement = conn.prepare_cached(
+            "SELECT sites.url, edges.nofollow, edges.text, edges.count, edges.external FROM edges \
+                JOIN sites ON sites.id = edges.target WHERE edges.relation = ?1 AND edges.source = ?2",
+        )?;
+
+        let links = statement
+            .query_map(params![relate_table, row_id(&id)?], |row| {
+                let link = LinkData {
+                    nofollow: row.get(1)?,
+                    text: row.get(2)?,
+                    count: row.get(3)?,
+                    external: row.get(4)?,
+                    run: None,
+                };
+                Ok((row.get(0)?, link))
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(links)
+    }
+
+    async fn prune_links(&self, relate_table: &str, id: Thing, urls: Vec<String>) -> anyhow::Result<usize> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        let keep: HashSet<String> = urls.into_iter().collect();
+
+        let stale: Vec<i64> = tx
+            .prepare_cached("SELECT edges.id, sites.url FROM edges JOIN sites ON sites.id = edges.target WHERE edges.relation = ?1 AND edges.source = ?2")?
+            .query_map(params![relate_table, row_id(&id)?], |row| Ok((row.get(0)?, row.get::<_, String>(1)?)))?
+            .filter(|edge| !edge.as_ref().is_ok_and(|(_, url)| keep.contains(url)))
+            .map(|edge| edge.map(|(id, _)| id))
+            .collect::<Result<_, _>>()?;
+        for edge in &stale {
+            tx.execute("DELETE FROM edges WHERE id = ?1", [edge])?;
+        }
+
+        tx.commit()?;
+        Ok(stale.len())
+    }
+
+    async fn mark_external(&self, id: Thing) -> anyhow::Result<()> {
+        set_flag(&self.lock(), &id, "external")
+    }
+
+    async fn mark_excluded(&self, id: Thing) -> anyhow::Result<()> {
+        set_flag(&self.lock(), &id, "excluded")
+    }
+
+    async fn mark_private_blocked(&self, id: Thing) -> anyhow::Result<()> {
+        set_flag(&self.lock(), &id, "private_blocked")
+    }
+
+    async fn mark_robots_blocked(&self, id: Thing) -> anyhow::Result<()> {
+        set_flag(&self.lock(), &id, "robots_blocked")
+    }
+
+    async fn record_failure(&self, id: Thing, err: &anyhow::Error) -> anyhow::Result<()> {
+        let error: String = format!("{err:#}").chars().take(1024).collect();
+
+        self.lock().execute(
+            &format!("UPDATE sites SET error = ?2, error_kind = ?3, failed_at = {NOW} WHERE id = ?1"),
+            params![row_id(&id)?, error, db::error_kind(err)],
+        )?;
+
+        Ok(())
+    }
+
+    async fn save_content(&self, _content_table: &str, hash: &str, content: &str) -> anyhow::Result<()> {
+        self.lock().execute("INSERT OR REPLACE INTO content (hash, body) VALUES (?1, ?2)", params![hash, content])?;
+        Ok(())
+    }
+
+    async fn save_email(&self, email_table: &str, address: &str) -> anyhow::Result<Thing> {
+        self.lock().execute("INSERT INTO emails (address) VALUES (?1) ON CONFLICT (address) DO NOTHING", [address])?;
+        Ok(Thing::from((email_table.to_owned(), Id::from(address))))
+    }
+
+    async fn enqueue(&self, frontier_table: &str, mut items: Vec<FrontierItem>, done: Option<Thing>) -> anyhow::Result<Vec<FrontierItem>> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+
+        for item in &mut items {
+            tx.prepare_cached("INSERT INTO frontier (depth, owner, item) VALUES (?1, ?2, ?3)")?
+                .execute(params![item.depth, item.owner, serde_json::to_string(item)?])?;
+            item.id = Some(Thing::from((frontier_table.to_owned(), Id::from(tx.last_insert_rowid()))));
+        }
+        if let Some(done) = done {
+            tx.execute("DELETE FROM frontier WHERE id = ?1", [row_id(&done)?])?;
+        }
+
+        tx.commit()?;
+        Ok(items)
+    }
+
+    async fn take_ownership(&self, entry: Thing) -> anyhow::Result<()> {
+        self.lock().execute("UPDATE frontier SET owner = 1 WHERE id = ?1", [row_id(&entry)?])?;
+        Ok(())
+    }
+
+    async fn load_frontier(&self, frontier_table: &str) -> anyhow::Result<Vec<FrontierItem>> {
+        let conn = self.lock();
+        let mut statement = conn.prepare_cached("SELECT id, owner, item FROM frontier ORDER BY depth")?;
+
+        let rows: Vec<(i64, bool, String)> = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+        rows.into_iter()
+            .map(|(id, owner, item)| {
+                let item: FrontierItem = serde_json::from_str(&item).context("Failed to read an item in the frontier")?;
+                Ok(FrontierItem {
+                    id: Some(Thing::from((frontier_table.to_owned(), Id::from(id)))),
+                    owner,
+                    ..item
+                })
+            })
+            .collect()
+    }
+
+    async fn frontier_len(&self, _frontier_table: &str) -> anyhow::Result<usize> {
+        let len = self.lock().query_row("SELECT count(*) FROM frontier", [], |row| row.get(0))?;
+        Ok(len)
+    }
+}
@@ -0,0 +1,118 @@
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+use indicatif::{HumanBytes, HumanDuration};
+use serde::Serialize;
+
+/// What a crawl did, put together from the counters kept while it ran.
+#[derive(Clone, Serialize)]
+pub struct Summary {
+    /// Pages that came back with a 2xx status.
+    pub pages_fetched: usize,
+    /// Pages that were found but not fetched, because of limits, filters, robots.txt and the like.
+    pub pages_unfetched: usize,
+    pub nodes_created: usize,
+    pub relations_created: usize,
+    /// Pages that were fetched but not parsed because of their content type.
+    pub skipped_content_type: usize,
+    /// Pages whose body was the same as one fetched before them.
+    pub duplicate_pages: usize,
+    pub errors: usize,
+    /// `errors` by category: dns, connect, timeout, tls, http, or body.
+    pub errors_by_kind: BTreeMap<&'static str, usize>,
+    /// How many different hosts nodes were created for.
+    pub domains: usize,
+    /// The hosts (or registrable domains) whose pages stopped being fetched at `--max-pages-per-domain`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub capped_domains: Vec<String>,
+    /// The hosts that were given up on after failing too many times in a row, with how many urls that skipped.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub benched_hosts: BTreeMap<String, usize>,
+    /// How many links had each scheme other than http and https, like `mailto` or `tel`. These are never fetched.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub other_schemes: BTreeMap<String, usize>,
+    pub bytes_downloaded: u64,
+    /// Every request sent, retries included.
+    pub requests: usize,
+    /// The average request rate over the whole run, to check `--rate` against.
+    pub requests_per_sec: f64,
+    pub duration_secs: f64,
+    /// Whether Ctrl-C cut the crawl short.
+    pub stopped_early: bool,
+    /// How many requests went through each proxy, when there were any.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub proxy_requests: BTreeMap<String, usize>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Pages fetched: {} ({} more found but not fetched)", self.pages_fetched, self.pages_unfetched)?;
+        writeln!(f, "Nodes created: {}", self.nodes_created)?;
+        writeln!(f, "Relations created: {}", self.relations_created)?;
+        writeln!(f, "Skipped by content type: {}", self.skipped_content_type)?;
+        writeln!(f, "Duplicate content: {}", self.duplicate_pages)?;
+
+        write!(f, "Errors: {}", self.errors)?;
+        if !self.errors_by_kind.is_empty() {
+            let kinds: Vec<String> = self.errors_by_kind
+                .iter()
+                .map(|(kind, count)| format!("{kind}: {count}"))
+                .collect();
+            write!(f, " ({})", kinds.join(", "))?;
+        }
+        writeln!(f)?;
+
+        writeln!(f, "Domains: {}", self.domains)?;
+        if !self.capped_domains.is_empty() {
+            writeln!(f, "Domains that hit --max-pages-per-domain: {}", self.capped_domains.join(", "))?;
+        }
+        if !self.benched_hosts.is_empty() {
+            let hosts: Vec<String> = self.benched_hosts
+                .iter()
+                .map(|(host, skipped)| format!("{host} ({skipped} skipped)"))
+                .collect();
+            writeln!(f, "Hosts benched after repeated errors: {}", hosts.join(", "))?;
+        }
+        if !self.other_schemes.is_empty() {
+            let schemes: Vec<String> = self.other_schemes
+                .iter()
+                .map(|(scheme, count)| format!("{scheme}: {count}"))
+                .collect();
+            writeln!(f, "Links with other schemes: {}", schemes.join(", "))?;
+        }
+        writeln!(f, "Downloaded: {}", HumanBytes(self.bytes_downloaded))?;
+
+        writeln!(f, "Requests: {} ({:.2}/s)", self.requests, self.requests_per_sec)?;
+
+        for (proxy, requests) in &self.proxy_requests {
+            writeln!(f, "Requests through {proxy}: {requests}")?;
+        }
+
+        write!(f, "Took: {}", HumanDuration(Duration::from_secs_f64(self.duration_secs)))
+    }
+}
+
+/// A snapshot of the crawl's counters, from [`crate::CrawlHandle::status`] and shown by `--progress`.
+pub struct Status {
+    pub pages_fetched: usize,
+    /// Queued or in flight.
+    pub pending: usize,
+    pub errors: usize,
+    pub bytes_downloaded: u64,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rate = self.pages_fetched as f64 / self.elapsed.as_secs_f64().max(0.001);
+
+        write!(
+            f,
+            "{} pages fetched ({rate:.1}/s), {} pending, {} errors, {} downloaded, {} elapsed",
+            self.pages_fetched,
+            self.pending,
+            self.errors,
+            HumanBytes(self.bytes_downloaded),
+            HumanDuration(self.elapsed),
+        )
+    }
+}
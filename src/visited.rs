@@ -0,0 +1,228 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use surrealdb::sql::Thing;
+use tokio::sync::watch;
+
+use crate::{model::SiteURLNode, store::GraphStore};
+
+enum Claim {
+    /// Some worker is writing this node right now; the id shows up here once it's done.
+    InFlight(watch::Receiver<Option<Thing>>),
+    Done(Done),
+}
+
+struct Done {
+    id: Thing,
+    fetch: Fetch,
+    /// Whoever claimed it decided not to fetch it (it was only linked as nofollow, or was past `--max-depth`),
+    /// so the next claim that would fetch it gets it instead.
+    deferred: bool,
+}
+
+impl Done {
+    fn new(id: Thing, deferred: bool) -> Self {
+        Self { id, fetch: Fetch::Pending, deferred }
+    }
+
+    /// Takes over a deferred claim for a caller that isn't deferring it.
+    fn lift(&mut self, deferred: bool) -> Claimed {
+        if self.deferred && !deferred {
+            self.deferred = false;
+            Claimed::Lifted
+        } else {
+            Claimed::Seen
+        }
+    }
+}
+
+/// Whether a claimed url's page was fetched, for counting the ones that weren't.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Fetch {
+    Pending,
+    Skipped,
+    Fetched,
+}
+
+/// How a [`Visited::claim`] went.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Claimed {
+    /// This call created the node.
+    Created,
+    /// The node was already in the db from a previous run, and this is the first time this run got to it.
+    Existing,
+    /// Something else already claimed it this run.
+    Seen,
+    /// Something else claimed it this run but deferred it, and this call is taking it over.
+    Lifted,
+}
+
+/// An in-memory record of every url this run has claimed, so repeat links don't need a query
+/// and a url being worked on by one worker is never fetched by another.
+///
+/// The store stays the source of truth: anything missing here (like nodes written by a previous run)
+/// still goes through [`GraphStore::claim_url`], which decides who gets to fetch it.
+pub struct Visited {
+    claims: Mutex<HashMap<String, Claim>>,
+    /// Whether [`Claimed::Existing`] nodes get fetched, and so can be deferred.
+    refetch_existing: bool,
+}
+
+impl Visited {
+    pub fn new(refetch_existing: bool) -> Self {
+        Self {
+            claims: Mutex::default(),
+            refetch_existing,
+        }
+    }
+
+    /// Same as [`GraphStore::claim_url`], but skips the store for urls seen before.
+    /// If another worker is still creating the node, this waits for its id instead.
+    ///
+    /// `deferred` is whether the caller won't fetch the node even if it gets it. A later claim that would is
+    /// handed it as [`Claimed::Lifted`].
+    pub async fn claim(&self, store: &dyn GraphStore, table: &str, node: &SiteURLNode, deferred: bool) -> anyhow::Result<(Thing, Claimed)> {
+        let tx = {
+            let mut claims = self.claims.lock().unwrap();
+
+            match claims.get_mut(&node.url) {
+                Some(Claim::Done(done)) => return Ok((done.id.clone(), done.lift(deferred))),
+                Some(Claim::InFlight(rx)) => Err(rx.clone()),
+                None => {
+                    let (tx, rx) = watch::channel(None);
+                    claims.insert(node.url.clone(), Claim::InFlight(rx));
+                    Ok(tx)
+                }
+            }
+        };
+
+        let tx = match tx {
+            Ok(tx) => tx,
+            Err(mut rx) => {
+                // either it's done now, or the winner failed to write it and it's up for grabs again.
+                let _ = rx.wait_for(Option::is_some).await;
+                return Box::pin(self.claim(store, table, node, deferred)).await;
+            }
+        };
+
+        match store.claim_url(table, node).await {
+            Ok((id, created)) => {
+                let deferred = deferred && (created || self.refetch_existing);
+                self.claims.lock().unwrap().insert(node.url.clone(), Claim::Done(Done::new(id.clone(), deferred)));
+                tx.send_replace(Some(id.clone()));
+                Ok((id, if created { Claimed::Created } else { Claimed::Existing }))
+            }
+            Err(err) => {
+                // dropping tx wakes up anyone waiting on us.
+                self.claims.lock().unwrap().remove(&node.url);
+                Err(err)
+            }
+        }
+    }
+
+    /// Reserves every url in `urls` nobody has claimed yet, so the caller can claim them all in one query.
+    /// Urls another worker is partway through claiming are waited on first.
+    pub async fn reserve(&self, urls: &[String]) -> Reservation<'_> {
+        let mut mine = HashMap::new();
+        let mut waiting: Vec<&String> = urls.iter().collect();
+
+        while !waiting.is_empty() {
+            let mut in_flight = Vec::new();
+            {
+                let mut claims = self.claims.lock().unwrap();
+
+                for url in waiting {
+                    if mine.contains_key(url) {
+                        continue;
+                    }
+
+                    match claims.get(url) {
+                        Some(Claim::Done(_)) => {}
+                        Some(Claim::InFlight(rx)) => in_flight.push((url, rx.clone())),
+                        None => {
+                            let (tx, rx) = watch::channel(None);
+                            claims.insert(url.clone(), Claim::InFlight(rx));
+                            mine.insert(url.clone(), tx);
+                        }
+                    }
+                }
+            }
+
+            waiting = Vec::new();
+            for (url, mut rx) in in_flight {
+                // the winner failed to write it, so it's up for grabs again.
+                if rx.wait_for(Option::is_some).await.is_err() {
+                    waiting.push(url);
+                }
+            }
+        }
+
+        Reservation { visited: self, mine }
+    }
+
+    /// Notes that the page at `url` was left unfetched, unless it was fetched after all.
+    pub fn skipped(&self, url: &str) {
+        if let Some(Claim::Done(done)) = self.claims.lock().unwrap().get_mut(url) {
+            if done.fetch == Fetch::Pending {
+                done.fetch = Fetch::Skipped;
+            }
+        }
+    }
+
+    /// Notes that the page at `url` was fetched, which takes precedence over it having been skipped.
+    pub fn fetched(&self, url: &str) {
+        if let Some(Claim::Done(done)) = self.claims.lock().unwrap().get_mut(url) {
+            done.fetch = Fetch::Fetched;
+        }
+    }
+
+    /// How many of the urls claimed this run were skipped and never fetched. Each url counts once, however many
+    /// times it came up.
+    pub fn unfetched(&self) -> usize {
+        self.claims
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|claim| matches!(claim, Claim::Done(Done { fetch: Fetch::Skipped, .. })))
+            .count()
+    }
+}
+
+/// Urls held by [`Visited::reserve`]. Any that aren't passed to [`Reservation::claimed`] are freed up again when it's dropped.
+pub struct Reservation<'a> {
+    visited: &'a Visited,
+    mine: HashMap<String, watch::Sender<Option<Thing>>>,
+}
+
+impl Reservation<'_> {
+    /// Records what the db said about `url`, and how that counts for us. `deferred` is the same as for [`Visited::claim`].
+    pub fn claimed(&mut self, url: &str, id: Thing, created: bool, deferred: bool) -> Claimed {
+        let mut claims = self.visited.claims.lock().unwrap();
+        let Some(tx) = self.mine.remove(url) else {
+            return match claims.get_mut(url) {
+                Some(Claim::Done(done)) => done.lift(deferred),
+                _ => Claimed::Seen,
+            };
+        };
+
+        let deferred = deferred && (created || self.visited.refetch_existing);
+        claims.insert(url.to_owned(), Claim::Done(Done::new(id.clone(), deferred)));
+        drop(claims);
+        tx.send_replace(Some(id));
+
+        if created {
+            Claimed::Created
+        } else {
+            Claimed::Existing
+        }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut claims = self.visited.claims.lock().unwrap();
+        // the senders get dropped after this, which wakes up anyone waiting on them.
+        for url in self.mine.keys() {
+            claims.remove(url);
+        }
+    }
+}
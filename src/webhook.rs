@@ -0,0 +1,252 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use reqwest::{header::CONTENT_TYPE, Client};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use surrealdb::sql::Thing;
+use tokio::{sync::mpsc, task::JoinHandle, time::Instant};
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::{
+    crawler::CrawlEvent,
+    model::{LinkData, SiteURLNode},
+    summary::Summary,
+};
+
+/// A batch is sent once it has this many events, or [`FLUSH_EVERY`] after its first one, whichever comes first.
+const BATCH_SIZE: usize = 100;
+const FLUSH_EVERY: Duration = Duration::from_secs(5);
+/// How many events can wait to be sent before new ones are dropped, so a slow webhook can't hold up the crawl.
+const QUEUE_SIZE: usize = 10_000;
+const RETRIES: u32 = 2;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum EventKind {
+    /// A url was seen for the first time.
+    NodeCreated,
+    /// A page was found linking to (or redirecting to, or declaring as canonical) another.
+    RelationCreated,
+    /// A page couldn't be fetched or read.
+    FetchFailed,
+    /// The crawl is over, with its summary.
+    CrawlFinished,
+}
+
+/// A [`CrawlEvent`] as it's posted to `--webhook`.
+#[derive(Serialize)]
+struct Event {
+    event: EventKind,
+    #[serde(flatten)]
+    fields: Map<String, Value>,
+}
+
+impl Event {
+    fn from_crawl(event: &CrawlEvent) -> Self {
+        match event {
+            CrawlEvent::NodeCreated { id, node } => Self::node_created(id, node),
+            CrawlEvent::RelationCreated { relate_table, id, from, to, url, link } => Self::relation_created(relate_table, id, from, to, url, link),
+            CrawlEvent::FetchFailed { id, url, error, error_kind } => Self::fetch_failed(id, url, error, error_kind),
+            CrawlEvent::CrawlFinished(summary) => Self::crawl_finished(summary),
+        }
+    }
+
+    fn node_created(id: &Thing, node: &SiteURLNode) -> Self {
+        let mut event = Self::new(EventKind::NodeCreated, id, node);
+        if let Some(run) = &node.first_seen_run {
+            event.fields.insert("first_seen_run".to_owned(), run.to_string().into());
+        }
+        event
+    }
+
+    fn relation_created(table: &str, id: &Thing, from: &Thing, to: &Thing, url: &str, link: &LinkData) -> Self {
+        let mut event = Self::new(EventKind::RelationCreated, id, link);
+        event.fields.insert("table".to_owned(), table.into());
+        event.fields.insert("from".to_owned(), from.to_string().into());
+        event.fields.insert("to".to_owned(), to.to_string().into());
+        event.fields.insert("url".to_owned(), url.into());
+        if let Some(run) = &link.run {
+            event.fields.insert("run".to_owned(), run.to_string().into());
+        }
+        event
+    }
+
+    fn fetch_failed(id: &Thing, url: &str, error: &str, error_kind: &str) -> Self {
+        let error = json!({
+            "url": url,
+            "error": error,
+            "error_kind": error_kind,
+        });
+        Self::new(EventKind::FetchFailed, id, error)
+    }
+
+    fn crawl_finished(summary: &Summary) -> Self {
+        Self {
+            event: EventKind::CrawlFinished,
+            fields: to_map(summary),
+        }
+    }
+
+    /// `fields` with `id` written over whatever id it had.
+    fn new(event: EventKind, id: &Thing, fields: impl Serialize) -> Self {
+        let mut fields = to_map(fields);
+        fields.insert("id".to_owned(), id.to_string().into());
+
+        Self { event, fields }
+    }
+}
+
+impl CrawlEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            CrawlEvent::NodeCreated { .. } => EventKind::NodeCreated,
+            CrawlEvent::RelationCreated { .. } => EventKind::RelationCreated,
+            CrawlEvent::FetchFailed { .. } => EventKind::FetchFailed,
+            CrawlEvent::CrawlFinished(_) => EventKind::CrawlFinished,
+        }
+    }
+}
+
+fn to_map(value: impl Serialize) -> Map<String, Value> {
+    match serde_json::to_value(value) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+enum Message {
+    Event(Event),
+    Finish,
+}
+
+/// Posts events to `--webhook` in batches from a task of its own, so the crawl never waits on it.
+pub struct Webhook {
+    events: Vec<EventKind>,
+    sender: mpsc::Sender<Message>,
+    /// Set once an event has been dropped, so the warning is only logged once.
+    dropped: AtomicBool,
+}
+
+impl Webhook {
+    /// Starts sending the `events` kinds to `url`. The task ends once [`Webhook::finish`] is called and
+    /// whatever was left has been sent.
+    pub fn start(url: Url, events: Vec<EventKind>, user_agent: &str) -> anyhow::Result<(Self, JoinHandle<()>)> {
+        let client = Client::builder().user_agent(user_agent).timeout(TIMEOUT).build()?;
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        let task = tokio::spawn(deliver(client, url, receiver));
+
+        Ok((
+            Self {
+                events,
+                sender,
+                dropped: AtomicBool::new(false),
+            },
+            task,
+        ))
+    }
+
+    pub fn wants(&self, kind: EventKind) -> bool {
+        self.events.contains(&kind)
+    }
+
+    /// Queues `event` to be sent, or drops it if it wasn't asked for or too many are already waiting.
+    pub fn send(&self, event: &CrawlEvent) {
+        if !self.wants(event.kind()) {
+            return;
+        }
+
+        if self.sender.try_send(Message::Event(Event::from_crawl(event))).is_err() && !self.dropped.swap(true, Ordering::Relaxed) {
+            warn!("The webhook isn't keeping up, dropping events until it catches up");
+        }
+    }
+
+    /// Sends whatever is still queued, then stops.
+    pub async fn finish(&self) {
+        let _ = self.sender.send(Message::Finish).await;
+    }
+}
+
+async fn deliver(client: Client, url: Url, mut receiver: mpsc::Receiver<Message>) {
+    let mut batch = Vec::new();
+    let mut deadline = None;
+
+    loop {
+        let message = match deadline {
+            Some(at) => match tokio::time::timeout_at(at, receiver.recv()).await {
+                Ok(message) => message,
+                Err(_) => {
+                    post(&client, &url, take_batch(&mut batch, &mut deadline)).await;
+                    continue;
+                }
+            },
+            None => receiver.recv().await,
+        };
+
+        match message {
+            Some(Message::Event(event)) => {
+                batch.push(event);
+                deadline.get_or_insert_with(|| Instant::now() + FLUSH_EVERY);
+
+                if batch.len() >= BATCH_SIZE {
+                    post(&client, &url, take_batch(&mut batch, &mut deadline)).await;
+                }
+            }
+            Some(Message::Finish) | None => {
+                if !batch.is_empty() {
+                    post(&client, &url, batch).await;
+                }
+                return;
+            }
+        }
+    }
+}
+
+fn take_batch(batch: &mut Vec<Event>, deadline: &mut Option<Instant>) -> Vec<Event> {
+    *deadline = None;
+    std::mem::take(batch)
+}
+
+/// Posts `batch` as a JSON array, retrying a couple of times before giving up on it.
+async fn post(client: &Client, url: &Url, batch: Vec<Event>) {
+    let body = match serde_json::to_vec(&batch) {
+        Ok(body) => body,
+        Err(err) => {
+            warn!(events = batch.len(), error = %err, "Failed to serialize webhook events, dropping them");
+            return;
+        }
+    };
+    let mut attempt = 0;
+
+    loop {
+        let result = client
+            .post(url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(|res| res.error_for_status());
+
+        match result {
+            Ok(_) => {
+                debug!(events = batch.len(), "Sent webhook events");
+                return;
+            }
+            Err(err) if attempt < RETRIES => {
+                attempt += 1;
+                debug!(error = %err, attempt, "Failed to send webhook events, retrying");
+                tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+            }
+            Err(err) => {
+                warn!(events = batch.len(), error = %err, "Failed to send webhook events, dropping them");
+                return;
+            }
+        }
+    }
+}
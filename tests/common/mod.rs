@@ -0,0 +1,237 @@
+//! A small http server for crawling against in tests, plus the setup most of them share.
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use site_connection_finder::{db, store::SurrealStore, Crawler, CrawlerBuilder};
+use surrealdb::{engine::any::Any, Surreal};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Keyed by lowercased name.
+    pub headers: HashMap<String, String>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    /// The Host header without its port.
+    pub fn host(&self) -> &str {
+        let host = self.header("host").unwrap_or("");
+        host.rsplit_once(':').map_or(host, |(host, _)| host)
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// How long to wait before answering, to keep the request in flight.
+    pub delay: Duration,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Self {
+        Self { status, headers: Vec::new(), body: Vec::new(), delay: Duration::ZERO }
+    }
+
+    pub fn html(body: impl Into<String>) -> Self {
+        Self::new(200).header("Content-Type", "text/html; charset=utf-8").body(body.into())
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(404).header("Content-Type", "text/plain").body("not found")
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// An html page linking to every one of `hrefs`.
+pub fn page(hrefs: &[&str]) -> String {
+    let links: String = hrefs.iter().map(|href| format!("<a href=\"{href}\">{href}</a>\n")).collect();
+    format!("<html><head><title>page</title></head><body>\n{links}</body></html>")
+}
+
+#[derive(Default)]
+struct Stats {
+    requests: Vec<Request>,
+    in_flight: usize,
+    max_in_flight: usize,
+    host_in_flight: HashMap<String, usize>,
+    host_max_in_flight: HashMap<String, usize>,
+}
+
+type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+pub struct MockServer {
+    addr: SocketAddr,
+    stats: Arc<Mutex<Stats>>,
+}
+
+impl MockServer {
+    /// Listens on a free port of 127.0.0.1, answering every request with whatever `handler` returns.
+    pub async fn start(handler: impl Fn(&Request) -> Response + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let stats = Arc::new(Mutex::new(Stats::default()));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let accepted = stats.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, handler.clone(), accepted.clone()));
+            }
+        });
+
+        Self { addr, stats }
+    }
+
+    /// `path` on this server, reached through 127.0.0.1.
+    pub fn url(&self, path: &str) -> String {
+        self.url_on("127.0.0.1", path)
+    }
+
+    /// `path` on this server, reached through `host`, which should resolve to 127.0.0.1.
+    pub fn url_on(&self, host: &str, path: &str) -> String {
+        format!("http://{host}:{}{path}", self.addr.port())
+    }
+
+    /// Every request received so far, in the order they came in.
+    pub fn requests(&self) -> Vec<Request> {
+        self.stats.lock().unwrap().requests.clone()
+    }
+
+    /// How many requests for `path` were received with `method`.
+    pub fn hits(&self, method: &str, path: &str) -> usize {
+        self.requests().iter().filter(|req| req.method == method && req.path == path).count()
+    }
+
+    /// The most requests that were being answered at once.
+    pub fn max_in_flight(&self) -> usize {
+        self.stats.lock().unwrap().max_in_flight
+    }
+
+    /// The most requests to `host` that were being answered at once.
+    pub fn max_in_flight_on(&self, host: &str) -> usize {
+        self.stats.lock().unwrap().host_max_in_flight.get(host).copied().unwrap_or(0)
+    }
+}
+
+async fn serve(mut stream: TcpStream, handler: Arc<Handler>, stats: Arc<Mutex<Stats>>) {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    while !buf.windows(4).any(|window| window == b"\r\n\r\n") {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+
+    let head = String::from_utf8_lossy(&buf).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or("").split(' ');
+    let request = Request {
+        method: request_line.next().unwrap_or("").to_owned(),
+        path: request_line.next().unwrap_or("").to_owned(),
+        headers: lines
+            .take_while(|line| !line.is_empty())
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_owned()))
+            .collect(),
+    };
+
+    let host = request.host().to_owned();
+    {
+        let mut stats = stats.lock().unwrap();
+        stats.requests.push(request.clone());
+        stats.in_flight += 1;
+        stats.max_in_flight = stats.max_in_flight.max(stats.in_flight);
+        let in_flight = stats.host_in_flight.entry(host.clone()).or_default();
+        *in_flight += 1;
+        let in_flight = *in_flight;
+        let max = stats.host_max_in_flight.entry(host.clone()).or_default();
+        *max = (*max).max(in_flight);
+    }
+
+    let response = handler(&request);
+    tokio::time::sleep(response.delay).await;
+
+    let mut out = format!("HTTP/1.1 {} Mock\r\nConnection: close\r\n", response.status);
+    for (name, value) in &response.headers {
+        out.push_str(&format!("{name}: {value}\r\n"));
+    }
+    if !response.headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-length")) {
+        out.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+    }
+    out.push_str("\r\n");
+
+    let mut bytes = out.into_bytes();
+    if request.method != "HEAD" {
+        bytes.extend_from_slice(&response.body);
+    }
+    let _ = stream.write_all(&bytes).await;
+    let _ = stream.shutdown().await;
+
+    let mut stats = stats.lock().unwrap();
+    stats.in_flight -= 1;
+    *stats.host_in_flight.get_mut(&host).unwrap() -= 1;
+}
+
+/// An empty in-memory database to crawl into.
+pub async fn memory_db() -> Surreal<Any> {
+    let db = db::connect_memory().await.unwrap();
+    db.use_ns("findconn").use_db("test").await.unwrap();
+    db
+}
+
+/// A crawler that's allowed to reach the mock server, and doesn't go looking for its robots.txt.
+pub fn crawler(seeds: &[String]) -> CrawlerBuilder {
+    Crawler::builder()
+        .seeds(seeds.iter().cloned())
+        .allow_private(true)
+        .robots(false, Duration::ZERO)
+}
+
+/// Builds `builder` over `db` and crawls to the end.
+pub async fn crawl(builder: CrawlerBuilder, db: &Surreal<Any>) -> site_connection_finder::summary::Summary {
+    builder.build(Arc::new(SurrealStore::new(db.clone()))).unwrap().crawl().await.unwrap()
+}
+
+/// How many records are in `table`.
+pub async fn count(db: &Surreal<Any>, table: &str) -> usize {
+    let mut res = db.query(format!("SELECT count() FROM {table} GROUP ALL")).await.unwrap();
+    let count: Option<usize> = res.take("count").unwrap();
+    count.unwrap_or(0)
+}
+
+/// Every url in the `site` table, sorted.
+pub async fn urls(db: &Surreal<Any>) -> Vec<String> {
+    let mut res = db.query("SELECT VALUE url FROM site ORDER BY url").await.unwrap();
+    res.take(0).unwrap()
+}
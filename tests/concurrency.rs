@@ -0,0 +1,23 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{crawl, crawler, memory_db, page, MockServer, Response};
+
+#[tokio::test]
+async fn concurrency_caps_requests_in_flight() {
+    let paths: Vec<String> = (0..40).map(|i| format!("/{i}")).collect();
+    let hrefs: Vec<&str> = paths.iter().map(String::as_str).collect();
+    let index = page(&hrefs);
+    let server = MockServer::start(move |req| match req.path.as_str() {
+        "/" => Response::html(index.clone()),
+        _ => Response::html(page(&[])).delay(Duration::from_millis(50)),
+    })
+    .await;
+
+    let db = memory_db().await;
+    let summary = crawl(crawler(&[server.url("/")]).concurrency(4).per_host_concurrency(64), &db).await;
+
+    assert_eq!(summary.pages_fetched, 41);
+    assert!(server.max_in_flight() <= 4, "{} requests were in flight at once", server.max_in_flight());
+}
@@ -0,0 +1,25 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{crawl, crawler, memory_db, page, MockServer, Response};
+use reqwest_cookie_store::CookieStoreMutex;
+
+#[tokio::test]
+async fn cookie_set_on_one_page_is_sent_to_the_next() {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/a" => Response::html(page(&["/b"])).header("Set-Cookie", "session=abc123; Path=/"),
+        _ => Response::html(page(&[])),
+    })
+    .await;
+
+    let db = memory_db().await;
+    let jar = Arc::new(CookieStoreMutex::default());
+    crawl(crawler(&[server.url("/a")]).cookies(jar), &db).await;
+
+    let requests = server.requests();
+    let a = requests.iter().find(|req| req.path == "/a").unwrap();
+    let b = requests.iter().find(|req| req.path == "/b").unwrap();
+    assert_eq!(a.header("cookie"), None);
+    assert_eq!(b.header("cookie"), Some("session=abc123"));
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use common::{crawl, crawler, memory_db, page, urls, MockServer, Response};
+use serde::Deserialize;
+use surrealdb::{engine::any::Any, Surreal};
+
+const FIXTURE: &[u8] = include_bytes!("fixtures/windows-1251.html");
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Link {
+    url: String,
+    text: Option<String>,
+}
+
+async fn crawl_fixture(content_type: &'static str) -> (MockServer, Surreal<Any>) {
+    let server = MockServer::start(move |req| match req.path.as_str() {
+        "/docs/" => Response::new(200).header("Content-Type", content_type).body(FIXTURE),
+        _ => Response::html(page(&[])),
+    })
+    .await;
+
+    let db = memory_db().await;
+    crawl(crawler(&[server.url("/docs/")]).max_depth(1), &db).await;
+    (server, db)
+}
+
+async fn check(server: &MockServer, db: &Surreal<Any>) {
+    let article = server.url("/docs/%D1%81%D1%82%D0%B0%D1%82%D1%8C%D1%8F.html");
+    let contacts = server.url("/%D0%BA%D0%BE%D0%BD%D1%82%D0%B0%D0%BA%D1%82%D1%8B/");
+    let search = server.url("/%D0%BF%D0%BE%D0%B8%D1%81%D0%BA?q=1");
+
+    let mut expected = vec![server.url("/docs/"), article.clone(), contacts.clone(), search.clone()];
+    expected.sort();
+    assert_eq!(urls(db).await, expected);
+
+    let mut res = db
+        .query("SELECT out.url AS url, text FROM containslink ORDER BY url")
+        .query("SELECT VALUE [title, encoding] FROM site WHERE url = $url")
+        .bind(("url", server.url("/docs/")))
+        .await
+        .unwrap();
+    let mut links: Vec<Link> = res.take(0).unwrap();
+    links.sort_by(|a, b| a.url.cmp(&b.url));
+    let mut expected = vec![
+        Link { url: article, text: Some("Статья о кодировках".to_owned()) },
+        Link { url: contacts, text: Some("Контакты".to_owned()) },
+        Link { url: search, text: Some("Поиск".to_owned()) },
+    ];
+    expected.sort_by(|a, b| a.url.cmp(&b.url));
+    assert_eq!(links, expected);
+
+    let page: Option<(String, String)> = res.take(1).unwrap();
+    assert_eq!(page, Some(("Документация".to_owned(), "windows-1251".to_owned())));
+}
+
+#[tokio::test]
+async fn windows_1251_from_content_type() {
+    let (server, db) = crawl_fixture("text/html; charset=windows-1251").await;
+    check(&server, &db).await;
+}
+
+#[tokio::test]
+async fn windows_1251_from_meta_charset() {
+    let (server, db) = crawl_fixture("text/html").await;
+    check(&server, &db).await;
+}
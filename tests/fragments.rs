@@ -0,0 +1,15 @@
+mod common;
+
+use common::{count, crawl, crawler, memory_db, page, MockServer, Response};
+
+#[tokio::test]
+async fn links_to_own_anchors_create_no_nodes() {
+    let server = MockServer::start(|_| Response::html(page(&["#one", "#two", "#three", "/#four", "/#five"]))).await;
+
+    let db = memory_db().await;
+    let summary = crawl(crawler(&[server.url("/")]), &db).await;
+
+    assert_eq!(summary.pages_fetched, 1);
+    assert_eq!(count(&db, "site").await, 1);
+    assert_eq!(count(&db, "containslink").await, 0);
+}
@@ -0,0 +1,23 @@
+mod common;
+
+use common::{crawl, crawler, memory_db, page, urls, MockServer, Response};
+
+#[tokio::test]
+async fn head_precheck_skips_a_200mb_page() {
+    let server = MockServer::start(|req| match (req.method.as_str(), req.path.as_str()) {
+        (_, "/") => Response::html(page(&["/big.html"])),
+        ("HEAD", "/big.html") => Response::new(200)
+            .header("Content-Type", "text/html")
+            .header("Content-Length", &(200 * 1024 * 1024).to_string()),
+        _ => Response::html(page(&[])),
+    })
+    .await;
+
+    let db = memory_db().await;
+    let summary = crawl(crawler(&[server.url("/")]).head_precheck(10 * 1024 * 1024), &db).await;
+
+    assert_eq!(server.hits("HEAD", "/big.html"), 1);
+    assert_eq!(server.hits("GET", "/big.html"), 0);
+    assert_eq!(summary.pages_unfetched, 1);
+    assert!(urls(&db).await.contains(&server.url("/big.html")));
+}
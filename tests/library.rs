@@ -0,0 +1,67 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{count, memory_db, page, MockServer, Response};
+use site_connection_finder::{store::SurrealStore, CrawlEvent, Crawler};
+use tokio::sync::mpsc;
+
+#[tokio::test]
+async fn crawls_through_the_library() {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/" => Response::html(page(&["/about", "/missing"])),
+        "/about" => Response::html(page(&["/"])),
+        _ => Response::not_found(),
+    })
+    .await;
+
+    let db = memory_db().await;
+    let (tx, mut rx) = mpsc::channel(100);
+    let crawler = Crawler::builder()
+        .seeds([server.url("/")])
+        .allow_private(true)
+        .robots(false, std::time::Duration::ZERO)
+        .events(tx)
+        .build(Arc::new(SurrealStore::new(db.clone())))
+        .unwrap();
+    let handle = crawler.handle();
+
+    let events = tokio::spawn(async move {
+        let mut events = Vec::new();
+        // the handle keeps the sender alive, so the channel never closes on its own.
+        while let Some(event) = rx.recv().await {
+            let finished = matches!(event, CrawlEvent::CrawlFinished(_));
+            events.push(event);
+            if finished {
+                break;
+            }
+        }
+        events
+    });
+    let summary = crawler.crawl().await.unwrap();
+    let events = events.await.unwrap();
+
+    // the 404 for /missing isn't a page fetched.
+    assert_eq!(summary.pages_fetched, 2);
+    assert_eq!(summary.nodes_created, 3);
+    assert_eq!(summary.relations_created, 3);
+    assert_eq!(handle.status().pages_fetched, 2);
+
+    let created: Vec<String> = events
+        .iter()
+        .filter_map(|event| match event {
+            CrawlEvent::NodeCreated { node, .. } => Some(node.url.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(created, [server.url("/"), server.url("/about"), server.url("/missing")]);
+    let relations = events.iter().filter(|event| matches!(event, CrawlEvent::RelationCreated { .. })).count();
+    assert_eq!(relations, 3);
+    assert!(matches!(events.last(), Some(CrawlEvent::CrawlFinished(_))));
+
+    assert_eq!(count(&db, "site").await, 3);
+    assert_eq!(count(&db, "containslink").await, 3);
+    let mut res = db.query("SELECT VALUE status FROM site WHERE url = $url").bind(("url", server.url("/missing"))).await.unwrap();
+    let status: Option<u16> = res.take(0).unwrap();
+    assert_eq!(status, Some(404));
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{crawl, crawler, memory_db, page, MockServer, Response};
+
+#[tokio::test]
+async fn page_past_max_depth_is_fetched_once_reached_within_it() {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/" => Response::html(page(&["/a", "/b"])),
+        "/a" => Response::html(page(&["/a/2"])),
+        "/a/2" => Response::html(page(&["/c"])),
+        // slow enough that /c is found past the max depth by way of /a first.
+        "/b" => Response::html(page(&["/c"])).delay(Duration::from_millis(500)),
+        _ => Response::html(page(&[])),
+    })
+    .await;
+
+    let db = memory_db().await;
+    let summary = crawl(crawler(&[server.url("/")]).max_depth(2).per_host_concurrency(4), &db).await;
+
+    assert_eq!(server.hits("GET", "/c"), 1);
+    assert_eq!(summary.pages_fetched, 5);
+
+    let mut res = db.query("SELECT VALUE depth FROM site WHERE url = $url").bind(("url", server.url("/c"))).await.unwrap();
+    let depth: Option<u32> = res.take(0).unwrap();
+    assert_eq!(depth, Some(2));
+}
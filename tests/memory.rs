@@ -0,0 +1,54 @@
+mod common;
+
+use std::{fs, process::Stdio};
+
+use common::{page, MockServer, Response};
+use tokio::process::Command;
+
+fn findconn() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_findconn"));
+    command.stdout(Stdio::null()).stderr(Stdio::piped());
+    command
+}
+
+#[tokio::test]
+async fn memory_crawl_leaves_nothing_on_disk() {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/" => Response::html(page(&["/a", "/b"])),
+        _ => Response::html(page(&["/"])),
+    })
+    .await;
+
+    let dir = std::env::temp_dir().join(format!("findconn-memory-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let summary_path = std::env::temp_dir().join(format!("findconn-memory-{}.json", std::process::id()));
+
+    let output = findconn()
+        .current_dir(&dir)
+        .args(["--url", &server.url("/"), "--memory", "--allow-private", "--ignore-robots", "--summary-json"])
+        .arg(&summary_path)
+        .output()
+        .await
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let summary: serde_json::Value = serde_json::from_slice(&fs::read(&summary_path).unwrap()).unwrap();
+    assert_eq!(summary["pages_fetched"], 3);
+    assert_eq!(summary["nodes_created"], 3);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+    fs::remove_file(summary_path).unwrap();
+    fs::remove_dir(dir).unwrap();
+}
+
+#[tokio::test]
+async fn memory_conflicts_with_output() {
+    let output = findconn()
+        .args(["--url", "http://127.0.0.1/", "--memory", "--output", "crawl.db"])
+        .output()
+        .await
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--output"));
+}
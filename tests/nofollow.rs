@@ -0,0 +1,20 @@
+mod common;
+
+use common::{crawl, crawler, memory_db, page, MockServer, Response};
+
+#[tokio::test]
+async fn nofollow_target_is_fetched_once_linked_normally() {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/" => Response::html(r#"<a rel="nofollow" href="/a">a</a> <a href="/b">b</a>"#),
+        "/b" => Response::html(page(&["/a"])),
+        _ => Response::html(page(&[])),
+    })
+    .await;
+
+    let db = memory_db().await;
+    let summary = crawl(crawler(&[server.url("/")]).skip_nofollow(true, true), &db).await;
+
+    assert_eq!(server.hits("GET", "/a"), 1);
+    assert_eq!(summary.pages_fetched, 3);
+    assert_eq!(summary.pages_unfetched, 0);
+}
@@ -0,0 +1,23 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{crawl, crawler, memory_db, page, MockServer, Response};
+
+#[tokio::test]
+async fn per_host_concurrency_serializes_each_host() {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/" => Response::html(page(&["/1", "/2", "/3", "/4"])).delay(Duration::from_millis(100)),
+        _ => Response::html(page(&[])).delay(Duration::from_millis(100)),
+    })
+    .await;
+
+    let db = memory_db().await;
+    let seeds = [server.url_on("127.0.0.1", "/"), server.url_on("localhost", "/")];
+    let summary = crawl(crawler(&seeds).concurrency(8).per_host_concurrency(1), &db).await;
+
+    assert_eq!(summary.pages_fetched, 10);
+    assert_eq!(server.max_in_flight_on("127.0.0.1"), 1);
+    assert_eq!(server.max_in_flight_on("localhost"), 1);
+    assert_eq!(server.max_in_flight(), 2);
+}
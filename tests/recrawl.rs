@@ -0,0 +1,27 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{count, crawl, crawler, memory_db, page, MockServer, Response};
+
+#[tokio::test]
+async fn crawling_twice_leaves_one_edge() {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/" => Response::html(page(&["/other", "/other"])),
+        _ => Response::html(page(&[])),
+    })
+    .await;
+
+    let db = memory_db().await;
+    crawl(crawler(&[server.url("/")]), &db).await;
+    let summary = crawl(crawler(&[server.url("/")]).refresh(Duration::ZERO), &db).await;
+
+    assert_eq!(server.hits("GET", "/"), 2);
+    assert_eq!(summary.relations_created, 0);
+    assert_eq!(count(&db, "site").await, 2);
+    assert_eq!(count(&db, "containslink").await, 1);
+
+    let mut res = db.query("SELECT VALUE count FROM containslink").await.unwrap();
+    let counts: Vec<u32> = res.take(0).unwrap();
+    assert_eq!(counts, [2]);
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use common::{crawl, crawler, memory_db, page, MockServer, Response};
+
+#[tokio::test]
+async fn redirect_to_a_skipped_extension_is_not_parsed() {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/" => Response::new(302).header("Location", "/report.pdf"),
+        // served as a page, so only the extension says not to fetch it.
+        "/report.pdf" => Response::html(page(&["/hidden"])),
+        _ => Response::html(page(&[])),
+    })
+    .await;
+
+    let db = memory_db().await;
+    crawl(crawler(&[server.url("/")]), &db).await;
+
+    assert_eq!(server.hits("GET", "/hidden"), 0);
+
+    let mut res = db.query("SELECT VALUE out.url FROM redirectsto").await.unwrap();
+    let targets: Vec<String> = res.take(0).unwrap();
+    assert_eq!(targets, [server.url("/report.pdf")]);
+
+    let mut res = db.query("SELECT VALUE status FROM site WHERE url = $url").bind(("url", server.url("/report.pdf"))).await.unwrap();
+    let status: Vec<Option<u16>> = res.take(0).unwrap();
+    assert_eq!(status, [None]);
+}
+
+#[tokio::test]
+async fn refreshing_into_a_redirect_keeps_what_was_known() {
+    let moved = AtomicBool::new(false);
+    let server = MockServer::start(move |req| match req.path.as_str() {
+        "/" if moved.swap(true, Ordering::SeqCst) => Response::new(301).header("Location", "/new"),
+        "/" => Response::html("<html><head><title>home</title></head><body></body></html>"),
+        _ => Response::html(page(&[])),
+    })
+    .await;
+
+    let db = memory_db().await;
+    crawl(crawler(&[server.url("/")]), &db).await;
+    crawl(crawler(&[server.url("/")]).refresh(Duration::ZERO), &db).await;
+
+    let mut res = db
+        .query("SELECT status, title, final_url FROM ONLY site WHERE url = $url LIMIT 1")
+        .bind(("url", server.url("/")))
+        .await
+        .unwrap();
+    assert_eq!(res.take::<Option<u16>>((0, "status")).unwrap(), Some(200));
+    assert_eq!(res.take::<Option<String>>((0, "title")).unwrap().as_deref(), Some("home"));
+    assert_eq!(res.take::<Option<String>>((0, "final_url")).unwrap(), Some(server.url("/new")));
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use common::{crawl, crawler, memory_db, page, urls, MockServer, Response};
+
+#[tokio::test]
+async fn page_failing_twice_is_retried_and_its_links_found() {
+    let failures = AtomicUsize::new(0);
+    let server = MockServer::start(move |req| match req.path.as_str() {
+        "/" if failures.fetch_add(1, Ordering::SeqCst) < 2 => Response::new(503),
+        "/" => Response::html(page(&["/found"])),
+        _ => Response::html(page(&[])),
+    })
+    .await;
+
+    let db = memory_db().await;
+    let summary = crawl(crawler(&[server.url("/")]).retries(2), &db).await;
+
+    assert_eq!(server.hits("GET", "/"), 3);
+    assert_eq!(server.hits("GET", "/found"), 1);
+    assert_eq!(summary.errors, 0);
+    assert!(urls(&db).await.contains(&server.url("/found")));
+}
+
+#[tokio::test]
+async fn page_always_rate_limited_is_given_up_on() {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/" => Response::new(429).header("Retry-After", "0"),
+        _ => Response::not_found(),
+    })
+    .await;
+
+    let db = memory_db().await;
+    let summary = crawl(crawler(&[server.url("/")]).retries(2), &db).await;
+
+    assert_eq!(server.hits("GET", "/"), 3);
+    assert_eq!(summary.errors, 1);
+
+    let mut res = db.query("SELECT VALUE error_kind FROM site").await.unwrap();
+    let kinds: Vec<Option<String>> = res.take(0).unwrap();
+    assert_eq!(kinds, [Some("http".to_owned())]);
+}
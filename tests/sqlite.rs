@@ -0,0 +1,60 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use std::{fs, sync::Arc, time::Duration};
+
+use common::{crawler, page, MockServer, Response};
+use rusqlite::Connection;
+use site_connection_finder::{store::SqliteStore, CrawlerBuilder};
+
+async fn crawl(builder: CrawlerBuilder, store: &Arc<SqliteStore>) {
+    builder.build(store.clone()).unwrap().crawl().await.unwrap();
+}
+
+fn count(conn: &Connection, table: &str) -> usize {
+    conn.query_row(&format!("SELECT count(*) FROM {table}"), [], |row| row.get(0)).unwrap()
+}
+
+#[tokio::test]
+async fn crawls_into_sites_and_edges() {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/" => Response::html(page(&["/a", "/b", "/b"])),
+        "/a" => Response::html(page(&["/", "/b"])),
+        "/b" => Response::html(page(&[])),
+        _ => Response::not_found(),
+    })
+    .await;
+
+    let path = std::env::temp_dir().join(format!("findconn-sqlite-{}.db", std::process::id()));
+    let store = Arc::new(SqliteStore::open(&path).unwrap());
+    crawl(crawler(&[server.url("/")]), &store).await;
+    // crawling it again can't add a second node for a url or a second edge between the same pages.
+    crawl(crawler(&[server.url("/")]).refresh(Duration::ZERO), &store).await;
+
+    let conn = Connection::open(&path).unwrap();
+    assert_eq!(count(&conn, "sites"), 3);
+    assert_eq!(count(&conn, "edges"), 4);
+    assert_eq!(count(&conn, "frontier"), 0);
+
+    let (status, title, depth): (u16, String, u32) = conn
+        .query_row("SELECT status, title, depth FROM sites WHERE url = ?1", [server.url("/b")], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap();
+    assert_eq!((status, title.as_str(), depth), (200, "page", 1));
+
+    let count: u32 = conn
+        .query_row(
+            "SELECT edges.count FROM edges JOIN sites AS source ON source.id = edges.source JOIN sites AS target ON target.id = edges.target \
+                WHERE source.url = ?1 AND target.url = ?2",
+            [server.url("/"), server.url("/b")],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(count, 2);
+
+    drop(conn);
+    drop(store);
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+}
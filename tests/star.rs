@@ -0,0 +1,27 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{count, crawl, crawler, memory_db, page, MockServer, Response};
+
+#[tokio::test]
+async fn star_target_is_fetched_once() {
+    let spokes: Vec<String> = (0..50).map(|i| format!("/spoke/{i}")).collect();
+    let hrefs: Vec<&str> = spokes.iter().map(String::as_str).collect();
+    let hub = page(&hrefs);
+    let server = MockServer::start(move |req| match req.path.as_str() {
+        "/" => Response::html(hub.clone()),
+        "/target" => Response::html(page(&[])).delay(Duration::from_millis(20)),
+        _ => Response::html(page(&["/target"])).delay(Duration::from_millis(10)),
+    })
+    .await;
+
+    let db = memory_db().await;
+    let summary = crawl(crawler(&[server.url("/")]).concurrency(16).per_host_concurrency(16), &db).await;
+
+    assert_eq!(server.hits("GET", "/target"), 1);
+    assert_eq!(summary.pages_fetched, 52);
+    assert_eq!(count(&db, "site").await, 52);
+    // 50 from the hub to its spokes, and 50 from the spokes to the target.
+    assert_eq!(count(&db, "containslink").await, 100);
+}
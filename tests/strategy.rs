@@ -0,0 +1,30 @@
+mod common;
+
+use common::{crawl, crawler, memory_db, page, MockServer, Response};
+use site_connection_finder::frontier::Strategy;
+
+async fn visit_order(strategy: Strategy) -> Vec<String> {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/" => Response::html(page(&["/a", "/b"])),
+        "/a" => Response::html(page(&["/a/1", "/a/2"])),
+        "/b" => Response::html(page(&["/b/1"])),
+        "/a/1" => Response::html(page(&["/a/1/x"])),
+        _ => Response::html(page(&[])),
+    })
+    .await;
+
+    let db = memory_db().await;
+    crawl(crawler(&[server.url("/")]).strategy(strategy).concurrency(1), &db).await;
+
+    server.requests().into_iter().map(|req| req.path).collect()
+}
+
+#[tokio::test]
+async fn bfs_visits_a_depth_at_a_time() {
+    assert_eq!(visit_order(Strategy::Bfs).await, ["/", "/a", "/b", "/a/1", "/a/2", "/b/1", "/a/1/x"]);
+}
+
+#[tokio::test]
+async fn dfs_follows_each_branch_down() {
+    assert_eq!(visit_order(Strategy::Dfs).await, ["/", "/a", "/a/1", "/a/1/x", "/a/2", "/b", "/b/1"]);
+}